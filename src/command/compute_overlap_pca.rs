@@ -107,11 +107,8 @@ fn find_shape(x_values: Vec<f64>, y_values: Vec<f64>) -> Result<(DMatrix<f64>, D
     assert_ne!(n, 0);
 
     // Combine and center the data
-    let data = Array2::from_shape_vec(
-        (n, 2),
-        x_values.into_iter().chain(y_values.into_iter()).collect(),
-    )
-    .unwrap();
+    let data =
+        Array2::from_shape_vec((n, 2), x_values.into_iter().chain(y_values).collect()).unwrap();
     let mean = data.mean_axis(ndarray::Axis(0)).unwrap();
     let data_centered = data - &mean;
     let data_matrix = DMatrix::from_row_slice(n, 2, data_centered.as_slice().unwrap());
@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::config::{Aggregation, AggregationMode, Filter, PlotCommon, Transform};
+use crate::{util, util::Result};
+
+/// The minimum number of common time windows needed to report a correlation.
+const MIN_COMMON_WINDOWS: usize = 3;
+
+/// A time series to correlate, read from a column of a table in the database.
+struct Series {
+    name: String,
+    table: String,
+    column: String,
+    mode: AggregationMode,
+    transform: Option<Transform>,
+    has_view_id: bool,
+}
+
+impl Series {
+    /// Parse a series specification on the form `table.column`, optionally followed by
+    /// `:mode` to override the default aggregation mode for this series and `:rate` to convert
+    /// a cumulative counter to its rate, e.g. `table.column:mean:rate`.
+    fn parse(conn: &Connection, spec: &str, default_mode: AggregationMode) -> Result<Self> {
+        let mut parts = spec.split(':');
+        let name = parts.next().unwrap_or_default();
+        let mut mode = default_mode;
+        let mut transform = None;
+        for part in parts {
+            if part == "rate" {
+                transform = Some(Transform::Rate);
+            } else {
+                mode = serde_yaml::from_str(part)
+                    .map_err(|_| format!("Invalid aggregation mode {} in {}", part, spec))?;
+            }
+        }
+        let (table, column) = name.split_once('.').ok_or_else(|| {
+            format!(
+                "Invalid series {}, expected table.column, optionally followed by :mode and :rate",
+                spec
+            )
+        })?;
+        if !util::table_has_column(conn, table, column)? {
+            return Err(format!("Table {} has no column {}", table, column).into());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            table: table.to_string(),
+            column: column.to_string(),
+            mode,
+            transform,
+            has_view_id: util::table_has_column(conn, table, "view_id")?,
+        })
+    }
+}
+
+/// The aggregated values of a series, keyed by time window index.
+type Windows = BTreeMap<i64, f64>;
+
+/// The correlation between two series, by their indices, within a view or over all views.
+struct CorrelationRow {
+    view_id: Option<i32>,
+    a: usize,
+    b: usize,
+    correlation: Correlation,
+}
+
+/// The correlation between two aggregated series.
+struct Correlation {
+    windows: usize,
+    pearson: f64,
+    spearman: f64,
+    best_lag: i64,
+    best_lag_pearson: f64,
+}
+
+pub(crate) fn correlate<P: AsRef<Path>>(
+    database_path: P,
+    series_specs: &[String],
+    filter: &Filter,
+    aggregation: &Aggregation,
+    max_lag: i64,
+    per_view: bool,
+) -> Result<()> {
+    if series_specs.len() < 2 {
+        return Err("At least two series are needed to compute correlations".into());
+    }
+    let conn = Connection::open(database_path)?;
+    util::register_calendar_functions(&conn)?;
+    let series = series_specs
+        .iter()
+        .map(|spec| Series::parse(&conn, spec, aggregation.mode))
+        .collect::<Result<Vec<Series>>>()?;
+
+    let rows = get_correlations(&conn, &series, filter, aggregation, max_lag, per_view)?;
+
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(std::io::stdout());
+
+    csv_writer.write_record([
+        "view ID",
+        "series 1",
+        "series 2",
+        "windows",
+        "pearson",
+        "spearman",
+        "best lag",
+        "best lag pearson",
+    ])?;
+
+    for row in rows {
+        csv_writer.write_record(&[
+            row.view_id.map(|id| id.to_string()).unwrap_or_default(),
+            series[row.a].name.clone(),
+            series[row.b].name.clone(),
+            row.correlation.windows.to_string(),
+            row.correlation.pearson.to_string(),
+            row.correlation.spearman.to_string(),
+            row.correlation.best_lag.to_string(),
+            row.correlation.best_lag_pearson.to_string(),
+        ])?;
+    }
+
+    Ok(())
+}
+
+/// Compute the correlation between each pair of series over all views, followed by the
+/// correlations for each view if `per_view` is set.
+fn get_correlations(
+    conn: &Connection,
+    series: &[Series],
+    filter: &Filter,
+    aggregation: &Aggregation,
+    max_lag: i64,
+    per_view: bool,
+) -> Result<Vec<CorrelationRow>> {
+    let mut overall: Vec<Windows> = Vec::with_capacity(series.len());
+    for s in series.iter() {
+        let (timestamp, value) = util::get_samples(
+            conn,
+            filter.start.as_deref(),
+            filter.end.as_deref(),
+            Some(filter),
+            &s.column,
+            &s.table,
+        )?;
+        overall.push(aggregate(timestamp, value, s, aggregation, filter)?);
+    }
+    let mut rows = Vec::new();
+    let overall_windows: Vec<Option<&Windows>> = overall.iter().map(Some).collect();
+    add_correlations(&mut rows, None, &overall_windows, max_lag, |_, _| true);
+
+    if per_view {
+        // Series from tables with a view ID are split by view, while the other series are
+        // compared as a whole. Pairs where neither series depends on the view are the same as
+        // the overall result and are not repeated. A view that is missing from one of the
+        // series split by view has no windows for it, so pairs with that series are left out.
+        let mut by_view: BTreeMap<i32, Vec<Option<Windows>>> = BTreeMap::new();
+        for (i, s) in series.iter().enumerate() {
+            if !s.has_view_id {
+                continue;
+            }
+            let samples_by_view = util::get_timed_samples_by_view(
+                conn,
+                filter.start.as_deref(),
+                filter.end.as_deref(),
+                Some(filter),
+                &s.column,
+                &s.table,
+            )?;
+            for (view_id, samples) in samples_by_view {
+                let windows = by_view
+                    .entry(view_id)
+                    .or_insert_with(|| vec![None; series.len()]);
+                windows[i] = Some(aggregate(
+                    samples.timestamp,
+                    samples.value,
+                    s,
+                    aggregation,
+                    filter,
+                )?);
+            }
+        }
+        for (view_id, view_windows) in by_view.iter() {
+            let windows: Vec<Option<&Windows>> = series
+                .iter()
+                .zip(view_windows.iter())
+                .zip(overall.iter())
+                .map(|((s, view), overall)| {
+                    if s.has_view_id {
+                        view.as_ref()
+                    } else {
+                        Some(overall)
+                    }
+                })
+                .collect();
+            add_correlations(&mut rows, Some(*view_id), &windows, max_lag, |a, b| {
+                series[a].has_view_id || series[b].has_view_id
+            });
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Aggregate the samples of a series into time windows, in the same way as the plots do.
+fn aggregate(
+    timestamp: Vec<i64>,
+    value: Vec<u64>,
+    series: &Series,
+    aggregation: &Aggregation,
+    filter: &Filter,
+) -> Result<Windows> {
+    let common_cfg = PlotCommon {
+        aggregation: Some(Aggregation {
+            mode: series.mode,
+            ..*aggregation
+        }),
+        transform: series.transform,
+        ..PlotCommon::empty()
+    };
+    util::check_filter_support(
+        Some(filter),
+        common_cfg.aggregation.as_ref(),
+        common_cfg.transform,
+    )?;
+    let binning_function =
+        util::get_grouping_function_for_time_window(&aggregation.size, aggregation.timezone);
+    Ok(util::aggregate_and_segment(&common_cfg, timestamp, value)?
+        .into_iter()
+        .flat_map(|(x, y)| x.into_iter().zip(y))
+        .map(|(x, y)| (binning_function(x), y))
+        .collect())
+}
+
+/// Add the correlation between each pair of series to the rows.
+///
+/// # Arguments
+/// * `rows` - The rows to add the correlations to.
+/// * `view_id` - The view of the correlations, or `None` for the overall result.
+/// * `windows` - The aggregated values of each series, or `None` for a series without values,
+///   which is left out.
+/// * `max_lag` - The maximum lag, in time windows, for the lagged cross-correlation.
+/// * `include_pair` - Decides whether the pair of series with the given indices is included.
+fn add_correlations<F: Fn(usize, usize) -> bool>(
+    rows: &mut Vec<CorrelationRow>,
+    view_id: Option<i32>,
+    windows: &[Option<&Windows>],
+    max_lag: i64,
+    include_pair: F,
+) {
+    for a in 0..windows.len() {
+        for b in a + 1..windows.len() {
+            if !include_pair(a, b) {
+                continue;
+            }
+            let (Some(windows_a), Some(windows_b)) = (windows[a], windows[b]) else {
+                continue;
+            };
+            if let Some(correlation) = get_correlation(windows_a, windows_b, max_lag) {
+                rows.push(CorrelationRow {
+                    view_id,
+                    a,
+                    b,
+                    correlation,
+                });
+            }
+        }
+    }
+}
+
+/// Correlate two aggregated series over the time windows where both have a value. The lagged
+/// cross-correlation compares window `i` of the first series with window `i + lag` of the second
+/// series, so a positive best lag means that changes in the second series follow changes in the
+/// first.
+///
+/// # Returns
+/// The correlation, or `None` if the series have too few windows in common.
+fn get_correlation(a: &Windows, b: &Windows, max_lag: i64) -> Option<Correlation> {
+    let (x, y) = get_common_windows(a, b, 0);
+    if x.len() < MIN_COMMON_WINDOWS {
+        return None;
+    }
+    let pearson = util::pearson_correlation(&x, &y);
+    let spearman = util::spearman_correlation(&x, &y);
+
+    let mut best_lag = 0;
+    let mut best_lag_pearson = pearson;
+    for lag in -max_lag..=max_lag {
+        let (x, y) = get_common_windows(a, b, lag);
+        if x.len() < MIN_COMMON_WINDOWS {
+            continue;
+        }
+        let lag_pearson = util::pearson_correlation(&x, &y);
+        if lag_pearson.abs() > best_lag_pearson.abs() || best_lag_pearson.is_nan() {
+            best_lag = lag;
+            best_lag_pearson = lag_pearson;
+        }
+    }
+
+    Some(Correlation {
+        windows: x.len(),
+        pearson,
+        spearman,
+        best_lag,
+        best_lag_pearson,
+    })
+}
+
+/// Pair up the values of window `i` in `a` with window `i + lag` in `b`, for all windows where
+/// both have a value.
+fn get_common_windows(a: &Windows, b: &Windows, lag: i64) -> (Vec<f64>, Vec<f64>) {
+    a.iter()
+        .filter_map(|(window, x)| b.get(&(window + lag)).map(|y| (*x, *y)))
+        .unzip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_correlations_per_view() {
+        // Two tables split by view, where only view 2 is in both, and one table without views.
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "create table a (timestamp integer, view_id integer, value integer);
+            create table b (timestamp integer, view_id integer, value integer);
+            create table c (timestamp integer, value integer);",
+        )
+        .unwrap();
+        for hour in 0..10i64 {
+            let timestamp = hour * 3_600_000_000_000;
+            let value = (hour * hour) % 7;
+            for (table, view_id) in [("a", 1), ("a", 2), ("b", 2), ("b", 3)] {
+                conn.execute(
+                    &format!("insert into {} values (?1, ?2, ?3)", table),
+                    (timestamp, view_id, value + view_id),
+                )
+                .unwrap();
+            }
+            conn.execute("insert into c values (?1, ?2)", (timestamp, value))
+                .unwrap();
+        }
+        let series: Vec<Series> = ["a.value", "b.value", "c.value"]
+            .iter()
+            .map(|spec| Series::parse(&conn, spec, AggregationMode::Mean).unwrap())
+            .collect();
+        let aggregation = Aggregation {
+            mode: AggregationMode::Mean,
+            size: "1h".parse().unwrap(),
+            min_count: None,
+            timezone: None,
+        };
+        let rows =
+            get_correlations(&conn, &series, &Filter::default(), &aggregation, 0, true).unwrap();
+        let pairs: Vec<(Option<i32>, usize, usize)> =
+            rows.iter().map(|row| (row.view_id, row.a, row.b)).collect();
+        assert_eq!(
+            pairs,
+            vec![
+                (None, 0, 1),
+                (None, 0, 2),
+                (None, 1, 2),
+                (Some(1), 0, 2),
+                (Some(2), 0, 1),
+                (Some(2), 0, 2),
+                (Some(2), 1, 2),
+                (Some(3), 1, 2),
+            ]
+        );
+        for row in rows.iter() {
+            assert_eq!(row.correlation.windows, 10);
+            assert!((row.correlation.pearson - 1.0).abs() < 1e-9);
+        }
+    }
+}
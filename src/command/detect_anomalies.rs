@@ -0,0 +1,320 @@
+use std::path::Path;
+
+use chrono::{Datelike, Timelike};
+use rusqlite::Connection;
+
+use crate::config::{Aggregation, Filter, PlotCommon, Timezone, Transform};
+use crate::{util, util::Result};
+
+/// Scale factor to make the median absolute deviation comparable to a standard deviation, for
+/// normally distributed data.
+const MAD_SCALE: f64 = 1.4826;
+
+/// The minimum number of bins in an hour of the week needed to establish a baseline for it.
+const MIN_BASELINE_BINS: usize = 3;
+
+/// An aggregated time window whose value deviates from the baseline.
+struct Anomaly {
+    start: i64,
+    end: i64,
+    value: f64,
+    baseline_median: f64,
+    baseline_mad: f64,
+    score: f64,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn detect_anomalies<P: AsRef<Path>>(
+    database_path: P,
+    table: &str,
+    column: &str,
+    filter: &Filter,
+    aggregation: &Aggregation,
+    transform: Option<Transform>,
+    threshold: f64,
+    top: usize,
+) -> Result<()> {
+    let conn = Connection::open(database_path)?;
+    util::register_calendar_functions(&conn)?;
+
+    let (timestamp, value) = util::get_samples(
+        &conn,
+        filter.start.as_deref(),
+        filter.end.as_deref(),
+        Some(filter),
+        column,
+        table,
+    )?;
+
+    let binning_function =
+        util::get_grouping_function_for_time_window(&aggregation.size, aggregation.timezone);
+    let unbinning_function =
+        util::get_ungrouping_function_for_time_period(&aggregation.size, aggregation.timezone);
+    let common_cfg = PlotCommon {
+        aggregation: Some(*aggregation),
+        transform,
+        ..PlotCommon::empty()
+    };
+    util::check_filter_support(Some(filter), Some(aggregation), transform)?;
+    let (bin_start, bin_statistic): (Vec<i64>, Vec<f64>) =
+        util::aggregate_and_segment(&common_cfg, timestamp, value)?
+            .into_iter()
+            .flat_map(|(x, y)| x.into_iter().zip(y))
+            .unzip();
+    let bin_end: Vec<i64> = bin_start
+        .iter()
+        .map(|t| unbinning_function(binning_function(*t) + 1))
+        .collect();
+
+    let timezone = aggregation
+        .timezone
+        .unwrap_or(Timezone::Named(chrono_tz::UTC));
+    let anomalies = find_anomalies(&bin_start, &bin_end, &bin_statistic, timezone, threshold);
+    eprintln!(
+        "Found {} anomalous windows out of {}",
+        anomalies.len(),
+        bin_statistic.len()
+    );
+
+    let has_view_id = util::table_has_column(&conn, table, "view_id")?;
+
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(std::io::stdout());
+
+    csv_writer.write_record([
+        "start",
+        "end",
+        "value",
+        "baseline median",
+        "baseline MAD",
+        "score",
+        "top views",
+    ])?;
+
+    for anomaly in anomalies.iter() {
+        let top_views = if has_view_id && top > 0 {
+            get_top_views(&conn, table, column, filter, anomaly, top)?
+        } else {
+            String::new()
+        };
+        csv_writer.write_record(&[
+            util::nanoseconds_epoch_to_string(anomaly.start),
+            util::nanoseconds_epoch_to_string(anomaly.end),
+            anomaly.value.to_string(),
+            anomaly.baseline_median.to_string(),
+            anomaly.baseline_mad.to_string(),
+            anomaly.score.to_string(),
+            top_views,
+        ])?;
+    }
+
+    Ok(())
+}
+
+/// Score aggregated time windows against a robust baseline for each hour of the week, since load
+/// (and hence most metrics) follows a weekly cycle.
+///
+/// # Arguments
+/// * `bin_start` - The start of each time window.
+/// * `bin_end` - The end of each time window.
+/// * `bin_statistic` - The aggregated value of each time window.
+/// * `timezone` - The time zone of the hours of the week.
+/// * `threshold` - The absolute score above which a window is anomalous.
+///
+/// # Returns
+/// The anomalous windows. The score of a window is infinite if it deviates from a baseline whose
+/// MAD is zero.
+fn find_anomalies(
+    bin_start: &[i64],
+    bin_end: &[i64],
+    bin_statistic: &[f64],
+    timezone: Timezone,
+    threshold: f64,
+) -> Vec<Anomaly> {
+    let hour_of_week: Vec<usize> = bin_start
+        .iter()
+        .map(|t| get_hour_of_week(*t, timezone))
+        .collect();
+    let mut baseline_values: Vec<Vec<f64>> = vec![Vec::new(); 7 * 24];
+    for (hour, value) in hour_of_week.iter().zip(bin_statistic.iter()) {
+        baseline_values[*hour].push(*value);
+    }
+    let baselines: Vec<Option<(f64, f64)>> = baseline_values
+        .iter_mut()
+        .map(|values| {
+            if values.len() < MIN_BASELINE_BINS {
+                return None;
+            }
+            values.sort_by(|a, b| a.total_cmp(b));
+            Some((
+                util::get_median(values),
+                util::get_median_absolute_deviation(values),
+            ))
+        })
+        .collect();
+
+    let mut anomalies: Vec<Anomaly> = Vec::new();
+    for i in 0..bin_statistic.len() {
+        let (baseline_median, baseline_mad) = match baselines[hour_of_week[i]] {
+            Some(baseline) => baseline,
+            None => continue,
+        };
+        let deviation = bin_statistic[i] - baseline_median;
+        // Most windows of a flat baseline, such as a count that is zero every night, equal the
+        // median, so any deviation from it is anomalous.
+        let score = if baseline_mad > 0.0 {
+            deviation / (MAD_SCALE * baseline_mad)
+        } else if deviation == 0.0 {
+            0.0
+        } else {
+            f64::INFINITY.copysign(deviation)
+        };
+        if score.abs() > threshold {
+            anomalies.push(Anomaly {
+                start: bin_start[i],
+                end: bin_end[i],
+                value: bin_statistic[i],
+                baseline_median,
+                baseline_mad,
+                score,
+            });
+        }
+    }
+    anomalies
+}
+
+/// Get the hour of the week (0 to 167, starting at midnight on Monday) of a timestamp in a time
+/// zone.
+fn get_hour_of_week(timestamp: i64, timezone: Timezone) -> usize {
+    let time = util::nanoseconds_epoch_to_local_datetime(timestamp, timezone);
+    time.weekday().num_days_from_monday() as usize * 24 + time.hour() as usize
+}
+
+/// Find the views that contributed the most to the total of the column within the time window
+/// of an anomaly.
+///
+/// # Returns
+/// A string listing the views and their share of the total, e.g. `123 (45.2%); 456 (12.0%)`.
+fn get_top_views(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    filter: &Filter,
+    anomaly: &Anomaly,
+    top: usize,
+) -> Result<String> {
+    let mut criteria = util::get_common_criteria(conn, None, None, Some(filter), None)?;
+    criteria.push(format!("timestamp >= {}", anomaly.start));
+    criteria.push(format!("timestamp < {}", anomaly.end));
+    criteria.push("view_id is not null".to_string());
+    let sql = format!(
+        "select view_id, sum({}) as total from {} where {} group by view_id order by total desc",
+        column,
+        table,
+        criteria.join(" and ")
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+    let mut totals: Vec<(i32, f64)> = Vec::new();
+    while let Some(row) = rows.next()? {
+        totals.push((row.get(0)?, row.get(1)?));
+    }
+    let sum: f64 = util::kahan_sum(totals.iter().map(|(_, total)| *total));
+
+    Ok(totals
+        .iter()
+        .take(top)
+        .map(|(view_id, total)| format!("{} ({:.1}%)", view_id, total / sum * 100.0))
+        .collect::<Vec<String>>()
+        .join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3_600_000_000_000;
+    /// Monday 2024-01-01 00:00:00 UTC.
+    const MONDAY: i64 = 1_704_067_200_000_000_000;
+
+    #[test]
+    fn test_get_hour_of_week() {
+        let utc = Timezone::Named(chrono_tz::UTC);
+        assert_eq!(get_hour_of_week(MONDAY, utc), 0);
+        assert_eq!(get_hour_of_week(MONDAY + 25 * HOUR, utc), 25);
+        assert_eq!(get_hour_of_week(MONDAY + 7 * 24 * HOUR - 1, utc), 167);
+        assert_eq!(get_hour_of_week(MONDAY + 7 * 24 * HOUR, utc), 0);
+
+        // Midnight UTC on Monday is 01:00 in Stockholm, and 19:00 on Sunday in New York.
+        let stockholm = Timezone::Named(chrono_tz::Europe::Stockholm);
+        assert_eq!(get_hour_of_week(MONDAY, stockholm), 1);
+        let new_york = Timezone::Named(chrono_tz::America::New_York);
+        assert_eq!(get_hour_of_week(MONDAY, new_york), 6 * 24 + 19);
+    }
+
+    #[test]
+    fn test_find_anomalies() {
+        // Six weeks of hourly windows, with a higher value at 09:00 on weekdays.
+        let bin_start: Vec<i64> = (0..6 * 7 * 24).map(|i| MONDAY + i * HOUR).collect();
+        let bin_end: Vec<i64> = bin_start.iter().map(|t| t + HOUR).collect();
+        let mut bin_statistic: Vec<f64> = (0..bin_start.len())
+            .map(|i| {
+                let base = if i % 24 == 9 && i % (7 * 24) < 5 * 24 {
+                    100.0
+                } else {
+                    10.0
+                };
+                base + (i / (7 * 24) % 3) as f64
+            })
+            .collect();
+        let utc = Timezone::Named(chrono_tz::UTC);
+        assert!(find_anomalies(&bin_start, &bin_end, &bin_statistic, utc, 3.0).is_empty());
+
+        // A value that is normal for 09:00 on a weekday is anomalous at 09:00 on a Saturday.
+        let saturday = 5 * 24 + 9 + 7 * 24;
+        bin_statistic[saturday] = 100.0;
+        let anomalies = find_anomalies(&bin_start, &bin_end, &bin_statistic, utc, 3.0);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].start, bin_start[saturday]);
+        assert_eq!(anomalies[0].end, bin_end[saturday]);
+        assert_eq!(anomalies[0].value, 100.0);
+        assert!(anomalies[0].score > 3.0);
+
+        // In another time zone, the weekday peaks fall in different hours of the week, but still
+        // share a baseline.
+        let stockholm = Timezone::Named(chrono_tz::Europe::Stockholm);
+        let anomalies = find_anomalies(&bin_start, &bin_end, &bin_statistic, stockholm, 3.0);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].start, bin_start[saturday]);
+    }
+
+    #[test]
+    fn test_find_anomalies_flat_baseline() {
+        // Four weeks of hourly windows that are zero at night and vary during the day.
+        let bin_start: Vec<i64> = (0..4 * 7 * 24).map(|i| MONDAY + i * HOUR).collect();
+        let bin_end: Vec<i64> = bin_start.iter().map(|t| t + HOUR).collect();
+        let mut bin_statistic: Vec<f64> = (0..bin_start.len())
+            .map(|i| {
+                if (8..17).contains(&(i % 24)) {
+                    10.0 + (i / (7 * 24)) as f64
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let utc = Timezone::Named(chrono_tz::UTC);
+        assert!(find_anomalies(&bin_start, &bin_end, &bin_statistic, utc, 3.5).is_empty());
+
+        // A spike at 03:00 on a Tuesday, where the baseline is always zero.
+        let night = 7 * 24 + 24 + 3;
+        bin_statistic[night] = 2.0;
+        let anomalies = find_anomalies(&bin_start, &bin_end, &bin_statistic, utc, 3.5);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].start, bin_start[night]);
+        assert_eq!(anomalies[0].baseline_median, 0.0);
+        assert_eq!(anomalies[0].baseline_mad, 0.0);
+        assert_eq!(anomalies[0].score, f64::INFINITY);
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::config::{Aggregation, ChangePoints, Filter};
+use crate::{util, util::Result};
+
+pub(crate) fn detect_change_points<P: AsRef<Path>>(
+    database_path: P,
+    filter: &Filter,
+    aggregation: &Aggregation,
+    change_point_cfg: &ChangePoints,
+) -> Result<()> {
+    if aggregation.mode.is_time_weighted() {
+        return Err("Time-weighted aggregation modes are not supported for execution times".into());
+    }
+    let conn = Connection::open(database_path)?;
+    util::register_calendar_functions(&conn)?;
+
+    let by_view = util::get_timed_samples_by_view(
+        &conn,
+        filter.start.as_deref(),
+        filter.end.as_deref(),
+        Some(filter),
+        "wallclock_time_ns",
+        "item_view_executor_execute",
+    )?;
+    let mut view_ids: Vec<i32> = by_view.keys().copied().collect();
+    view_ids.sort();
+
+    let binning_function =
+        util::get_grouping_function_for_time_window(&aggregation.size, aggregation.timezone);
+    let unbinning_function =
+        util::get_ungrouping_function_for_time_period(&aggregation.size, aggregation.timezone);
+
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(std::io::stdout());
+
+    csv_writer.write_record([
+        "view ID",
+        "timestamp",
+        "before count",
+        "after count",
+        "before median",
+        "after median",
+        "median ratio",
+    ])?;
+
+    eprintln!("Detecting change points for {} views...", view_ids.len());
+    for view_id in view_ids {
+        let util::TimedSamples { timestamp, value } = &by_view[&view_id];
+
+        let (bin_indices, mut bin_values) =
+            util::group_by_x(timestamp, value, aggregation.min_count, &binning_function);
+        util::sort_bins_inplace(&mut bin_values);
+        let statistics_per_bin = util::get_statistics_per_bin(&bin_values);
+        let bin_statistic = util::extract_statistic(&statistics_per_bin, &aggregation.mode)?;
+
+        let change_points = util::detect_relative_change_points(&bin_statistic, change_point_cfg);
+
+        // Compare the raw samples between consecutive change points, rather than the aggregated
+        // values, so that the medians are not skewed by bins with few samples.
+        let boundaries: Vec<i64> = change_points
+            .iter()
+            .map(|i| unbinning_function(bin_indices[*i]))
+            .collect();
+        for (i, boundary) in boundaries.iter().enumerate() {
+            let previous = if i == 0 { i64::MIN } else { boundaries[i - 1] };
+            let next = boundaries.get(i + 1).copied().unwrap_or(i64::MAX);
+            let before = get_sorted_seconds_in_range(timestamp, value, previous, *boundary);
+            let after = get_sorted_seconds_in_range(timestamp, value, *boundary, next);
+            if before.is_empty() || after.is_empty() {
+                continue;
+            }
+            let before_median = util::get_median(&before);
+            let after_median = util::get_median(&after);
+            csv_writer.write_record(&[
+                view_id.to_string(),
+                util::nanoseconds_epoch_to_string(*boundary),
+                before.len().to_string(),
+                after.len().to_string(),
+                before_median.to_string(),
+                after_median.to_string(),
+                util::get_median_ratio(before_median, after_median).to_string(),
+            ])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the values, converted from nanoseconds to seconds and sorted, whose timestamps are in
+/// the range `start..end`. The timestamps must be sorted.
+fn get_sorted_seconds_in_range(timestamp: &[i64], value: &[u64], start: i64, end: i64) -> Vec<f64> {
+    let first = timestamp.partition_point(|t| *t < start);
+    let last = timestamp.partition_point(|t| *t < end);
+    let mut values = util::nanoseconds_duration_to_seconds(&value[first..last]);
+    values.sort_by(|a, b| a.total_cmp(b));
+    values
+}
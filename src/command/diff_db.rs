@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use rusqlite::{Connection, OpenFlags};
+
+use super::form_statistics::get_samples_by_form;
+use super::view_statistics::get_samples_by_view;
+use crate::{util, util::Result};
+
+/// A view or form that was matched between the two databases.
+struct DiffRow {
+    kind: &'static str,
+    before_id: i32,
+    after_id: i32,
+    matched_by: &'static str,
+    before: util::Statistics<f64>,
+    after: util::Statistics<f64>,
+}
+
+impl DiffRow {
+    /// The relative change of the median, where values above 1.0 mean that
+    /// the view or form got slower.
+    fn median_ratio(&self) -> f64 {
+        util::get_median_ratio(self.before.median, self.after.median)
+    }
+}
+
+pub(crate) fn diff_db<P1: AsRef<Path>, P2: AsRef<Path>>(
+    before_path: P1,
+    after_path: P2,
+    threshold: f64,
+) -> Result<()> {
+    let before_conn = open_database(before_path)?;
+    let after_conn = open_database(after_path)?;
+
+    let mut rows: Vec<DiffRow> = Vec::new();
+
+    let before_views = get_statistics_by_id(get_samples_by_view(
+        &before_conn,
+        None,
+        None,
+        None,
+        "wallclock_time_ns",
+        "item_view_executor_execute",
+    )?);
+    let after_views = get_statistics_by_id(get_samples_by_view(
+        &after_conn,
+        None,
+        None,
+        None,
+        "wallclock_time_ns",
+        "item_view_executor_execute",
+    )?);
+    let before_queries = get_normalized_queries_by_view(&before_conn)?;
+    let after_queries = get_normalized_queries_by_view(&after_conn)?;
+    for (before_id, after_id, matched_by) in
+        match_views(&before_views, &after_views, &before_queries, &after_queries)
+    {
+        rows.push(DiffRow {
+            kind: "view",
+            before_id,
+            after_id,
+            matched_by,
+            before: before_views[&before_id].clone(),
+            after: after_views[&after_id].clone(),
+        });
+    }
+
+    let before_forms = get_statistics_by_id(get_samples_by_form(
+        &before_conn,
+        None,
+        None,
+        None,
+        "wallclock_time_ns",
+        "form_widget_startup",
+    )?);
+    let mut after_forms = get_statistics_by_id(get_samples_by_form(
+        &after_conn,
+        None,
+        None,
+        None,
+        "wallclock_time_ns",
+        "form_widget_startup",
+    )?);
+    for (form_id, before) in before_forms {
+        if let Some(after) = after_forms.remove(&form_id) {
+            rows.push(DiffRow {
+                kind: "form",
+                before_id: form_id,
+                after_id: form_id,
+                matched_by: "id",
+                before,
+                after,
+            });
+        }
+    }
+
+    // Only report rows where the median changed by more than the threshold,
+    // in either direction. The worst regressions are listed first.
+    rows.retain(|row| {
+        let ratio = row.median_ratio();
+        ratio > 1.0 + threshold || ratio < 1.0 / (1.0 + threshold)
+    });
+    rows.sort_by(|a, b| b.median_ratio().total_cmp(&a.median_ratio()));
+
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(std::io::stdout());
+
+    csv_writer.write_record([
+        "kind",
+        "before ID",
+        "after ID",
+        "matched by",
+        "change",
+        "before count",
+        "after count",
+        "before median",
+        "after median",
+        "before Q3",
+        "after Q3",
+        "median ratio",
+    ])?;
+
+    for row in rows.iter() {
+        let change = if row.median_ratio() > 1.0 {
+            "regression"
+        } else {
+            "improvement"
+        };
+        csv_writer.write_record(&[
+            row.kind.to_string(),
+            row.before_id.to_string(),
+            row.after_id.to_string(),
+            row.matched_by.to_string(),
+            change.to_string(),
+            row.before.count.to_string(),
+            row.after.count.to_string(),
+            row.before.median.to_string(),
+            row.after.median.to_string(),
+            row.before.q3.to_string(),
+            row.after.q3.to_string(),
+            row.median_ratio().to_string(),
+        ])?;
+    }
+
+    Ok(())
+}
+
+/// Open a database read-only, so that a missing file is an error rather than
+/// an empty database.
+fn open_database<P: AsRef<Path>>(path: P) -> Result<Connection> {
+    let path = path.as_ref();
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Error opening database {}: {}", path.display(), e).into())
+}
+
+/// Compute statistics (in seconds) for each ID in a map of nanosecond samples.
+fn get_statistics_by_id(
+    mut samples_by_id: HashMap<i32, Vec<u64>>,
+) -> BTreeMap<i32, util::Statistics<f64>> {
+    samples_by_id
+        .iter_mut()
+        .map(|(id, values)| {
+            values.sort();
+            let values = util::nanoseconds_duration_to_seconds(values);
+            (*id, util::get_statistics(&values))
+        })
+        .collect()
+}
+
+/// Read the set of normalized SQL queries that each view has executed. This
+/// requires the `item_view_executor_execute_normalized` table created by the
+/// `build-view-sql-index` command. If the table does not exist, an empty map
+/// is returned and views can only be matched by ID.
+fn get_normalized_queries_by_view(conn: &Connection) -> Result<HashMap<i32, BTreeSet<String>>> {
+    if !util::table_exists(conn, "item_view_executor_execute_normalized")? {
+        eprintln!(
+            "Table item_view_executor_execute_normalized not found, run build-view-sql-index \
+            to also match views by SQL query"
+        );
+        return Ok(HashMap::new());
+    }
+
+    let mut stmt = conn.prepare(
+        "select distinct e.view_id, n.query from item_view_executor_execute as e
+        inner join item_view_executor_execute_normalized as n on n.id = e.query
+        where e.view_id is not null and n.query is not null",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut by_view: HashMap<i32, BTreeSet<String>> = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let view_id: i32 = row.get(0)?;
+        let query: String = row.get(1)?;
+        by_view.entry(view_id).or_default().insert(query);
+    }
+    Ok(by_view)
+}
+
+/// Pair up views from the two databases. Views are first matched by ID. The
+/// remaining views are then matched if they have executed the same normalized
+/// SQL query, which covers views that have been recreated with a new ID (for
+/// example when a configuration is exported and imported into a new system).
+///
+/// # Returns
+/// A vector of `(before ID, after ID, matched by)` tuples.
+fn match_views<T>(
+    before_views: &BTreeMap<i32, T>,
+    after_views: &BTreeMap<i32, T>,
+    before_queries: &HashMap<i32, BTreeSet<String>>,
+    after_queries: &HashMap<i32, BTreeSet<String>>,
+) -> Vec<(i32, i32, &'static str)> {
+    let mut matches = Vec::new();
+    let mut unmatched_before: BTreeSet<i32> = BTreeSet::new();
+    let mut unmatched_after: BTreeSet<i32> = BTreeSet::new();
+    for view_id in before_views.keys() {
+        if after_views.contains_key(view_id) {
+            matches.push((*view_id, *view_id, "id"));
+        } else {
+            unmatched_before.insert(*view_id);
+        }
+    }
+    for view_id in after_views.keys() {
+        if !before_views.contains_key(view_id) {
+            unmatched_after.insert(*view_id);
+        }
+    }
+
+    let mut before_by_query: HashMap<&str, i32> = HashMap::new();
+    for view_id in unmatched_before.iter().rev() {
+        if let Some(queries) = before_queries.get(view_id) {
+            for query in queries {
+                before_by_query.insert(query.as_str(), *view_id);
+            }
+        }
+    }
+
+    for after_id in unmatched_after {
+        let queries = match after_queries.get(&after_id) {
+            Some(queries) => queries,
+            None => continue,
+        };
+        let before_id = queries
+            .iter()
+            .filter_map(|query| before_by_query.get(query.as_str()))
+            .find(|before_id| unmatched_before.contains(before_id))
+            .copied();
+        if let Some(before_id) = before_id {
+            unmatched_before.remove(&before_id);
+            matches.push((before_id, after_id, "sql"));
+        }
+    }
+
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queries(views: &[(i32, &[&str])]) -> HashMap<i32, BTreeSet<String>> {
+        views
+            .iter()
+            .map(|(id, queries)| (*id, queries.iter().map(|q| q.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_match_views() {
+        let before_views: BTreeMap<i32, ()> = [1, 2, 3, 4].into_iter().map(|id| (id, ())).collect();
+        let after_views: BTreeMap<i32, ()> =
+            [1, 12, 13, 14].into_iter().map(|id| (id, ())).collect();
+        let before_queries = queries(&[
+            (1, &["select a"]),
+            (2, &["select b", "select c"]),
+            (3, &["select d"]),
+            (4, &["select e"]),
+        ]);
+        let after_queries = queries(&[
+            (1, &["select a"]),
+            (12, &["select c"]),
+            (13, &["select d"]),
+            (14, &["select x"]),
+        ]);
+        let matches = match_views(&before_views, &after_views, &before_queries, &after_queries);
+        assert_eq!(matches, vec![(1, 1, "id"), (2, 12, "sql"), (3, 13, "sql")]);
+
+        // A view that matches by SQL is not matched twice.
+        let after_views: BTreeMap<i32, ()> = [22, 23].into_iter().map(|id| (id, ())).collect();
+        let after_queries = queries(&[(22, &["select b"]), (23, &["select c"])]);
+        let matches = match_views(&before_views, &after_views, &before_queries, &after_queries);
+        assert_eq!(matches, vec![(2, 22, "sql")]);
+    }
+}
@@ -5,17 +5,14 @@ use std::path::Path;
 use crate::config::Filter;
 use crate::{util, util::Result};
 
-pub(crate) fn form_statistics<P: AsRef<Path>>(
-    database_path: P,
-    start: Option<&str>,
-    end: Option<&str>,
-) -> Result<()> {
+pub(crate) fn form_statistics<P: AsRef<Path>>(database_path: P, filter: &Filter) -> Result<()> {
     let conn = rusqlite::Connection::open(database_path)?;
+    util::register_calendar_functions(&conn)?;
     let mut by_form: HashMap<i32, Vec<u64>> = get_samples_by_form(
         &conn,
-        start,
-        end,
-        None,
+        filter.start.as_deref(),
+        filter.end.as_deref(),
+        Some(filter),
         "wallclock_time_ns",
         "form_widget_startup",
     )?;
@@ -75,7 +72,7 @@ pub fn get_samples_by_form(
     table: &str,
 ) -> Result<HashMap<i32, Vec<u64>>> {
     let mut sql = format!("select form_id, {} from {}", column, table);
-    let mut criteria = util::get_common_criteria(start, end, filter, None);
+    let mut criteria = util::get_common_criteria(conn, start, end, filter, None)?;
     criteria.push("form_id is not null".to_string());
     sql.push_str(" where ");
     sql.push_str(&criteria.join(" and "));
@@ -304,7 +304,7 @@ fn import_directory<P1: AsRef<Path>, P2: AsRef<Path>>(target: &P1, source: P2) -
 
 fn get_file_sizes<P: AsRef<Path>>(
     root_path: &P,
-    descriptor: &Vec<TableDescriptor>,
+    descriptor: &[TableDescriptor],
 ) -> Result<Vec<u64>> {
     let mut sizes = Vec::with_capacity(descriptor.len());
     for table_descriptor in descriptor.iter() {
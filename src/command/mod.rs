@@ -1,23 +1,35 @@
 pub(crate) use build_view_sql_index::build_view_sql_index;
 pub(crate) use compute_overlap::compute_overlap;
 pub(crate) use compute_overlap_pca::compute_overlap_pca;
+pub(crate) use correlate::correlate;
+pub(crate) use detect_anomalies::detect_anomalies;
+pub(crate) use detect_change_points::detect_change_points;
+pub(crate) use diff_db::diff_db;
 pub(crate) use form_statistics::form_statistics;
 pub(crate) use get_cell::get_cell;
 pub(crate) use import::import_data;
 pub(crate) use match_query_view::match_query_view;
 pub(crate) use normalize_sql::normalize_sql;
 pub(crate) use plot::plot;
+pub(crate) use test_regression::test_regression;
 pub(crate) use unit_conversion::convert_unit;
+pub(crate) use validate_config::validate_config;
 pub(crate) use view_statistics::view_statistics;
 
 mod build_view_sql_index;
 mod compute_overlap;
 mod compute_overlap_pca;
+mod correlate;
+mod detect_anomalies;
+mod detect_change_points;
+mod diff_db;
 mod form_statistics;
 mod get_cell;
 mod import;
 mod match_query_view;
 mod normalize_sql;
 mod plot;
+mod test_regression;
 mod unit_conversion;
+mod validate_config;
 mod view_statistics;
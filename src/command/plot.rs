@@ -3,10 +3,24 @@ use std::path::Path;
 
 use plotly::Plot;
 
-use crate::{config, plot, util::Result};
+use crate::{config, plot, util, util::Result};
 
-pub(crate) fn plot<T: AsRef<Path>>(configuration_yaml_path: T) -> Result<()> {
-    let config: config::Root = match config::load_config(&configuration_yaml_path) {
+/// Plot the data described by a configuration file. The plot is written to a file if an output
+/// path is given, either as an argument or in the configuration, and shown in the browser
+/// otherwise.
+///
+/// # Arguments
+/// * `configuration_yaml_path` - The plot configuration file.
+/// * `output` - The file to write the plot to, overriding the `output` setting in the
+///   configuration. The format is given by the file extension.
+/// * `variables` - Values for the variables in the configuration, as name and value, overriding
+///   those defined in the configuration.
+pub(crate) fn plot<T: AsRef<Path>>(
+    configuration_yaml_path: T,
+    output: Option<&Path>,
+    variables: &[(String, String)],
+) -> Result<()> {
+    let config: config::Root = match config::load_config(&configuration_yaml_path, variables) {
         Ok(config) => config,
         Err(e) => {
             return Err(format!(
@@ -36,38 +50,114 @@ pub(crate) fn plot<T: AsRef<Path>>(configuration_yaml_path: T) -> Result<()> {
             .into());
         }
     };
+    util::register_calendar_functions(&conn)?;
 
     let mut plot = Plot::new();
-    if let Some(layout) = &config.layout {
-        plot.set_layout(layout.into());
-    }
+    let mut layout: plotly::Layout = match &config.layout {
+        Some(layout) => layout.into(),
+        None => plotly::Layout::new(),
+    };
 
     let color_gen = plot::DefaultColorGenerator::new();
     let mut context = plot::PlotContext {
         conn,
         color_gen: RefCell::new(color_gen),
         plot_config: config::PlotCommon::empty(),
+        shapes: RefCell::new(Vec::new()),
     };
 
+    let mut time_axes: Vec<(String, String)> = Vec::new();
     for plot_common in config.plots {
         context.plot_config = plot_common.plot_common;
+        if plot_common.plot_variant.has_time_axis() {
+            let axes = (
+                context
+                    .plot_config
+                    .x_axis
+                    .clone()
+                    .unwrap_or("x".to_string()),
+                context
+                    .plot_config
+                    .y_axis
+                    .clone()
+                    .unwrap_or("y".to_string()),
+            );
+            if !time_axes.contains(&axes) {
+                time_axes.push(axes);
+            }
+        }
         let traces = match &plot_common.plot_variant {
             config::PlotVariant::TimeScatter(plot_variant_config) => {
-                println!("Plotting time scatter plot: {:?}", plot_variant_config);
+                eprintln!("Plotting time scatter plot {}", context.plot_config.name);
                 plot::time_scatter_plot(&context, plot_variant_config)?
             }
             config::PlotVariant::CountScatter(plot_variant_config) => {
-                println!("Plotting count scatter plot: {:?}", plot_variant_config);
+                eprintln!("Plotting count scatter plot {}", context.plot_config.name);
                 plot::count_scatter_plot(&context, plot_variant_config)?
             }
             config::PlotVariant::Overlap(plot_variant_config) => {
-                println!("Plotting overlap plot: {:?}", plot_variant_config);
+                eprintln!("Plotting overlap plot {}", context.plot_config.name);
                 plot::overlap_plot(&context.conn, &context.plot_config, plot_variant_config)?
             }
+            config::PlotVariant::Histogram(plot_variant_config) => {
+                eprintln!("Plotting histogram plot {}", context.plot_config.name);
+                plot::histogram_plot(&context, plot_variant_config)?
+            }
+            config::PlotVariant::Box(plot_variant_config) => {
+                eprintln!("Plotting box plot {}", context.plot_config.name);
+                plot::box_plot(&context, plot_variant_config)?
+            }
+            config::PlotVariant::Violin(plot_variant_config) => {
+                eprintln!("Plotting violin plot {}", context.plot_config.name);
+                plot::violin_plot(&context, plot_variant_config)?
+            }
+            config::PlotVariant::CalendarHeatmap(plot_variant_config) => {
+                eprintln!(
+                    "Plotting calendar heatmap plot {}",
+                    context.plot_config.name
+                );
+                plot::calendar_heatmap_plot(&context, plot_variant_config)?
+            }
+            config::PlotVariant::Concurrency(plot_variant_config) => {
+                eprintln!("Plotting concurrency plot {}", context.plot_config.name);
+                plot::concurrency_plot(&context, plot_variant_config)?
+            }
+            config::PlotVariant::Sql(plot_variant_config) => {
+                eprintln!("Plotting SQL plot {}", context.plot_config.name);
+                plot::sql_plot(&context, plot_variant_config)?
+            }
+            config::PlotVariant::Xy(plot_variant_config) => {
+                eprintln!("Plotting xy plot {}", context.plot_config.name);
+                plot::xy_plot(&context, plot_variant_config)?
+            }
         };
         plot.add_traces(traces);
     }
 
-    plot.show();
+    for shape in context.shapes.take() {
+        layout.add_shape(shape);
+    }
+    for annotation in config.annotations.iter().flatten() {
+        let annotations = util::get_annotations(&context.conn, annotation, configuration_dir)?;
+        util::add_annotations(&mut layout, &annotations, &time_axes);
+    }
+    plot.set_layout(layout);
+
+    let output = match output {
+        Some(output) => Some(output.to_path_buf()),
+        None => config
+            .output
+            .as_ref()
+            .map(|output| configuration_dir.join(output)),
+    };
+    match output {
+        Some(output) => {
+            eprintln!("Writing plot to {}", output.display());
+            let width = config.layout.as_ref().and_then(|layout| layout.width);
+            let height = config.layout.as_ref().and_then(|layout| layout.height);
+            util::write_plot(&mut plot, &output, width, height)?;
+        }
+        None => plot.show(),
+    }
     Ok(())
 }
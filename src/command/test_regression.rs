@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::config::Filter;
+use crate::{util, util::Result};
+
+/// The percentiles for which confidence intervals are reported.
+const PERCENTILES: [(&str, f64); 4] = [("median", 0.5), ("Q3", 0.75), ("P90", 0.9), ("P99", 0.99)];
+
+pub(crate) fn test_regression<P: AsRef<Path>>(
+    database_path: P,
+    view_id: i32,
+    before: &str,
+    after: &str,
+    confidence: f64,
+    resamples: usize,
+) -> Result<()> {
+    if !(confidence > 0.0 && confidence < 1.0) {
+        return Err(format!(
+            "Confidence level must be between 0 and 1, got {}",
+            confidence
+        )
+        .into());
+    }
+    if resamples == 0 {
+        return Err("Number of resamples must be at least 1".into());
+    }
+    let conn = Connection::open(database_path)?;
+
+    let before_samples = get_view_samples(&conn, view_id, before)?;
+    let after_samples = get_view_samples(&conn, view_id, after)?;
+    if before_samples.is_empty() || after_samples.is_empty() {
+        return Err(format!(
+            "No samples for view {} in the {} period",
+            view_id,
+            if before_samples.is_empty() {
+                "before"
+            } else {
+                "after"
+            }
+        )
+        .into());
+    }
+
+    let mann_whitney = util::mann_whitney_u(&after_samples, &before_samples);
+    let kolmogorov_smirnov = util::kolmogorov_smirnov(&before_samples, &after_samples);
+
+    let mut rng = util::Rng::new(0);
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(std::io::stdout());
+
+    csv_writer.write_record([
+        "statistic",
+        "before",
+        "before CI low",
+        "before CI high",
+        "after",
+        "after CI low",
+        "after CI high",
+        "difference",
+        "difference CI low",
+        "difference CI high",
+    ])?;
+
+    for (name, percentile) in PERCENTILES {
+        let statistic = |sorted: &[f64]| util::get_percentile(sorted, percentile);
+        let before_value = statistic(&before_samples);
+        let after_value = statistic(&after_samples);
+        let (before_low, before_high) = util::bootstrap_confidence_interval(
+            &before_samples,
+            &statistic,
+            resamples,
+            confidence,
+            &mut rng,
+        );
+        let (after_low, after_high) = util::bootstrap_confidence_interval(
+            &after_samples,
+            &statistic,
+            resamples,
+            confidence,
+            &mut rng,
+        );
+        let (difference_low, difference_high) = util::bootstrap_difference_confidence_interval(
+            &before_samples,
+            &after_samples,
+            &statistic,
+            resamples,
+            confidence,
+            &mut rng,
+        );
+        csv_writer.write_record(&[
+            name.to_string(),
+            before_value.to_string(),
+            before_low.to_string(),
+            before_high.to_string(),
+            after_value.to_string(),
+            after_low.to_string(),
+            after_high.to_string(),
+            (after_value - before_value).to_string(),
+            difference_low.to_string(),
+            difference_high.to_string(),
+        ])?;
+    }
+    csv_writer.flush()?;
+    drop(csv_writer);
+
+    println!();
+    println!(
+        "Samples: {} before, {} after",
+        before_samples.len(),
+        after_samples.len()
+    );
+    println!(
+        "Mann-Whitney U: U = {}, z = {:.4}, p = {:.6}, rank-biserial effect size = {:.4}",
+        mann_whitney.u, mann_whitney.z, mann_whitney.p_value, mann_whitney.effect_size
+    );
+    println!(
+        "Kolmogorov-Smirnov: D = {:.4}, p = {:.6}",
+        kolmogorov_smirnov.d, kolmogorov_smirnov.p_value
+    );
+
+    Ok(())
+}
+
+/// Read the sorted execution times of a view, in seconds, within a time range.
+fn get_view_samples(conn: &Connection, view_id: i32, range: &str) -> Result<Vec<f64>> {
+    let (start, end) = util::split_time_range(range);
+    let filter = Filter {
+        sql_where: Some(format!("view_id = {}", view_id)),
+        ..Default::default()
+    };
+    let (_, mut values) = util::get_samples(
+        conn,
+        start,
+        end,
+        Some(&filter),
+        "wallclock_time_ns",
+        "item_view_executor_execute",
+    )?;
+    values.sort();
+    Ok(util::nanoseconds_duration_to_seconds(&values))
+}
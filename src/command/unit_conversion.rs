@@ -1,6 +1,6 @@
 use regex::Regex;
 
-use crate::util::{parse_datetime_ceil, parse_datetime_floor};
+use crate::util::parse_time_range;
 
 lazy_static! {
     static ref DURATION_REGEX: Regex = Regex::new(r"^\s*(\d+)\s*([A-Za-z]+)\s*$").unwrap();
@@ -8,16 +8,18 @@ lazy_static! {
 }
 
 pub(crate) fn convert_unit(value: &str) {
-    if let Ok(start) = parse_datetime_floor(value) {
-        let end = parse_datetime_ceil(value).unwrap();
+    let time = parse_time_range(None, value);
+    if let Ok((start, end)) = time {
+        let (Some(start_ns), Some(end_ns)) =
+            (start.timestamp_nanos_opt(), end.timestamp_nanos_opt())
+        else {
+            eprintln!("{} is out of range for timestamps in nanoseconds", value);
+            return;
+        };
         if start == end {
-            println!("{}", start.timestamp_nanos_opt().unwrap());
+            println!("{}", start_ns);
         } else {
-            println!(
-                "{} - {}",
-                start.timestamp_nanos_opt().unwrap(),
-                end.timestamp_nanos_opt().unwrap()
-            );
+            println!("{} - {}", start_ns, end_ns);
             println!("({} - {})", start, end);
         }
         return;
@@ -85,5 +87,10 @@ pub(crate) fn convert_unit(value: &str) {
         return;
     }
 
-    eprintln!("Unable to parse {} as a timestamp or a duration", value);
+    if let Err(e) = time {
+        eprintln!(
+            "Unable to parse {} as a time, a timestamp or a duration: {}",
+            value, e
+        );
+    }
 }
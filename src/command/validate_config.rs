@@ -0,0 +1,312 @@
+use std::path::Path;
+
+use regex::Regex;
+use rusqlite::Connection;
+
+use crate::config::{self, ConfigError, PathSegment, PlotVariant};
+use crate::{util, util::Result};
+
+lazy_static! {
+    static ref IDENTIFIER_REGEX: Regex = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+}
+
+/// Check a configuration file without plotting it: that it matches the schema of the
+/// configuration, which loading it checks, and that the tables, columns and SQL of its plots are
+/// valid in the source database.
+///
+/// # Arguments
+/// * `configuration_yaml_path` - The plot configuration file.
+/// * `variables` - Values for the variables in the configuration, as name and value, overriding
+///   those defined in the configuration.
+pub(crate) fn validate_config<T: AsRef<Path>>(
+    configuration_yaml_path: T,
+    variables: &[(String, String)],
+) -> Result<()> {
+    let path = configuration_yaml_path.as_ref();
+    let (config, mut locator) = config::load_located_config(path, variables)
+        .map_err(|e| format!("Error loading configuration {}: {}", path.display(), e))?;
+    let source_path = path.parent().unwrap().join(&config.source);
+    let conn =
+        Connection::open_with_flags(&source_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Error opening database {}: {}", source_path.display(), e))?;
+    // Filters can use the calendar functions.
+    util::register_calendar_functions(&conn)?;
+
+    let tables = util::get_table_names(&conn)?;
+    let mut errors: Vec<ConfigError> = Vec::new();
+    for (i, plot) in config.plots.iter().enumerate() {
+        let mut checker = PlotChecker {
+            conn: &conn,
+            tables: &tables,
+            path: vec![PathSegment::from("plots"), PathSegment::Index(i)],
+            errors: &mut errors,
+        };
+        checker.check_plot(plot)?;
+    }
+    if !errors.is_empty() {
+        return Err(locator.format_errors(&errors).into());
+    }
+    println!("{} is valid", path.display());
+    Ok(())
+}
+
+/// The columns or SQL expressions of a plot, with their keys.
+type Columns<'a> = Vec<(&'static str, Option<&'a String>)>;
+
+/// Checks the tables, columns and SQL of a plot against the source database.
+struct PlotChecker<'a> {
+    conn: &'a Connection,
+    tables: &'a [String],
+    /// The path of the plot in the configuration.
+    path: Vec<PathSegment>,
+    errors: &'a mut Vec<ConfigError>,
+}
+
+impl PlotChecker<'_> {
+    fn check_plot(&mut self, plot: &config::PlotConfig) -> Result<()> {
+        let plot_common = &plot.plot_common;
+        let (table, joins, columns): (&str, &[String], Columns) = match &plot.plot_variant {
+            PlotVariant::TimeScatter(plot) => (
+                &plot.table,
+                &[],
+                vec![
+                    ("column", Some(&plot.column)),
+                    ("groupby", plot.group_by.as_ref()),
+                ],
+            ),
+            PlotVariant::CountScatter(plot) => (
+                &plot.table,
+                &[],
+                vec![
+                    ("column", Some(&plot.column)),
+                    ("groupby", plot.group_by.as_ref()),
+                ],
+            ),
+            PlotVariant::Histogram(plot) => {
+                (&plot.table, &[], vec![("column", Some(&plot.column))])
+            }
+            PlotVariant::Box(plot) | PlotVariant::Violin(plot) => (
+                &plot.table,
+                &[],
+                vec![
+                    ("column", Some(&plot.column)),
+                    ("groupby", Some(&plot.group_by)),
+                ],
+            ),
+            PlotVariant::CalendarHeatmap(plot) => {
+                (&plot.table, &[], vec![("column", Some(&plot.column))])
+            }
+            PlotVariant::Xy(plot) => (
+                &plot.table,
+                plot.join.as_deref().unwrap_or_default(),
+                vec![
+                    ("x", Some(&plot.x)),
+                    ("y", Some(&plot.y)),
+                    ("colorby", plot.color_by.as_ref()),
+                ],
+            ),
+            PlotVariant::Sql(plot) => {
+                let columns = match self.conn.prepare(&plot.query) {
+                    Ok(stmt) => stmt
+                        .column_names()
+                        .into_iter()
+                        .map(str::to_string)
+                        .collect::<Vec<String>>(),
+                    Err(e) => {
+                        self.error(
+                            &["query"],
+                            format!("invalid query: {}", get_sql_error_message(e)),
+                        );
+                        return Ok(());
+                    }
+                };
+                // The plot selects these from the query, as in `select x, y from (query)`.
+                let from = format!("({})", plot.query);
+                let expressions = [
+                    ("x", Some(&plot.x)),
+                    ("y", Some(&plot.y)),
+                    ("series", plot.series.as_ref()),
+                ];
+                for (key, expression) in expressions {
+                    if let Some(expression) = expression {
+                        self.check_expression(key, expression, &from, "the query", &columns);
+                    }
+                }
+                self.check_where(plot_common, &from);
+                return Ok(());
+            }
+            // These plots read the tables that compute-overlap creates.
+            PlotVariant::Overlap(_) => {
+                let mut columns = Vec::new();
+                for table in [
+                    "item_view_executor_execute",
+                    "item_view_executor_execute_overlap",
+                ] {
+                    self.check_table(table, &["plot"], &mut columns)?;
+                }
+                return Ok(());
+            }
+            PlotVariant::Concurrency(plot) => {
+                // Without `compute`, the counts are computed from the executions if the
+                // active_query_count table is missing.
+                let mut columns = Vec::new();
+                match plot.compute {
+                    Some(false) => {
+                        self.check_table("active_query_count", &["compute"], &mut columns)?;
+                    }
+                    Some(true) => {
+                        self.check_table("item_view_executor_execute", &["plot"], &mut columns)?;
+                    }
+                    None => {
+                        if !self.has_table("active_query_count") {
+                            self.check_table(
+                                "item_view_executor_execute",
+                                &["plot"],
+                                &mut columns,
+                            )?;
+                        }
+                    }
+                }
+                return Ok(());
+            }
+        };
+
+        let mut known_columns: Vec<String> = Vec::new();
+        let table_names: Vec<String> = std::iter::once(table)
+            .chain(joins.iter().map(String::as_str))
+            .map(|name| format!("`{}`", name))
+            .collect();
+        let table_names = table_names.join(", ");
+        let mut from = table.to_string();
+        let mut missing_table = !self.check_table(table, &["table"], &mut known_columns)?;
+        for (j, join) in joins.iter().enumerate() {
+            let path = [PathSegment::from("join"), PathSegment::Index(j)];
+            missing_table |= !self.check_table(join, &path, &mut known_columns)?;
+            from.push_str(&format!(" inner join {} using (timestamp, ordinal)", join));
+        }
+        if missing_table {
+            return Ok(());
+        }
+
+        for (key, expression) in columns {
+            let Some(expression) = expression else {
+                continue;
+            };
+            let expression = match key {
+                "groupby" => util::get_group_expression(expression, plot_common.timezone()),
+                _ => expression.clone(),
+            };
+            self.check_expression(key, &expression, &from, &table_names, &known_columns);
+        }
+        self.check_where(plot_common, &from);
+        Ok(())
+    }
+
+    /// Check the `where` of the filter of a plot.
+    ///
+    /// # Arguments
+    /// * `plot_common` - The common configuration of the plot, with the filter.
+    /// * `from` - The tables or query that the plot selects from.
+    fn check_where(&mut self, plot_common: &config::PlotCommon, from: &str) {
+        let Some(sql_where) = plot_common
+            .filter
+            .as_ref()
+            .and_then(|filter| filter.sql_where.as_ref())
+        else {
+            return;
+        };
+        let sql = format!("select 1 from {} where {}", from, sql_where);
+        if let Err(e) = self.conn.prepare(&sql) {
+            self.error(
+                &["filter", "where"],
+                format!("invalid condition: {}", get_sql_error_message(e)),
+            );
+        }
+    }
+
+    /// Check that a table exists and add its columns to `columns`. Returns whether it exists.
+    fn check_table<K: Into<PathSegment> + Clone>(
+        &mut self,
+        table: &str,
+        key: &[K],
+        columns: &mut Vec<String>,
+    ) -> Result<bool> {
+        if !self.has_table(table) {
+            let suggestion = util::get_suggestion(table, self.tables.iter().map(String::as_str));
+            self.error(
+                key,
+                format!("no table `{}` in the source{}", table, suggestion),
+            );
+            return Ok(false);
+        }
+        columns.extend(util::get_column_names(self.conn, table)?);
+        Ok(true)
+    }
+
+    /// Check whether a table exists, ignoring case as SQLite does.
+    fn has_table(&self, table: &str) -> bool {
+        self.tables
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(table))
+    }
+
+    /// Check a column, or an SQL expression of the columns.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the expression in the plot.
+    /// * `expression` - The column or expression.
+    /// * `from` - The tables to select from, with their joins.
+    /// * `table_names` - The names of the tables, for the error message.
+    /// * `columns` - The columns of the tables.
+    fn check_expression(
+        &mut self,
+        key: &str,
+        expression: &str,
+        from: &str,
+        table_names: &str,
+        columns: &[String],
+    ) {
+        if IDENTIFIER_REGEX.is_match(expression) {
+            if !columns
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(expression))
+            {
+                let suggestion =
+                    util::get_suggestion(expression, columns.iter().map(String::as_str));
+                self.error(
+                    &[key],
+                    format!(
+                        "no column `{}` in {}{}",
+                        expression, table_names, suggestion
+                    ),
+                );
+            }
+            return;
+        }
+        let sql = format!("select {} from {}", expression, from);
+        if let Err(e) = self.conn.prepare(&sql) {
+            self.error(
+                &[key],
+                format!("invalid expression: {}", get_sql_error_message(e)),
+            );
+        }
+    }
+
+    fn error<K: Into<PathSegment> + Clone>(&mut self, key: &[K], message: String) {
+        let mut path = self.path.clone();
+        path.extend(key.iter().cloned().map(Into::into));
+        self.errors.push(ConfigError {
+            path,
+            key: false,
+            message,
+        });
+    }
+}
+
+/// Get the message of an SQL error, without the SQL.
+fn get_sql_error_message(error: rusqlite::Error) -> String {
+    match error {
+        rusqlite::Error::SqlInputError { msg, .. } => msg,
+        error => error.to_string(),
+    }
+}
@@ -7,17 +7,14 @@ use rusqlite::Connection;
 use crate::config::Filter;
 use crate::{util, util::Result};
 
-pub(crate) fn view_statistics<P: AsRef<Path>>(
-    database_path: P,
-    start: Option<&str>,
-    end: Option<&str>,
-) -> Result<()> {
+pub(crate) fn view_statistics<P: AsRef<Path>>(database_path: P, filter: &Filter) -> Result<()> {
     let conn = Connection::open(database_path)?;
+    util::register_calendar_functions(&conn)?;
     let mut by_view: HashMap<i32, Vec<u64>> = get_samples_by_view(
         &conn,
-        start,
-        end,
-        None,
+        filter.start.as_deref(),
+        filter.end.as_deref(),
+        Some(filter),
         "wallclock_time_ns",
         "item_view_executor_execute",
     )?;
@@ -77,7 +74,7 @@ pub fn get_samples_by_view(
     table: &str,
 ) -> Result<HashMap<i32, Vec<u64>>> {
     let mut sql = format!("select view_id, {} from {}", column, table);
-    let mut criteria = util::get_common_criteria(start, end, filter, None);
+    let mut criteria = util::get_common_criteria(conn, start, end, filter, None)?;
     criteria.push("view_id is not null".to_string());
     sql.push_str(" where ");
     sql.push_str(&criteria.join(" and "));
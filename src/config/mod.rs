@@ -1,3 +1,6 @@
+pub(crate) use schema::{ConfigError, PathSegment};
 pub use yaml::*;
 
+mod schema;
+mod template;
 mod yaml;
@@ -0,0 +1,967 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use regex::Regex;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_yaml::Value;
+
+use super::template::PlotOrigin;
+use super::Root;
+use crate::util::{self, Result};
+
+/*
+ * Validation of the configuration against a JSON schema, which is generated from the
+ * configuration structs so that it can't get out of date. It catches mistakes that serde lets
+ * through, such as misspelled keys, which serde ignores, and reports all of them at once with
+ * where they are in the YAML files.
+ *
+ * The validator only implements the parts of JSON schema that the generated schema uses. Unlike
+ * in JSON schema, mappings are closed: keys that are neither properties of the schema nor of the
+ * branch of `oneOf` or `anyOf` that the mapping matches are errors.
+ */
+
+/// The error message that the locator raises when it finds the value it is looking for.
+const FOUND: &str = "\0found";
+
+/// A key of a mapping or an index of a list, in the path of a value in the configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl From<&str> for PathSegment {
+    fn from(key: &str) -> Self {
+        PathSegment::Key(key.to_string())
+    }
+}
+
+/// An error in the configuration.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ConfigError {
+    pub path: Vec<PathSegment>,
+    /// Whether the error is about the key at the end of the path rather than its value.
+    pub key: bool,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            return write!(f, "{}", self.message);
+        }
+        write!(f, "{}: {}", format_path(&self.path), self.message)
+    }
+}
+
+/// Format a path like `plots[1].aggregation`.
+pub(crate) fn format_path(path: &[PathSegment]) -> String {
+    let mut formatted = String::new();
+    for segment in path {
+        match segment {
+            PathSegment::Key(key) if formatted.is_empty() => formatted.push_str(key),
+            PathSegment::Key(key) => {
+                formatted.push('.');
+                formatted.push_str(key);
+            }
+            PathSegment::Index(i) => formatted.push_str(&format!("[{}]", i)),
+        }
+    }
+    formatted
+}
+
+/// Get the JSON schema of the configuration.
+pub(crate) fn get_schema() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(Root)).unwrap()
+}
+
+/// Validate an expanded configuration against the schema.
+///
+/// # Returns
+/// An error that lists every error in the configuration, each with where it is.
+pub(crate) fn check_config(config: &Value, locator: &mut ConfigLocator) -> Result<()> {
+    let schema = get_schema();
+    let errors = validate(config, &schema);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(locator.format_errors(&errors).into())
+}
+
+/// Finds where errors in an expanded configuration are in the files that it was read from.
+pub(crate) struct ConfigLocator {
+    /// The files that the configuration was read from, the configuration file first.
+    files: Vec<PathBuf>,
+    /// Where each plot is defined.
+    plot_origins: Vec<PlotOrigin>,
+    contents: HashMap<PathBuf, Option<String>>,
+}
+
+impl ConfigLocator {
+    pub fn new(files: Vec<PathBuf>, plot_origins: Vec<PlotOrigin>) -> Self {
+        ConfigLocator {
+            files,
+            plot_origins,
+            contents: HashMap::new(),
+        }
+    }
+
+    /// Format errors in the configuration as a message with one line per error.
+    pub fn format_errors(&mut self, errors: &[ConfigError]) -> String {
+        let lines: Vec<String> = errors.iter().map(|error| self.format(error)).collect();
+        format!(
+            "{} error{} in the configuration:\n{}",
+            errors.len(),
+            if errors.len() == 1 { "" } else { "s" },
+            lines.join("\n")
+        )
+    }
+
+    /// Format an error as `file:line:column: path: message`, or with only the configuration file
+    /// if it isn't found in any of the files.
+    pub fn format(&mut self, error: &ConfigError) -> String {
+        match self.locate(error) {
+            Some((file, line, column)) => {
+                format!("{}:{}:{}: {}", file.display(), line, column, error)
+            }
+            None => format!("{}: {}", self.files[0].display(), error),
+        }
+    }
+
+    /// Find the file, line and column of an error. Errors in a plot are looked for where the
+    /// plot is defined, and other errors in the configuration file and the files it includes.
+    fn locate(&mut self, error: &ConfigError) -> Option<(PathBuf, usize, usize)> {
+        let candidates: Vec<(PathBuf, Vec<PathSegment>)> = match error.path.as_slice() {
+            [PathSegment::Key(plots), PathSegment::Index(i), rest @ ..] if plots == "plots" => self
+                .plot_origins
+                .get(*i)?
+                .iter()
+                .map(|(file, prefix)| {
+                    let mut path = prefix.clone();
+                    path.extend_from_slice(rest);
+                    (file.clone(), path)
+                })
+                .collect(),
+            path => self
+                .files
+                .iter()
+                .map(|file| (file.clone(), path.to_vec()))
+                .collect(),
+        };
+        for (file, path) in candidates {
+            let contents = self
+                .contents
+                .entry(file.clone())
+                .or_insert_with(|| std::fs::read_to_string(&file).ok());
+            let location = contents
+                .as_deref()
+                .and_then(|contents| locate(contents, &path, error.key));
+            if let Some((line, column)) = location {
+                return Some((file, line, column));
+            }
+        }
+        None
+    }
+}
+
+/// Validate a configuration against a JSON schema.
+///
+/// # Returns
+/// The errors in the configuration, in the order that they appear in it.
+pub(crate) fn validate(config: &Value, schema: &serde_json::Value) -> Vec<ConfigError> {
+    let mut validator = Validator {
+        definitions: &schema["definitions"],
+        errors: Vec::new(),
+    };
+    validator.validate(config, schema, &mut Vec::new());
+    validator.errors
+}
+
+struct Validator<'s> {
+    definitions: &'s serde_json::Value,
+    errors: Vec<ConfigError>,
+}
+
+impl<'s> Validator<'s> {
+    fn validate(
+        &mut self,
+        value: &Value,
+        schema: &'s serde_json::Value,
+        path: &mut Vec<PathSegment>,
+    ) {
+        let schema = self.resolve(schema);
+        let value = untag(value);
+
+        let mut branch = None;
+        if let Some(branches) = schema
+            .get("oneOf")
+            .or_else(|| schema.get("anyOf"))
+            .and_then(|branches| branches.as_array())
+        {
+            match self.select_branch(value, branches, path) {
+                Some(selected) => branch = Some(selected),
+                None => return,
+            }
+        }
+        let constrained = ["type", "enum", "properties"]
+            .iter()
+            .any(|keyword| schema.get(keyword).is_some());
+        if let (Some(branch), false) = (branch, constrained) {
+            self.validate(value, branch, path);
+            return;
+        }
+
+        if let Some(types) = schema.get("type") {
+            if !type_matches(types, value) {
+                self.error(
+                    path,
+                    false,
+                    format!(
+                        "expected {}, found {}",
+                        describe_types(types, value),
+                        kind_of(value)
+                    ),
+                );
+                return;
+            }
+        }
+        if let Some(values) = schema.get("enum").and_then(|values| values.as_array()) {
+            if !values
+                .iter()
+                .any(|expected| yaml_equals_json(value, expected))
+            {
+                let message = unknown_value_message(value, values);
+                self.error(path, false, message);
+                return;
+            }
+        }
+        match value {
+            Value::Mapping(mapping) => {
+                let mut schemas = vec![schema];
+                schemas.extend(branch.map(|branch| self.resolve(branch)));
+                self.validate_mapping(mapping, &schemas, path);
+            }
+            Value::Sequence(items) => self.validate_sequence(items, schema, path),
+            Value::Number(number) => {
+                let minimum = schema.get("minimum").and_then(|minimum| minimum.as_f64());
+                if let (Some(minimum), Some(number)) = (minimum, number.as_f64()) {
+                    if number < minimum {
+                        self.error(path, false, format!("must be at least {}", minimum));
+                    }
+                }
+            }
+            Value::String(s) => {
+                if let Some(pattern) = schema.get("pattern").and_then(|pattern| pattern.as_str()) {
+                    if !Regex::new(pattern).unwrap().is_match(s) {
+                        let description = schema
+                            .get("description")
+                            .and_then(|description| description.as_str())
+                            .map(|description| {
+                                format!(
+                                    ", expected {}",
+                                    lowercase_first(description).trim_end_matches('.')
+                                )
+                            })
+                            .unwrap_or_default();
+                        self.error(path, false, format!("invalid value `{}`{}", s, description));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Validate a mapping against the properties of one or more schemas: an object schema and
+    /// the branch of its `oneOf` that the mapping matches.
+    fn validate_mapping(
+        &mut self,
+        mapping: &serde_yaml::Mapping,
+        schemas: &[&'s serde_json::Value],
+        path: &mut Vec<PathSegment>,
+    ) {
+        let mut properties: Vec<(&'s str, &'s serde_json::Value)> = Vec::new();
+        let mut required: Vec<&'s str> = Vec::new();
+        let mut additional = None;
+        for schema in schemas {
+            if let Some(schema_properties) = schema.get("properties").and_then(|p| p.as_object()) {
+                properties.extend(
+                    schema_properties
+                        .iter()
+                        .map(|(key, value)| (key.as_str(), value)),
+                );
+            }
+            if let Some(schema_required) = schema.get("required").and_then(|r| r.as_array()) {
+                required.extend(schema_required.iter().filter_map(|key| key.as_str()));
+            }
+            additional = additional.or_else(|| schema.get("additionalProperties"));
+        }
+
+        for (key, value) in mapping {
+            let Some(key) = key.as_str() else {
+                self.error(
+                    path,
+                    false,
+                    format!("keys must be strings, found {}", kind_of(key)),
+                );
+                continue;
+            };
+            path.push(PathSegment::from(key));
+            match properties.iter().find(|(name, _)| *name == key) {
+                Some((_, schema)) => self.validate(value, schema, path),
+                None => match additional {
+                    Some(schema) if schema.is_object() => self.validate(value, schema, path),
+                    Some(serde_json::Value::Bool(true)) => (),
+                    None if properties.is_empty() => (),
+                    _ => {
+                        let suggestion =
+                            util::get_suggestion(key, properties.iter().map(|(name, _)| *name));
+                        self.error(path, true, format!("unknown key `{}`{}", key, suggestion));
+                    }
+                },
+            }
+            path.pop();
+        }
+        for key in required {
+            if !mapping.contains_key(key) {
+                self.error(path, false, format!("missing key `{}`", key));
+            }
+        }
+    }
+
+    fn validate_sequence(
+        &mut self,
+        items: &[Value],
+        schema: &'s serde_json::Value,
+        path: &mut Vec<PathSegment>,
+    ) {
+        let min_items = schema.get("minItems").and_then(|n| n.as_u64());
+        let max_items = schema.get("maxItems").and_then(|n| n.as_u64());
+        let len = items.len() as u64;
+        if min_items.is_some_and(|min| len < min) || max_items.is_some_and(|max| len > max) {
+            let expected = match (min_items, max_items) {
+                (Some(min), Some(max)) if min == max => min.to_string(),
+                (Some(min), Some(max)) => format!("{} to {}", min, max),
+                (Some(min), None) => format!("at least {}", min),
+                (None, _) => format!("at most {}", max_items.unwrap()),
+            };
+            self.error(
+                path,
+                false,
+                format!("expected {} items, found {}", expected, len),
+            );
+            return;
+        }
+        for (i, item) in items.iter().enumerate() {
+            let item_schema = match schema.get("items") {
+                Some(serde_json::Value::Array(tuple)) => tuple.get(i),
+                items => items,
+            };
+            if let Some(item_schema) = item_schema {
+                path.push(PathSegment::Index(i));
+                self.validate(item, item_schema, path);
+                path.pop();
+            }
+        }
+    }
+
+    /// Select the branch of `oneOf` or `anyOf` to validate a value against: the first branch
+    /// that the value matches, or otherwise the branch that it was most likely meant to match,
+    /// so that its errors can be reported. Returns `None` after reporting an error if there is
+    /// no such branch.
+    fn select_branch(
+        &mut self,
+        value: &Value,
+        branches: &'s [serde_json::Value],
+        path: &mut Vec<PathSegment>,
+    ) -> Option<&'s serde_json::Value> {
+        for branch in branches {
+            let mut validator = Validator {
+                definitions: self.definitions,
+                errors: Vec::new(),
+            };
+            validator.validate(value, branch, path);
+            if validator.errors.is_empty() {
+                return Some(branch);
+            }
+        }
+
+        // Branches that are told apart by a tag, such as the `plot` of a plot.
+        if let (Some(tag), Value::Mapping(mapping)) = (self.get_tag(branches), value) {
+            let tags: Vec<&'s serde_json::Value> = branches
+                .iter()
+                .map(|branch| &self.resolve(branch)["properties"][tag]["enum"][0])
+                .collect();
+            let Some(value) = mapping.get(tag) else {
+                self.error(path, false, format!("missing key `{}`", tag));
+                return None;
+            };
+            if let Some(i) = tags
+                .iter()
+                .position(|expected| yaml_equals_json(value, expected))
+            {
+                return Some(&branches[i]);
+            }
+            let tags: Vec<serde_json::Value> = tags.into_iter().cloned().collect();
+            path.push(PathSegment::from(tag));
+            self.error(path, false, unknown_value_message(value, &tags));
+            path.pop();
+            return None;
+        }
+
+        let matching = branches
+            .iter()
+            .find(|branch| self.accepts_type(branch, value));
+        if matching.is_none() {
+            let expected = self.describe_branches(branches, value);
+            self.error(
+                path,
+                false,
+                format!("expected {}, found {}", expected, kind_of(value)),
+            );
+        }
+        matching
+    }
+
+    /// Get the property that tells the branches apart, if they all have a property with a single
+    /// allowed value.
+    fn get_tag(&self, branches: &'s [serde_json::Value]) -> Option<&'s str> {
+        let first = self
+            .resolve(branches.first()?)
+            .get("properties")?
+            .as_object()?;
+        first.keys().map(String::as_str).find(|key| {
+            branches.iter().all(|branch| {
+                let values = &self.resolve(branch)["properties"][*key]["enum"];
+                values.as_array().is_some_and(|values| values.len() == 1)
+            })
+        })
+    }
+
+    /// Whether a schema accepts values of the type of a value.
+    fn accepts_type(&self, schema: &'s serde_json::Value, value: &Value) -> bool {
+        let schema = self.resolve(schema);
+        if let Some(types) = schema.get("type") {
+            return type_matches(types, untag(value));
+        }
+        match schema.get("oneOf").or_else(|| schema.get("anyOf")) {
+            Some(serde_json::Value::Array(branches)) => branches
+                .iter()
+                .any(|branch| self.accepts_type(branch, value)),
+            _ => true,
+        }
+    }
+
+    /// Describe the types that a schema accepts, for an error about a value of another type.
+    fn describe(&self, schema: &'s serde_json::Value, value: &Value) -> String {
+        let schema = self.resolve(schema);
+        if let Some(types) = schema.get("type") {
+            return describe_types(types, value);
+        }
+        match schema.get("oneOf").or_else(|| schema.get("anyOf")) {
+            Some(serde_json::Value::Array(branches)) => self.describe_branches(branches, value),
+            _ => "any value".to_string(),
+        }
+    }
+
+    fn describe_branches(&self, branches: &'s [serde_json::Value], value: &Value) -> String {
+        let descriptions: Vec<String> = branches
+            .iter()
+            .map(|branch| self.describe(branch, value))
+            .filter(|description| !description.is_empty())
+            .collect();
+        descriptions.join(" or ")
+    }
+
+    /// Follow a `$ref` to the definition that it refers to.
+    fn resolve(&self, schema: &'s serde_json::Value) -> &'s serde_json::Value {
+        match schema.get("$ref").and_then(|reference| reference.as_str()) {
+            Some(reference) => {
+                let name = reference.trim_start_matches("#/definitions/");
+                self.resolve(&self.definitions[name])
+            }
+            None => schema,
+        }
+    }
+
+    fn error(&mut self, path: &[PathSegment], key: bool, message: String) {
+        self.errors.push(ConfigError {
+            path: path.to_vec(),
+            key,
+            message,
+        });
+    }
+}
+
+fn untag(value: &Value) -> &Value {
+    match value {
+        Value::Tagged(tagged) => untag(&tagged.value),
+        value => value,
+    }
+}
+
+/// Get the JSON schema type of a value, to describe it in errors.
+fn kind_of(value: &Value) -> &'static str {
+    match untag(value) {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Sequence(_) => "array",
+        Value::Mapping(_) => "object",
+        Value::Tagged(_) => unreachable!(),
+    }
+}
+
+/// Whether a value has one of the types of a schema, which are a type or a list of types.
+fn type_matches(types: &serde_json::Value, value: &Value) -> bool {
+    let kind = kind_of(value);
+    let matches = |expected: &serde_json::Value| match expected.as_str() {
+        Some("number") => kind == "number" || kind == "integer",
+        Some(expected) => expected == kind,
+        None => false,
+    };
+    match types {
+        serde_json::Value::Array(types) => types.iter().any(matches),
+        expected => matches(expected),
+    }
+}
+
+/// Describe the types of a schema, leaving out `null` unless the value is null, since a missing
+/// value is rarely what was meant.
+fn describe_types(types: &serde_json::Value, value: &Value) -> String {
+    let types: Vec<&str> = match types {
+        serde_json::Value::Array(types) => types.iter().filter_map(|t| t.as_str()).collect(),
+        expected => expected.as_str().into_iter().collect(),
+    };
+    types
+        .into_iter()
+        .filter(|t| *t != "null" || value.is_null())
+        .collect::<Vec<&str>>()
+        .join(" or ")
+}
+
+fn yaml_equals_json(value: &Value, expected: &serde_json::Value) -> bool {
+    match (untag(value), expected) {
+        (Value::String(value), serde_json::Value::String(expected)) => value == expected,
+        (Value::Bool(value), serde_json::Value::Bool(expected)) => value == expected,
+        (Value::Number(value), serde_json::Value::Number(expected)) => {
+            value.as_f64() == expected.as_f64()
+        }
+        (Value::Null, serde_json::Value::Null) => true,
+        _ => false,
+    }
+}
+
+fn unknown_value_message(value: &Value, values: &[serde_json::Value]) -> String {
+    let values: Vec<String> = values
+        .iter()
+        .map(|value| match value {
+            serde_json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        })
+        .collect();
+    let value = match untag(value) {
+        Value::String(value) => value.clone(),
+        value => serde_yaml::to_string(value).unwrap().trim_end().to_string(),
+    };
+    let suggestion = util::get_suggestion(&value, values.iter().map(String::as_str));
+    let expected: Vec<String> = values.iter().map(|value| format!("`{}`", value)).collect();
+    match suggestion.is_empty() {
+        true => format!(
+            "unknown value `{}`, expected {}",
+            value,
+            expected.join(", ")
+        ),
+        false => format!("unknown value `{}`{}", value, suggestion),
+    }
+}
+
+fn lowercase_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Find the line and column of a value in a YAML document.
+///
+/// # Arguments
+/// * `contents` - The YAML document.
+/// * `path` - The path of the value.
+/// * `key` - Whether to find the key at the end of the path rather than its value.
+///
+/// # Returns
+/// The line and column, both starting at 1, or `None` if the document has no such value.
+pub(crate) fn locate(contents: &str, path: &[PathSegment], key: bool) -> Option<(usize, usize)> {
+    let deserializer = serde_yaml::Deserializer::from_str(contents);
+    let error = match (Locator { path, key }).deserialize(deserializer) {
+        Ok(()) => return None,
+        Err(error) => error,
+    };
+    // The message also has the path and the location of the value.
+    if !error.to_string().contains(FOUND) {
+        return None;
+    }
+    let location = error.location()?;
+    Some((location.line(), location.column()))
+}
+
+/// Walks a YAML document to a path and fails with `FOUND` there, which makes the deserializer
+/// tell where it is.
+struct Locator<'p> {
+    path: &'p [PathSegment],
+    key: bool,
+}
+
+impl<'de> DeserializeSeed<'de> for Locator<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if self.path.is_empty() {
+            deserializer.deserialize_any(Found)
+        } else {
+            deserializer.deserialize_any(self)
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for Locator<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> std::result::Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let PathSegment::Key(target) = &self.path[0] else {
+            return Ok(());
+        };
+        let rest = &self.path[1..];
+        let seed = KeySeed {
+            target,
+            found: self.key && rest.is_empty(),
+        };
+        while let Some(matches) = map.next_key_seed(seed)? {
+            if matches {
+                return map.next_value_seed(Locator {
+                    path: rest,
+                    key: self.key,
+                });
+            }
+            map.next_value::<IgnoredAny>()?;
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let PathSegment::Index(index) = self.path[0] else {
+            return Ok(());
+        };
+        for _ in 0..index {
+            if seq.next_element::<IgnoredAny>()?.is_none() {
+                return Ok(());
+            }
+        }
+        seq.next_element_seed(Locator {
+            path: &self.path[1..],
+            key: self.key,
+        })?;
+        Ok(())
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> std::result::Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> std::result::Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> std::result::Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> std::result::Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> std::result::Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> std::result::Result<(), E> {
+        Ok(())
+    }
+}
+
+/// Reads a key of a mapping and tells whether it is the key on the path. Fails with `FOUND` if
+/// it is the key to find.
+#[derive(Clone, Copy)]
+struct KeySeed<'p> {
+    target: &'p str,
+    found: bool,
+}
+
+impl KeySeed<'_> {
+    fn check<E: de::Error>(self, key: &str) -> std::result::Result<bool, E> {
+        match key == self.target {
+            true if self.found => Err(E::custom(FOUND)),
+            matches => Ok(matches),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for KeySeed<'_> {
+    type Value = bool;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<bool, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeySeed<'_> {
+    type Value = bool;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a key")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> std::result::Result<bool, E> {
+        self.check(value)
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> std::result::Result<bool, E> {
+        self.check(&value.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> std::result::Result<bool, E> {
+        self.check(&value.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> std::result::Result<bool, E> {
+        self.check(&value.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> std::result::Result<bool, E> {
+        self.check(&value.to_string())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> std::result::Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> std::result::Result<bool, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+        Ok(false)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<bool, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(false)
+    }
+}
+
+/// Fails with `FOUND` for any value.
+struct Found;
+
+impl<'de> Visitor<'de> for Found {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, _: A) -> std::result::Result<(), A::Error> {
+        Err(de::Error::custom(FOUND))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, _: A) -> std::result::Result<(), A::Error> {
+        Err(de::Error::custom(FOUND))
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> std::result::Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> std::result::Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> std::result::Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> std::result::Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> std::result::Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> std::result::Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_file() {
+        // plots_schema.json is generated from the configuration structs, for editors to use.
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("plots_schema.json");
+        let schema = serde_json::to_string_pretty(&get_schema()).unwrap() + "\n";
+        if std::env::var_os("UPDATE_SCHEMA").is_some() {
+            std::fs::write(&path, &schema).unwrap();
+        }
+        let existing = std::fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            existing == schema,
+            "plots_schema.json is out of date, update it with UPDATE_SCHEMA=1 cargo test"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let yaml = "\
+source: trace.sqlite
+plots:
+  - name: View
+    plot: time_scatter
+    table: item_view_executor_execute
+    column: wallclock_time_ns
+    aggregation:
+      mode: mean
+      sise: 1h
+  - name: Histogram
+    plot: histogramm
+";
+        let config: Value = serde_yaml::from_str(yaml).unwrap();
+        let errors: Vec<String> = validate(&config, &get_schema())
+            .iter()
+            .map(ConfigError::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "plots[0].aggregation.sise: unknown key `sise`, did you mean `size`?",
+                "plots[0].aggregation: missing key `size`",
+                "plots[1].plot: unknown value `histogramm`, did you mean `histogram`?",
+            ]
+        );
+
+        let path = [
+            PathSegment::from("plots"),
+            PathSegment::Index(0),
+            PathSegment::from("aggregation"),
+            PathSegment::from("sise"),
+        ];
+        assert_eq!(locate(yaml, &path, true), Some((9, 7)));
+        assert_eq!(locate(yaml, &path, false), Some((9, 13)));
+        assert_eq!(locate(yaml, &path[..2], false), Some((3, 5)));
+        assert_eq!(locate(yaml, &[PathSegment::from("layout")], false), None);
+    }
+
+    #[test]
+    fn test_validate_variants() {
+        let yaml = "\
+source: trace.sqlite
+plots:
+  - name: XY
+    plot: xy
+    table: item_view_executor_execute
+    x: wallclock_time_ns
+    y: cpu_time_ns
+    x_bins: many
+    y_bin: 10
+  - name: View
+    plot: time_scatter
+    table: item_view_executor_execute
+    column: wallclock_time_ns
+    filter:
+      workhours: true
+      calendar:
+        timezon: UTC
+    smoothing:
+      method: mean
+      window: 1x
+  - name: Missing
+    table: item_view_executor_execute
+  - name: Histogram
+    plot: histogram
+    table: item_view_executor_execute
+    column: wallclock_time_ns
+    view_id: 1
+  - name: Valid
+    plot: time_scatter
+    table: item_view_executor_execute
+    column: wallclock_time_ns
+    filter:
+      workhours: true
+      calendar: stockholm
+    smoothing:
+      method: mean
+      window: 5
+  - name: Valid
+    plot: overlap
+    view_id: 1
+    x_bins: 10
+    filter:
+      workhours: true
+      calendar:
+        timezone: Europe/Stockholm
+    smoothing:
+      method: mean
+      window: 1h
+";
+        let config: Value = serde_yaml::from_str(yaml).unwrap();
+        let errors: Vec<String> = validate(&config, &get_schema())
+            .iter()
+            .map(ConfigError::to_string)
+            .collect();
+        // The flattened bins of the xy plot, both branches of the untagged calendar, the integer
+        // or time period of the smoothing window, and the keys of the other plot types.
+        assert_eq!(
+            errors,
+            vec![
+                "plots[0].x_bins: expected integer, found string",
+                "plots[0].y_bin: unknown key `y_bin`, did you mean `y_bins`?",
+                "plots[1].filter.calendar.timezon: unknown key `timezon`, did you mean `timezone`?",
+                "plots[1].smoothing.window: invalid value `1x`, expected a quantity and a time \
+                 unit, such as 5m",
+                "plots[2]: missing key `plot`",
+                "plots[3].view_id: unknown key `view_id`",
+            ]
+        );
+
+        let path = [
+            PathSegment::from("plots"),
+            PathSegment::Index(0),
+            PathSegment::from("x_bins"),
+        ];
+        assert_eq!(locate(yaml, &path, false), Some((8, 13)));
+    }
+}
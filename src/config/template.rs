@@ -0,0 +1,534 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use regex::{Captures, Regex};
+use serde_yaml::{Mapping, Value};
+
+use super::schema::PathSegment;
+use crate::util::Result;
+
+/*
+ * Templating for the YAML configuration. This works on the parsed YAML, before it is
+ * deserialized into the configuration structs, so that the structs don't need to know about it:
+ *
+ * - `variables` at the top level defines variables, which can be overridden from the command
+ *   line, and which are substituted for `${name}` in any string.
+ * - `include` at the top level merges other configuration files into this one, e.g. to share the
+ *   source and layout between configurations. In the list of plots, `include` inserts the plots
+ *   from another file, which can be used as templates.
+ * - `foreach` in a plot repeats the plot for each value of one or more variables, given either as
+ *   a list or as an SQL query on the source database.
+ */
+
+lazy_static! {
+    static ref VARIABLE_REGEX: Regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    static ref NAME_REGEX: Regex = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+}
+
+/// The maximum depth of nested includes, to catch configurations that include themselves.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+type Variables = BTreeMap<String, Value>;
+
+/// Where a plot of the expanded configuration is defined, as files and the paths of the plot in
+/// them. A plot that includes a template comes before the plots of the template, since its keys
+/// override theirs.
+pub(crate) type PlotOrigin = Vec<(PathBuf, Vec<PathSegment>)>;
+
+/// A configuration with its templating expanded.
+pub(crate) struct ExpandedConfig {
+    pub config: Value,
+    /// The files that the configuration was read from, the configuration file first.
+    pub files: Vec<PathBuf>,
+    /// Where each plot is defined, to locate errors in the plots.
+    pub plot_origins: Vec<PlotOrigin>,
+}
+
+/// Read a configuration file and expand its templating.
+///
+/// # Arguments
+/// * `path` - The configuration file.
+/// * `overrides` - Variables given on the command line, as name and value. The values are parsed
+///   as YAML, so that numbers stay numbers. They take precedence over the variables in the file.
+///
+/// # Returns
+/// The configuration with includes, `foreach` and variables expanded, ready to be deserialized.
+pub(crate) fn expand_template(
+    path: &Path,
+    overrides: &[(String, String)],
+) -> Result<ExpandedConfig> {
+    let mut files = Vec::new();
+    let (mut config, origins) = read_included_file(path, 0, &mut files)?;
+    let Value::Mapping(ref mut root) = config else {
+        return Err("The configuration must be a mapping".into());
+    };
+
+    let mut variables = Variables::new();
+    if let Some(defined) = root.remove("variables") {
+        let Value::Mapping(defined) = defined else {
+            return Err("The variables must be a mapping from name to value".into());
+        };
+        for (name, value) in defined {
+            variables.insert(get_name(&name)?, value);
+        }
+    }
+    for (name, value) in overrides {
+        if !NAME_REGEX.is_match(name) {
+            return Err(format!("Invalid variable name {}", name).into());
+        }
+        variables.insert(name.clone(), serde_yaml::from_str(value)?);
+    }
+
+    // Everything except the plots can use the variables right away. The plots are expanded
+    // afterwards, since `foreach` may need to query the source.
+    let plots = root.remove("plots");
+    for (_, value) in root.iter_mut() {
+        *value = substitute(value, &variables)?;
+    }
+    let source = match root.get("source") {
+        Some(Value::String(source)) => Some(path.parent().unwrap().join(source)),
+        _ => None,
+    };
+    let mut plot_origins = Vec::new();
+    if let Some(plots) = plots {
+        let base_dir = path.parent().unwrap();
+        let mut expander = PlotExpander { source, conn: None };
+        let plots =
+            expander.expand_plots(plots, &origins, base_dir, &variables, 0, &mut plot_origins)?;
+        root.insert("plots".into(), Value::Sequence(plots));
+    }
+    Ok(ExpandedConfig {
+        config,
+        files,
+        plot_origins,
+    })
+}
+
+/// Read a configuration file and merge the files it includes at the top level into it.
+///
+/// # Arguments
+/// * `path` - The configuration file.
+/// * `depth` - The depth of nested includes.
+/// * `files` - The files that are read are added to this.
+///
+/// # Returns
+/// The merged configuration, and where each of its plots is defined.
+fn read_included_file(
+    path: &Path,
+    depth: usize,
+    files: &mut Vec<PathBuf>,
+) -> Result<(Value, Vec<PlotOrigin>)> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(format!(
+            "Includes nested too deeply at {}, does a file include itself?",
+            path.display()
+        )
+        .into());
+    }
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+    let mut config: Value = serde_yaml::from_str(&contents)
+        .map_err(|e| format!("Error parsing {}: {}", path.display(), e))?;
+    files.push(path.to_path_buf());
+    let Value::Mapping(ref mut mapping) = config else {
+        return Ok((config, Vec::new()));
+    };
+    let origins = get_plot_origins(path, mapping.get("plots"), &[PathSegment::from("plots")]);
+    let Some(includes) = mapping.remove("include") else {
+        return Ok((config, origins));
+    };
+
+    let mut merged = Mapping::new();
+    let mut merged_origins = Vec::new();
+    for include in get_paths(&includes, path.parent().unwrap())? {
+        match read_included_file(&include, depth + 1, files)? {
+            (Value::Mapping(included), included_origins) => {
+                merge_plot_origins(&merged, &included, &mut merged_origins, included_origins);
+                merge_configuration(&mut merged, included);
+            }
+            _ => {
+                return Err(
+                    format!("The included file {} is not a mapping", include.display()).into(),
+                )
+            }
+        }
+    }
+    let mapping = std::mem::take(mapping);
+    merge_plot_origins(&merged, &mapping, &mut merged_origins, origins);
+    merge_configuration(&mut merged, mapping);
+    Ok((Value::Mapping(merged), merged_origins))
+}
+
+/// Get where each plot in a list of plots in a file is defined.
+///
+/// # Arguments
+/// * `path` - The file.
+/// * `plots` - The plots, as a list or a single plot.
+/// * `prefix` - The path of the plots in the file.
+fn get_plot_origins(path: &Path, plots: Option<&Value>, prefix: &[PathSegment]) -> Vec<PlotOrigin> {
+    match plots {
+        Some(Value::Sequence(plots)) => (0..plots.len())
+            .map(|i| {
+                let mut plot_path = prefix.to_vec();
+                plot_path.push(PathSegment::Index(i));
+                vec![(path.to_path_buf(), plot_path)]
+            })
+            .collect(),
+        Some(Value::Mapping(_)) => vec![vec![(path.to_path_buf(), prefix.to_vec())]],
+        _ => Vec::new(),
+    }
+}
+
+/// Update where the plots are defined for merging a configuration into another, see
+/// `merge_configuration`.
+fn merge_plot_origins(
+    base: &Mapping,
+    overlay: &Mapping,
+    origins: &mut Vec<PlotOrigin>,
+    overlay_origins: Vec<PlotOrigin>,
+) {
+    match (base.get("plots"), overlay.get("plots")) {
+        (Some(Value::Sequence(_)), Some(Value::Sequence(_))) => origins.extend(overlay_origins),
+        (_, Some(_)) => *origins = overlay_origins,
+        (_, None) => (),
+    }
+}
+
+/// Merge a configuration into another. Keys in `overlay` replace those in `base`, except that
+/// plots and annotations are appended, and variables, the layout and calendars are merged key by
+/// key.
+fn merge_configuration(base: &mut Mapping, overlay: Mapping) {
+    for (key, value) in overlay {
+        match (key.as_str(), base.get_mut(&key), value) {
+            (
+                Some("plots" | "annotations"),
+                Some(Value::Sequence(items)),
+                Value::Sequence(more),
+            ) => items.extend(more),
+            (Some("variables" | "layout" | "calendars"), Some(existing), value) => {
+                merge_values(existing, value)
+            }
+            (_, _, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Merge a YAML value into another, recursing into mappings. Anything else in `overlay`
+/// replaces what is in `base`.
+fn merge_values(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+struct PlotExpander {
+    /// The source database, which is opened when a `foreach` first needs it.
+    source: Option<PathBuf>,
+    conn: Option<rusqlite::Connection>,
+}
+
+impl PlotExpander {
+    /// Expand the list of plots: insert included plots, repeat plots with `foreach` and
+    /// substitute the variables.
+    ///
+    /// # Arguments
+    /// * `plots` - The list of plots.
+    /// * `origins` - Where each plot is defined.
+    /// * `base_dir` - The directory that included files are relative to.
+    /// * `variables` - The variables defined where the plots are used.
+    /// * `depth` - The depth of nested includes.
+    /// * `expanded_origins` - Gets where each of the expanded plots is defined.
+    fn expand_plots(
+        &mut self,
+        plots: Value,
+        origins: &[PlotOrigin],
+        base_dir: &Path,
+        variables: &Variables,
+        depth: usize,
+        expanded_origins: &mut Vec<PlotOrigin>,
+    ) -> Result<Vec<Value>> {
+        let plots = match plots {
+            Value::Sequence(plots) => plots,
+            // A template file can contain a single plot.
+            plot @ Value::Mapping(_) => vec![plot],
+            _ => return Err("The plots must be a list".into()),
+        };
+        let mut expanded: Vec<Value> = Vec::with_capacity(plots.len());
+        for (i, plot) in plots.into_iter().enumerate() {
+            let origin = origins.get(i).cloned().unwrap_or_default();
+            let Value::Mapping(mut plot) = plot else {
+                return Err("Each plot must be a mapping".into());
+            };
+            let include = plot.remove("include");
+            let foreach = plot.remove("foreach");
+            let mut plot_variables = variables.clone();
+            if let Some(local) = plot.remove("variables") {
+                let Value::Mapping(local) = substitute(&local, variables)? else {
+                    return Err("The variables of a plot must be a mapping".into());
+                };
+                for (name, value) in local {
+                    plot_variables.insert(get_name(&name)?, value);
+                }
+            }
+
+            for binding in self.get_bindings(foreach, &plot_variables)? {
+                let mut variables = plot_variables.clone();
+                variables.extend(binding);
+                match &include {
+                    Some(include) => {
+                        if depth > MAX_INCLUDE_DEPTH {
+                            return Err(
+                                "Plot includes nested too deeply, does a file include itself?"
+                                    .into(),
+                            );
+                        }
+                        for include in get_paths(include, base_dir)? {
+                            let (template, template_origins) = read_plot_template(&include)?;
+                            let mut template_plot_origins = Vec::new();
+                            let template_plots = self.expand_plots(
+                                template,
+                                &template_origins,
+                                include.parent().unwrap(),
+                                &variables,
+                                depth + 1,
+                                &mut template_plot_origins,
+                            )?;
+                            for (template_plot, template_origin) in
+                                template_plots.into_iter().zip(template_plot_origins)
+                            {
+                                // The keys next to the include override those in the template.
+                                let mut template_plot = template_plot;
+                                merge_values(
+                                    &mut template_plot,
+                                    substitute(&Value::Mapping(plot.clone()), &variables)?,
+                                );
+                                expanded.push(template_plot);
+                                let mut plot_origin = origin.clone();
+                                plot_origin.extend(template_origin);
+                                expanded_origins.push(plot_origin);
+                            }
+                        }
+                    }
+                    None => {
+                        expanded.push(substitute(&Value::Mapping(plot.clone()), &variables)?);
+                        expanded_origins.push(origin.clone());
+                    }
+                }
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Get the values of the variables for each repetition of a plot with `foreach`. With several
+    /// variables, the plot is repeated for every combination of their values.
+    fn get_bindings(
+        &mut self,
+        foreach: Option<Value>,
+        variables: &Variables,
+    ) -> Result<Vec<Variables>> {
+        let mut bindings: Vec<Variables> = vec![Variables::new()];
+        let Some(foreach) = foreach else {
+            return Ok(bindings);
+        };
+        let Value::Mapping(foreach) = foreach else {
+            return Err("foreach must be a mapping from variable name to values".into());
+        };
+        for (name, values) in foreach {
+            let name = get_name(&name)?;
+            let values = match substitute(&values, variables)? {
+                Value::Sequence(values) => values,
+                Value::Mapping(query) => match query.get("sql") {
+                    Some(Value::String(sql)) => self.query_values(sql)?,
+                    _ => {
+                        return Err(
+                            format!("foreach {} must have a list of values or sql", name).into(),
+                        )
+                    }
+                },
+                value => vec![value],
+            };
+            bindings = bindings
+                .iter()
+                .flat_map(|binding| {
+                    values.iter().map(|value| {
+                        let mut binding = binding.clone();
+                        binding.insert(name.clone(), value.clone());
+                        binding
+                    })
+                })
+                .collect();
+        }
+        Ok(bindings)
+    }
+
+    /// Run a query on the source database and return the first column of each row.
+    fn query_values(&mut self, sql: &str) -> Result<Vec<Value>> {
+        if self.conn.is_none() {
+            let Some(source) = &self.source else {
+                return Err("foreach with sql requires the source to be set".into());
+            };
+            let conn = rusqlite::Connection::open_with_flags(
+                source,
+                rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+            )
+            .map_err(|e| format!("Error opening database {}: {}", source.display(), e))?;
+            self.conn = Some(conn);
+        }
+        let conn = self.conn.as_ref().unwrap();
+        eprintln!("Executing query: {}", sql);
+        let mut stmt = conn
+            .prepare(sql)
+            .map_err(|e| format!("Error in foreach query {}: {}", sql, e))?;
+        let mut rows = stmt.query([])?;
+        let mut values: Vec<Value> = Vec::new();
+        while let Some(row) = rows.next()? {
+            values.push(match row.get_ref(0)? {
+                rusqlite::types::ValueRef::Null => Value::Null,
+                rusqlite::types::ValueRef::Integer(i) => i.into(),
+                rusqlite::types::ValueRef::Real(f) => f.into(),
+                rusqlite::types::ValueRef::Text(t) => String::from_utf8_lossy(t).into(),
+                rusqlite::types::ValueRef::Blob(_) => {
+                    return Err(format!("foreach query {} returned a blob", sql).into())
+                }
+            });
+        }
+        Ok(values)
+    }
+}
+
+/// Read a file with plot templates: a single plot, a list of plots or a configuration with
+/// `plots`. Returns the plots and where each of them is defined.
+fn read_plot_template(path: &Path) -> Result<(Value, Vec<PlotOrigin>)> {
+    match read_included_file(path, 0, &mut Vec::new())? {
+        (Value::Mapping(mut mapping), origins) if mapping.contains_key("plots") => {
+            Ok((mapping.remove("plots").unwrap(), origins))
+        }
+        (template, _) => {
+            let origins = get_plot_origins(path, Some(&template), &[]);
+            Ok((template, origins))
+        }
+    }
+}
+
+/// Get the paths of the included files, relative to the directory of the file that includes them.
+fn get_paths(include: &Value, dir: &Path) -> Result<Vec<PathBuf>> {
+    let paths = match include {
+        Value::String(path) => vec![dir.join(path)],
+        Value::Sequence(paths) => paths
+            .iter()
+            .map(|path| match path {
+                Value::String(path) => Ok(dir.join(path)),
+                _ => Err("include must be a file name or a list of file names".into()),
+            })
+            .collect::<Result<Vec<PathBuf>>>()?,
+        _ => return Err("include must be a file name or a list of file names".into()),
+    };
+    Ok(paths)
+}
+
+fn get_name(name: &Value) -> Result<String> {
+    match name {
+        Value::String(name) => Ok(name.clone()),
+        _ => Err(format!("Invalid variable name {:?}", name).into()),
+    }
+}
+
+/// Substitute the variables for `${name}` in all strings of a YAML value. A string that only
+/// consists of a variable is replaced by its value, so that e.g. numbers can be given as
+/// variables.
+fn substitute(value: &Value, variables: &Variables) -> Result<Value> {
+    Ok(match value {
+        Value::String(s) => {
+            if let Some(captures) = VARIABLE_REGEX.captures(s) {
+                if captures.get(0).unwrap().as_str() == s {
+                    return get_variable(&captures[1], variables).cloned();
+                }
+            }
+            let mut error: Option<String> = None;
+            let substituted =
+                VARIABLE_REGEX.replace_all(s, |captures: &Captures| {
+                    match get_variable(&captures[1], variables) {
+                        Ok(Value::String(value)) => value.clone(),
+                        Ok(Value::Number(value)) => value.to_string(),
+                        Ok(Value::Bool(value)) => value.to_string(),
+                        Ok(_) => {
+                            error = Some(format!(
+                                "Variable {} must be a string, number or boolean to be used in {}",
+                                &captures[1], s
+                            ));
+                            String::new()
+                        }
+                        Err(e) => {
+                            error = Some(e.to_string());
+                            String::new()
+                        }
+                    }
+                });
+            if let Some(error) = error {
+                return Err(error.into());
+            }
+            Value::String(substituted.into_owned())
+        }
+        Value::Sequence(values) => Value::Sequence(
+            values
+                .iter()
+                .map(|value| substitute(value, variables))
+                .collect::<Result<Vec<Value>>>()?,
+        ),
+        Value::Mapping(mapping) => {
+            let mut substituted = Mapping::with_capacity(mapping.len());
+            for (key, value) in mapping {
+                substituted.insert(key.clone(), substitute(value, variables)?);
+            }
+            Value::Mapping(substituted)
+        }
+        Value::Tagged(tagged) => Value::Tagged(Box::new(serde_yaml::value::TaggedValue {
+            tag: tagged.tag.clone(),
+            value: substitute(&tagged.value, variables)?,
+        })),
+        value => value.clone(),
+    })
+}
+
+fn get_variable<'a>(name: &str, variables: &'a Variables) -> Result<&'a Value> {
+    variables.get(name).ok_or_else(|| {
+        format!(
+            "Undefined variable {}, define it under variables or with --set {}=<value>",
+            name, name
+        )
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute() {
+        let mut variables = Variables::new();
+        variables.insert("view".to_string(), 123.into());
+        variables.insert("name".to_string(), "Search".into());
+        let value: Value = serde_yaml::from_str(
+            "{name: 'View ${name} (${view})', id: '${view}', where: ['view_id = ${view}']}",
+        )
+        .unwrap();
+        let expected: Value =
+            serde_yaml::from_str("{name: 'View Search (123)', id: 123, where: [view_id = 123]}")
+                .unwrap();
+        assert_eq!(substitute(&value, &variables).unwrap(), expected);
+        assert!(substitute(&"${missing}".into(), &variables).is_err());
+    }
+}
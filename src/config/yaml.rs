@@ -1,11 +1,14 @@
+use std::collections::HashMap;
 use std::path::Path;
 
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::util::Result;
+use super::schema::ConfigLocator;
+use crate::util::{self, Result};
 
-/**
+/*
  * The goal for most YAML configuration is really to just be a passthrough so
  * people can use all the features of Plotly and rely on the Plotly
  * documentation for configuration options. This is why we use serde to
@@ -28,20 +31,40 @@ use crate::util::Result;
  * The root configuration struct for the YAML configuration file. This is the
  * top level struct that we will deserialize the YAML file into.
  */
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct Root {
     pub source: String,
+    pub output: Option<String>,
     pub layout: Option<Layout>,
+    pub calendars: Option<HashMap<String, Calendar>>,
+    pub annotations: Option<Vec<Annotation>>,
     pub plots: Vec<PlotConfig>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Annotations mark events such as upgrades on every subplot with a time axis, as a vertical line
+/// at `start`, or a shaded range from `start` to `end`, with an optional label. An entry is either
+/// a single annotation, or reads annotations from a CSV file or an SQL query, with the columns
+/// `start` and optionally `end` and `label`. The times are time expressions, or timestamps in
+/// nanoseconds since the Unix epoch.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Annotation {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub label: Option<String>,
+    /// A CSV file with a header row, relative to the configuration file.
+    pub csv: Option<String>,
+    pub sql: Option<String>,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct Layout {
     pub title: Option<Title>,
     #[serde(rename = "showlegend")]
     show_legend: Option<bool>,
     pub width: Option<i32>,
     pub height: Option<i32>,
+    grid: Option<Grid>,
 
     #[serde(rename = "xaxis")]
     x_axis: Option<Axis>,
@@ -93,7 +116,7 @@ pub struct Layout {
     z_axis8: Option<Axis>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct Axis {
     visible: Option<bool>,
     //color: Option<Box<dyn Color>>,
@@ -196,11 +219,10 @@ pub struct Axis {
     //divider_color: Option<Box<dyn Color>>,
     //#[serde(rename = "dividerwidth")]
     //divider_width: Option<usize>,
-    //anchor: Option<String>,
+    anchor: Option<String>,
     side: Option<AxisSide>,
     overlaying: Option<String>,
-    //#[field_setter(skip)]
-    //domain: Option<Vec<f64>>,
+    domain: Option<Vec<f64>>,
     //position: Option<f64>,
     //#[serde(rename = "rangeslider")]
     //range_slider: Option<RangeSlider>,
@@ -209,7 +231,39 @@ pub struct Axis {
     //calendar: Option<Calendar>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// A grid of subplots. Each cell of the grid gets its own pair of axes, numbered from the top
+/// left: the first cell uses `x` and `y`, the second `x2` and `y2`, and so on. With the `coupled`
+/// pattern, all cells in a column share the x-axis and all cells in a row share the y-axis, so
+/// cell n of a single-column grid uses `x` and `yn`.
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct Grid {
+    rows: Option<usize>,
+    columns: Option<usize>,
+    pattern: Option<GridPattern>,
+    #[serde(rename = "roworder")]
+    row_order: Option<RowOrder>,
+    #[serde(rename = "xgap")]
+    x_gap: Option<f64>,
+    #[serde(rename = "ygap")]
+    y_gap: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GridPattern {
+    Independent,
+    Coupled,
+}
+
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
+pub enum RowOrder {
+    #[serde(rename = "top to bottom")]
+    TopToBottom,
+    #[serde(rename = "bottom to top")]
+    BottomToTop,
+}
+
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum RangeMode {
     Normal,
@@ -270,8 +324,8 @@ impl<'de> Deserialize<'de> for Title {
             {
                 let mut text = None;
 
-                while let Some(key) = map.next_key()? {
-                    match key {
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
                         "text" => {
                             text = Some(map.next_value()?);
                         }
@@ -290,7 +344,32 @@ impl<'de> Deserialize<'de> for Title {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl JsonSchema for Title {
+    fn schema_name() -> String {
+        "Title".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        json_schema(serde_json::json!({
+            "description": "A title string, or a title struct.",
+            "anyOf": [
+                { "type": "string" },
+                {
+                    "type": "object",
+                    "required": ["text"],
+                    "properties": { "text": { "type": "string" } },
+                },
+            ],
+        }))
+    }
+}
+
+/// Make the schema of a type that implements `Deserialize` by hand from its JSON.
+fn json_schema(schema: serde_json::Value) -> schemars::schema::Schema {
+    serde_json::from_value(schema).expect("invalid JSON schema")
+}
+
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum AxisType {
     #[serde(rename = "-")]
@@ -302,7 +381,7 @@ pub enum AxisType {
     MultiCategory,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum AxisSide {
     Top,
@@ -322,7 +401,7 @@ impl From<&AxisSide> for plotly::common::AxisSide {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct PlotConfig {
     #[serde(flatten)]
     pub plot_common: PlotCommon,
@@ -330,11 +409,15 @@ pub struct PlotConfig {
     pub plot_variant: PlotVariant,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct PlotCommon {
     pub name: String,
     pub filter: Option<Filter>,
     pub aggregation: Option<Aggregation>,
+    pub transform: Option<Transform>,
+    pub smoothing: Option<Smoothing>,
+    #[serde(rename = "changepoints")]
+    pub change_points: Option<ChangePoints>,
     pub visible: Option<Visible>,
     //pub show_legend: Option<bool>,
     //pub legend_group: Option<String>,
@@ -382,39 +465,221 @@ impl PlotCommon {
             mode: None,
             filter: None,
             aggregation: None,
+            transform: None,
+            smoothing: None,
+            change_points: None,
+        }
+    }
+
+    /// The time zone of the hours and days that the plot groups samples by: the time zone of the
+    /// aggregation, or else that of the work hours if they are filtered on, so that the groups
+    /// line up with the filter, or else UTC.
+    pub fn timezone(&self) -> Timezone {
+        if let Some(timezone) = self.aggregation.as_ref().and_then(|a| a.timezone) {
+            return timezone;
+        }
+        match &self.filter {
+            Some(filter) if filter.work_hours == Some(true) => match &filter.calendar {
+                Some(CalendarReference::Calendar(calendar)) => {
+                    calendar.timezone.unwrap_or(Timezone::Local)
+                }
+                _ => Timezone::Local,
+            },
+            _ => Timezone::Named(chrono_tz::UTC),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(tag = "plot", rename_all = "snake_case")]
 pub enum PlotVariant {
     TimeScatter(TimeScatterPlot),
     CountScatter(CountScatterPlot),
     Overlap(OverlapPlot),
+    Histogram(HistogramPlot),
+    Box(BoxPlot),
+    Violin(BoxPlot),
+    CalendarHeatmap(CalendarHeatmapPlot),
+    Concurrency(ConcurrencyPlot),
+    Sql(SqlPlot),
+    Xy(XyPlot),
 }
 
-#[derive(Debug, Deserialize)]
+impl PlotVariant {
+    /// Whether the x-axis of the plot is time, so that annotations apply to it.
+    pub fn has_time_axis(&self) -> bool {
+        match self {
+            PlotVariant::TimeScatter(_)
+            | PlotVariant::CountScatter(_)
+            | PlotVariant::Concurrency(_)
+            | PlotVariant::Sql(_) => true,
+            PlotVariant::Overlap(_)
+            | PlotVariant::Histogram(_)
+            | PlotVariant::Box(_)
+            | PlotVariant::Violin(_)
+            | PlotVariant::CalendarHeatmap(_)
+            | PlotVariant::Xy(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct TimeScatterPlot {
     pub table: String,
     pub column: String,
     pub unit: Option<TimeUnit>,
+    #[serde(rename = "groupby")]
+    pub group_by: Option<String>,
+    pub top: Option<usize>,
+    #[serde(rename = "sortby")]
+    pub sort_by: Option<GroupOrder>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CountScatterPlot {
     pub table: String,
     pub column: String,
+    #[serde(rename = "groupby")]
+    pub group_by: Option<String>,
+    pub top: Option<usize>,
+    #[serde(rename = "sortby")]
+    pub sort_by: Option<GroupOrder>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct OverlapPlot {
     pub view_id: i32,
+    #[serde(flatten)]
+    pub bins: Bins2d,
+}
+
+/// The bins of a 2D histogram, shared by the plots that draw one.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Bins2d {
     pub x_bins: Option<u32>,
     pub y_bins: Option<u32>,
+    #[serde(rename = "x_logbins")]
+    pub x_log_bins: Option<bool>,
+    #[serde(rename = "y_logbins")]
+    pub y_log_bins: Option<bool>,
+    pub x_min: Option<f64>,
+    pub x_max: Option<f64>,
+    pub y_min: Option<f64>,
+    pub y_max: Option<f64>,
+    /// The percentage of the values to leave out at each end of a range that is not given.
+    pub clip: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HistogramPlot {
+    pub table: String,
+    pub column: String,
+    pub unit: Option<TimeUnit>,
+    pub bins: Option<usize>,
+    #[serde(rename = "binwidth")]
+    pub bin_width: Option<f64>,
+    #[serde(rename = "logbins")]
+    pub log_bins: Option<bool>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub normalization: Option<HistogramNormalization>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Copy, Clone, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HistogramNormalization {
+    Count,
+    Probability,
+    Density,
+    Cdf,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BoxPlot {
+    pub table: String,
+    pub column: String,
+    pub unit: Option<TimeUnit>,
+    #[serde(rename = "groupby")]
+    pub group_by: String,
+    pub top: Option<usize>,
+    #[serde(rename = "sortby")]
+    pub sort_by: Option<GroupOrder>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Copy, Clone, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupOrder {
+    Key,
+    Count,
+    Mean,
+    Median,
+    Q3,
+    Max,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CalendarHeatmapPlot {
+    pub table: String,
+    pub column: String,
+    pub unit: Option<TimeUnit>,
+    pub statistic: AggregationMode,
+    pub rows: Option<CalendarRows>,
+    #[serde(rename = "mincount")]
+    pub min_count: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Copy, Clone, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CalendarRows {
+    Weekday,
+    Date,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ConcurrencyPlot {
+    pub compute: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Default)]
+/// A time series from an SQL query. The x column must be a timestamp in nanoseconds since the
+/// Unix epoch, as in the trace tables.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SqlPlot {
+    pub query: String,
+    pub x: String,
+    pub y: String,
+    pub series: Option<String>,
+}
+
+/// One value of the samples of a table plotted against another, e.g. the wallclock time of
+/// view executions against their overlap with other executions.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct XyPlot {
+    pub table: String,
+    /// Tables to join with `table` on `timestamp` and `ordinal`, such as its `_overlap` table.
+    pub join: Option<Vec<String>>,
+    /// The column or SQL expression for the x values.
+    pub x: String,
+    /// The column or SQL expression for the y values.
+    pub y: String,
+    pub x_unit: Option<TimeUnit>,
+    pub y_unit: Option<TimeUnit>,
+    pub style: Option<XyStyle>,
+    /// A column or SQL expression to color the markers of a scatter plot by.
+    #[serde(rename = "colorby")]
+    pub color_by: Option<String>,
+    pub regression: Option<bool>,
+    #[serde(flatten)]
+    pub bins: Bins2d,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Copy, Clone, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum XyStyle {
+    Scatter,
+    Histogram,
+}
+
+#[derive(Debug, Deserialize, Default, JsonSchema)]
 pub struct Line {
     pub width: Option<f64>,
     pub shape: Option<LineShape>,
@@ -438,7 +703,7 @@ pub struct Line {
     pub outlier_width: Option<usize>,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, Default, JsonSchema)]
 pub struct Filter {
     pub start: Option<String>,
     pub end: Option<String>,
@@ -446,10 +711,51 @@ pub struct Filter {
     pub sql_where: Option<String>,
     #[serde(rename = "workhours")]
     pub work_hours: Option<bool>,
+    /// The calendar that defines the work hours, either inline or the name of a calendar under
+    /// `calendars` in the configuration. Names are replaced by the calendar when the
+    /// configuration is loaded.
+    pub calendar: Option<CalendarReference>,
+}
+
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
+#[serde(untagged)]
+pub enum CalendarReference {
+    Name(String),
+    Calendar(Box<Calendar>),
+}
+
+/// A work-hour calendar for the workhours filter: the work hours of each day of the week, and
+/// holidays when there are no work hours at all. Without a calendar, work hours are 08 to 17 on
+/// weekdays, local time.
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
+pub struct Calendar {
+    pub timezone: Option<Timezone>,
+    pub hours: Option<WorkHours>,
+    /// Holidays, as dates in the format `YYYY-MM-DD`.
+    pub holidays: Option<Vec<String>>,
+    /// An iCalendar (`.ics`) or CSV file with more holidays, relative to the configuration
+    /// file. Its holidays are added to `holidays` when the configuration is loaded.
+    #[serde(rename = "holidayfile")]
+    pub holiday_file: Option<String>,
+}
+
+/// The work hours of each day of the week, as comma-separated time ranges such as
+/// `"08:00-12:00, 13:00-17:00"`. `weekdays` applies to Monday to Friday, unless overridden for
+/// the specific day. Days without work hours are not work days.
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
+pub struct WorkHours {
+    pub weekdays: Option<String>,
+    pub monday: Option<String>,
+    pub tuesday: Option<String>,
+    pub wednesday: Option<String>,
+    pub thursday: Option<String>,
+    pub friday: Option<String>,
+    pub saturday: Option<String>,
+    pub sunday: Option<String>,
 }
 
 // TODO write custom serializer for this so we can accept booleans
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Deserialize, Clone, Debug, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Visible {
     True,
@@ -468,7 +774,7 @@ impl From<&Visible> for plotly::common::Visible {
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum LineShape {
     Linear,
@@ -479,7 +785,7 @@ pub enum LineShape {
     Vhv,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum DashType {
     Solid,
@@ -490,14 +796,14 @@ pub enum DashType {
     LongDashDot,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(untagged)]
 pub enum ColorScale {
     Palette(ColorScalePalette),
     Vector(Vec<ColorScaleElement>),
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, JsonSchema)]
 pub enum ColorScalePalette {
     Greys,
     YlGnBu,
@@ -519,7 +825,7 @@ pub enum ColorScalePalette {
     Cividis,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Deserialize, Clone, Debug, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Mode {
     Lines,
@@ -551,10 +857,10 @@ impl From<&Mode> for plotly::common::Mode {
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, JsonSchema)]
 pub struct ColorScaleElement(pub f64, pub String);
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum TimeUnit {
     #[serde(rename = "Y")]
@@ -579,7 +885,7 @@ pub enum TimeUnit {
     Nanoseconds,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum AggregationMode {
     #[serde(rename = "mean")]
@@ -596,6 +902,27 @@ pub enum AggregationMode {
     Max,
     #[serde(rename = "count")]
     Count,
+    /// The mean of the value over time, where each sample holds until the next one.
+    #[serde(rename = "twmean")]
+    TimeWeightedMean,
+    /// The value in effect at the end of the time window.
+    #[serde(rename = "last")]
+    Last,
+    /// The integral of the value over time, in value-seconds, where each sample holds until the
+    /// next one.
+    #[serde(rename = "integral")]
+    Integral,
+}
+
+impl AggregationMode {
+    /// Whether the mode treats the samples as a step function, where each sample holds until the
+    /// next one, rather than as independent samples.
+    pub fn is_time_weighted(&self) -> bool {
+        matches!(
+            self,
+            AggregationMode::TimeWeightedMean | AggregationMode::Last | AggregationMode::Integral
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -608,48 +935,327 @@ lazy_static! {
     static ref TIME_PERIOD_REGEX: Regex = Regex::new(r"^\s*(\d+)\s*([A-Za-z]+)\s*$").unwrap();
 }
 
-impl<'de> Deserialize<'de> for TimePeriod {
-    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let s = String::deserialize(deserializer)?;
+impl TimePeriod {
+    /// Get the length of the time period in nanoseconds, or `None` for months and years, which
+    /// vary in length, or if it is out of range.
+    pub fn as_nanoseconds(&self) -> Option<i64> {
+        let nanoseconds_per_unit: i64 = match self.unit {
+            TimeUnit::Weeks => 7 * 86_400_000_000_000,
+            TimeUnit::Days => 86_400_000_000_000,
+            TimeUnit::Hours => 3_600_000_000_000,
+            TimeUnit::Minutes => 60_000_000_000,
+            TimeUnit::Seconds => 1_000_000_000,
+            TimeUnit::Milliseconds => 1_000_000,
+            TimeUnit::Microseconds => 1_000,
+            TimeUnit::Nanoseconds => 1,
+            TimeUnit::Years | TimeUnit::Months => return None,
+        };
+        i64::try_from(self.quantity)
+            .ok()?
+            .checked_mul(nanoseconds_per_unit)
+    }
+}
 
-        let captures = match TIME_PERIOD_REGEX.captures(&s) {
+impl std::str::FromStr for TimePeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let captures = match TIME_PERIOD_REGEX.captures(s) {
             Some(captures) => captures,
-            None => return Err(serde::de::Error::custom("Invalid time period")),
+            None => return Err("Invalid time period".to_string()),
         };
 
         let quantity = match captures.get(1).unwrap().as_str().parse::<u64>() {
             Ok(quantity) => quantity,
             Err(_) => {
-                return Err(serde::de::Error::custom(
-                    "Invalid time period quantity (maximum value is 2**64-1)",
-                ))
+                return Err("Invalid time period quantity (maximum value is 2**64-1)".to_string())
             }
         };
 
         let unit_str = captures.get(2).unwrap().as_str();
         let unit = match serde_yaml::from_str::<TimeUnit>(unit_str) {
             Ok(unit) => unit,
-            Err(_) => return Err(serde::de::Error::custom("Invalid time period unit")),
+            Err(_) => return Err("Invalid time period unit".to_string()),
         };
         Ok(TimePeriod { quantity, unit })
     }
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
+impl<'de> Deserialize<'de> for TimePeriod {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for TimePeriod {
+    fn schema_name() -> String {
+        "TimePeriod".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        // The units are taken from the schema of TimeUnit so that they can't differ.
+        let units: Vec<String> = match TimeUnit::json_schema(gen) {
+            schemars::schema::Schema::Object(unit) => unit.enum_values.unwrap_or_default(),
+            _ => Vec::new(),
+        }
+        .iter()
+        .filter_map(|unit| unit.as_str().map(regex::escape))
+        .collect();
+        json_schema(serde_json::json!({
+            "description": "A quantity and a time unit, such as 5m.",
+            "type": "string",
+            "pattern": format!(r"^\s*\d+\s*({})\s*$", units.join("|")),
+        }))
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize, Copy, Clone, JsonSchema)]
 pub struct Aggregation {
     pub mode: AggregationMode,
     pub size: TimePeriod,
     #[serde(rename = "mincount")]
     pub min_count: Option<usize>,
+    pub timezone: Option<Timezone>,
+}
+
+/// The time zone that calendar-aware aggregation windows (days, weeks, months and years) are
+/// aligned to. Either `local` for the time zone of the computer, or a name from the IANA time
+/// zone database such as `UTC` or `Europe/Stockholm`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Timezone {
+    Local,
+    Named(chrono_tz::Tz),
 }
 
-pub fn load_config<T: AsRef<Path>>(path: T) -> Result<Root> {
-    let config = std::fs::read_to_string(path)?;
-    let config: Root = serde_yaml::from_str(&config)?;
-    Ok(config)
+impl std::str::FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Timezone::Local);
+        }
+        s.parse::<chrono_tz::Tz>()
+            .map(Timezone::Named)
+            .map_err(|_| format!("Invalid time zone {}", s))
+    }
+}
+
+impl<'de> Deserialize<'de> for Timezone {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for Timezone {
+    fn schema_name() -> String {
+        "Timezone".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        json_schema(serde_json::json!({
+            "description": "local, or a name from the IANA time zone database such as UTC.",
+            "type": "string",
+        }))
+    }
+}
+
+/// A transformation of the samples before they are aggregated.
+#[derive(Debug, PartialEq, Deserialize, Copy, Clone, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Transform {
+    /// The per-second rate of increase of a cumulative counter.
+    Rate,
+}
+
+/// Smoothing of the values of a series, after the transform and the aggregation. The rolling
+/// methods compute the statistic of a trailing window at each value, and the trend methods fit a
+/// line or a curve to the whole series.
+#[derive(Debug, PartialEq, Deserialize, Copy, Clone, JsonSchema)]
+pub struct Smoothing {
+    pub method: SmoothingMethod,
+    /// The window of the rolling methods, and of `ewma` unless `alpha` is set.
+    pub window: Option<SmoothingWindow>,
+    /// The percentile of the `percentile` method, from 0 to 100.
+    pub percentile: Option<f64>,
+    /// The weight of each new value in `ewma`, from 0 to 1.
+    pub alpha: Option<f64>,
+    /// The fraction of the values that each local fit of `loess` uses, from 0 to 1.
+    pub span: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Copy, Clone, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SmoothingMethod {
+    /// The rolling mean.
+    Mean,
+    /// The rolling median.
+    Median,
+    /// A rolling percentile.
+    Percentile,
+    /// The exponentially weighted moving average.
+    Ewma,
+    /// A least-squares linear trend line.
+    Linear,
+    /// A locally weighted linear regression (LOESS) trend curve.
+    Loess,
+}
+
+/// A window of a number of values, or of a time span such as `1h`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum SmoothingWindow {
+    Values(usize),
+    Time(TimePeriod),
+}
+
+impl<'de> Deserialize<'de> for SmoothingWindow {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SmoothingWindowVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for SmoothingWindowVisitor {
+            type Value = SmoothingWindow;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a number of values or a time period such as 1h")
+            }
+
+            fn visit_u64<E>(self, value: u64) -> std::result::Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(SmoothingWindow::Values(value as usize))
+            }
+
+            fn visit_str<E>(self, value: &str) -> std::result::Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                value.parse().map(SmoothingWindow::Time).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(SmoothingWindowVisitor)
+    }
+}
+
+impl JsonSchema for SmoothingWindow {
+    fn schema_name() -> String {
+        "SmoothingWindow".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let time_period = gen.subschema_for::<TimePeriod>();
+        json_schema(serde_json::json!({
+            "description": "A number of values, or a time period such as 1h.",
+            "anyOf": [{ "type": "integer", "minimum": 0 }, time_period],
+        }))
+    }
+}
+
+/// Configuration for marking change points in a plot. Change points are
+/// detected in the aggregated series, so this requires `aggregation` to be set.
+#[derive(Debug, PartialEq, Deserialize, Default, JsonSchema)]
+pub struct ChangePoints {
+    pub penalty: Option<f64>,
+    #[serde(rename = "minsegment")]
+    pub min_segment: Option<usize>,
+}
+
+pub fn load_config<T: AsRef<Path>>(path: T, variables: &[(String, String)]) -> Result<Root> {
+    load_located_config(path, variables).map(|(config, _)| config)
+}
+
+/// Load a configuration file like `load_config`, along with a locator that finds where errors in
+/// the configuration are in its files.
+pub(crate) fn load_located_config<T: AsRef<Path>>(
+    path: T,
+    variables: &[(String, String)],
+) -> Result<(Root, ConfigLocator)> {
+    let expanded = super::template::expand_template(path.as_ref(), variables)?;
+    let mut locator = ConfigLocator::new(expanded.files, expanded.plot_origins);
+    super::schema::check_config(&expanded.config, &mut locator)?;
+    let mut config: Root = serde_yaml::from_value(expanded.config)?;
+    let base_dir = path.as_ref().parent().unwrap();
+    let mut calendars = config.calendars.take().unwrap_or_default();
+    for calendar in calendars.values_mut() {
+        read_holidays(calendar, base_dir)?;
+    }
+    for plot in config.plots.iter_mut() {
+        let name = &plot.plot_common.name;
+        if let Some(filter) = plot.plot_common.filter.as_mut() {
+            resolve_calendar(filter, &calendars, base_dir)
+                .map_err(|e| format!("Error in the filter of plot {}: {}", name, e))?;
+        }
+    }
+    config.calendars = Some(calendars);
+    Ok((config, locator))
+}
+
+/// Load a named calendar from a YAML file with calendars under `calendars`, as in the
+/// configuration file.
+pub fn load_calendar<T: AsRef<Path>>(path: T, name: &str) -> Result<Calendar> {
+    #[derive(Deserialize)]
+    struct Calendars {
+        calendars: HashMap<String, Calendar>,
+    }
+
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Error opening calendar file {}: {}", path.display(), e))?;
+    let mut calendars: Calendars = serde_yaml::from_reader(file)
+        .map_err(|e| format!("Error reading calendar file {}: {}", path.display(), e))?;
+    let mut calendar = calendars
+        .calendars
+        .remove(name)
+        .ok_or_else(|| format!("Calendar {} not found in {}", name, path.display()))?;
+    read_holidays(&mut calendar, path.parent().unwrap())?;
+    Ok(calendar)
+}
+
+/// Replace a calendar name in a filter by the calendar.
+fn resolve_calendar(
+    filter: &mut Filter,
+    calendars: &HashMap<String, Calendar>,
+    base_dir: &Path,
+) -> Result<()> {
+    let calendar = match filter.calendar.take() {
+        None => return Ok(()),
+        Some(CalendarReference::Name(name)) => calendars
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| format!("Calendar {} is not defined under calendars", name))?,
+        Some(CalendarReference::Calendar(mut calendar)) => {
+            read_holidays(&mut calendar, base_dir)?;
+            *calendar
+        }
+    };
+    if filter.work_hours != Some(true) {
+        return Err("A calendar requires workhours: true".into());
+    }
+    filter.calendar = Some(CalendarReference::Calendar(Box::new(calendar)));
+    Ok(())
+}
+
+/// Add the holidays from the holiday file of a calendar to its holidays.
+fn read_holidays(calendar: &mut Calendar, base_dir: &Path) -> Result<()> {
+    if let Some(holiday_file) = calendar.holiday_file.take() {
+        let holidays = util::read_holiday_file(&base_dir.join(holiday_file))?;
+        calendar
+            .holidays
+            .get_or_insert_with(Vec::new)
+            .extend(holidays);
+    }
+    Ok(())
 }
 
 impl From<&Layout> for plotly::layout::Layout {
@@ -669,6 +1275,9 @@ impl From<&Layout> for plotly::layout::Layout {
             assert!(height > 0);
             playout = playout.height(height as usize);
         }
+        if let Some(grid) = &layout.grid {
+            playout = playout.grid(grid.into());
+        }
 
         #[allow(clippy::type_complexity)]
         let axes: &[(
@@ -744,10 +1353,59 @@ impl From<&Axis> for plotly::layout::Axis {
         if let Some(overlaying) = &axis.overlaying {
             plotly_axis = plotly_axis.overlaying(overlaying);
         }
+        if let Some(anchor) = &axis.anchor {
+            plotly_axis = plotly_axis.anchor(anchor);
+        }
+        if let Some(domain) = &axis.domain {
+            plotly_axis = plotly_axis.domain(domain);
+        }
         plotly_axis
     }
 }
 
+impl From<&Grid> for plotly::layout::LayoutGrid {
+    fn from(grid: &Grid) -> plotly::layout::LayoutGrid {
+        let mut plotly_grid = plotly::layout::LayoutGrid::new();
+        if let Some(rows) = grid.rows {
+            plotly_grid = plotly_grid.rows(rows);
+        }
+        if let Some(columns) = grid.columns {
+            plotly_grid = plotly_grid.columns(columns);
+        }
+        if let Some(pattern) = &grid.pattern {
+            plotly_grid = plotly_grid.pattern(pattern.into());
+        }
+        if let Some(row_order) = &grid.row_order {
+            plotly_grid = plotly_grid.row_order(row_order.into());
+        }
+        if let Some(x_gap) = grid.x_gap {
+            plotly_grid = plotly_grid.x_gap(x_gap);
+        }
+        if let Some(y_gap) = grid.y_gap {
+            plotly_grid = plotly_grid.y_gap(y_gap);
+        }
+        plotly_grid
+    }
+}
+
+impl From<&GridPattern> for plotly::layout::GridPattern {
+    fn from(pattern: &GridPattern) -> plotly::layout::GridPattern {
+        match pattern {
+            GridPattern::Independent => plotly::layout::GridPattern::Independent,
+            GridPattern::Coupled => plotly::layout::GridPattern::Coupled,
+        }
+    }
+}
+
+impl From<&RowOrder> for plotly::layout::RowOrder {
+    fn from(row_order: &RowOrder) -> plotly::layout::RowOrder {
+        match row_order {
+            RowOrder::TopToBottom => plotly::layout::RowOrder::TopToBottom,
+            RowOrder::BottomToTop => plotly::layout::RowOrder::BottomToTop,
+        }
+    }
+}
+
 impl From<&Title> for plotly::common::Title {
     fn from(title: &Title) -> plotly::common::Title {
         plotly::common::Title::new(&title.text)
@@ -1,7 +1,9 @@
 #[macro_use]
 extern crate lazy_static;
 
-use clap::{value_parser, Arg, Command};
+use std::path::Path;
+
+use clap::{value_parser, Arg, ArgAction, Command};
 
 use util::Result;
 
@@ -46,11 +48,46 @@ fn run() -> Result<()> {
         )
         .subcommand(
             Command::new("show")
-                .about("Load plot configuration from YAML file and show the plot")
+                .about("Load plot configuration from YAML files and show the plots, or write them to files")
+                .arg(
+                    Arg::new("plot.yaml")
+                        .help("The plot configuration files to load")
+                        .required(true)
+                        .num_args(1..)
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("File to write the plot to instead of showing it, as html, png, jpeg, webp, svg or pdf depending on the extension. If it is a directory, each plot is written to it, named after its configuration file")
+                        .long("output")
+                )
+                .arg(
+                    Arg::new("format")
+                        .help("Format of the plots written to an output directory")
+                        .long("format")
+                        .value_parser(["html", "png", "jpeg", "webp", "svg", "pdf"])
+                        .default_value("html")
+                )
+                .arg(
+                    Arg::new("set")
+                        .help("Set a variable in the configuration, as name=value, e.g. view=123. Can be given several times")
+                        .long("set")
+                        .action(ArgAction::Append)
+                ),
+        )
+        .subcommand(
+            Command::new("validate-config")
+                .about("Check plot configuration files against the configuration schema, and check that the tables and columns that the plots use exist in the source database")
                 .arg(
                     Arg::new("plot.yaml")
-                        .help("The plot configuration file to load")
+                        .help("The plot configuration files to check")
                         .required(true)
+                        .num_args(1..)
+                )
+                .arg(
+                    Arg::new("set")
+                        .help("Set a variable in the configuration, as name=value, e.g. view=123. Can be given several times")
+                        .long("set")
+                        .action(ArgAction::Append)
                 ),
         )
         .subcommand(
@@ -72,13 +109,15 @@ fn run() -> Result<()> {
                 )
                 .arg(
                     Arg::new("start")
-                        .help("Start of time period")
+                        .help("Start of time period, e.g. 2024-03, 2024-W12, last monday, -7d or latest-24h")
                         .long("start")
+                        .allow_hyphen_values(true)
                 )
                 .arg(
                     Arg::new("end")
-                        .help("End of time period")
+                        .help("End of time period, e.g. 2024-03, 2024-W12, last monday, now or latest")
                         .long("end")
+                        .allow_hyphen_values(true)
                 )
         )
         .subcommand(
@@ -90,20 +129,45 @@ fn run() -> Result<()> {
                         .required(true)
                         .index(1)
                 )
+                .args(filter_args())
+        )
+        .subcommand(
+            Command::new("form-statistics")
+                .about("Print statistics for each form")
                 .arg(
-                    Arg::new("start")
-                        .help("Start of time period")
-                        .long("start")
+                    Arg::new("database")
+                        .help("The target SQLite database")
+                        .required(true)
+                        .index(1)
                 )
+                .args(filter_args())
+        )
+        .subcommand(
+            Command::new("diff-db")
+                .about("Compare view and form statistics between two databases and print regressions and improvements")
                 .arg(
-                    Arg::new("end")
-                        .help("End of time period")
-                        .long("end")
+                    Arg::new("before")
+                        .help("The SQLite database to use as baseline")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::new("after")
+                        .help("The SQLite database to compare against the baseline")
+                        .required(true)
+                        .index(2)
+                )
+                .arg(
+                    Arg::new("threshold")
+                        .help("Minimum relative change of the median to report, e.g. 0.1 for 10%")
+                        .long("threshold")
+                        .value_parser(value_parser!(f64))
+                        .default_value("0.1")
                 )
         )
         .subcommand(
-            Command::new("form-statistics")
-                .about("Print statistics for each form")
+            Command::new("test-regression")
+                .about("Test whether the execution time of a view differs significantly between two time periods")
                 .arg(
                     Arg::new("database")
                         .help("The target SQLite database")
@@ -111,23 +175,164 @@ fn run() -> Result<()> {
                         .index(1)
                 )
                 .arg(
-                    Arg::new("start")
-                        .help("Start of time period")
-                        .long("start")
+                    Arg::new("view")
+                        .help("The ID of the view to test")
+                        .long("view")
+                        .value_parser(value_parser!(i32))
+                        .required(true)
                 )
                 .arg(
-                    Arg::new("end")
-                        .help("End of time period")
-                        .long("end")
+                    Arg::new("before")
+                        .help("Time period to use as baseline, as START..END or a single date")
+                        .long("before")
+                        .required(true)
+                        .allow_hyphen_values(true)
+                )
+                .arg(
+                    Arg::new("after")
+                        .help("Time period to compare against the baseline, as START..END or a single date")
+                        .long("after")
+                        .required(true)
+                        .allow_hyphen_values(true)
+                )
+                .arg(
+                    Arg::new("confidence")
+                        .help("Confidence level for the bootstrap confidence intervals")
+                        .long("confidence")
+                        .value_parser(value_parser!(f64))
+                        .default_value("0.95")
+                )
+                .arg(
+                    Arg::new("resamples")
+                        .help("Number of bootstrap resamples")
+                        .long("resamples")
+                        .value_parser(value_parser!(usize))
+                        .default_value("1000")
                 )
         )
+        .subcommand(
+            Command::new("detect-anomalies")
+                .about("List time windows where a metric deviates from its usual value for that hour of the week")
+                .arg(
+                    Arg::new("database")
+                        .help("The target SQLite database")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::new("table")
+                        .help("The table to read samples from")
+                        .long("table")
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("column")
+                        .help("The column to read samples from")
+                        .long("column")
+                        .required(true)
+                )
+                .args(aggregation_args("1h"))
+                .mut_arg("timezone", |arg| {
+                    arg.help("Time zone of the hours of the week, and that days, weeks, months and years are aligned to, e.g. local or Europe/Stockholm (default UTC)")
+                })
+                .arg(
+                    Arg::new("rate")
+                        .help("Treat the column as a cumulative counter and use its rate per second")
+                        .num_args(0)
+                        .long("rate")
+                )
+                .arg(
+                    Arg::new("threshold")
+                        .help("Minimum robust z-score (deviation from the baseline median, in scaled MADs) to report")
+                        .long("threshold")
+                        .value_parser(value_parser!(f64))
+                        .default_value("3.5")
+                )
+                .arg(
+                    Arg::new("top")
+                        .help("Number of views to list as the largest contributors to each anomalous window")
+                        .long("top")
+                        .value_parser(value_parser!(usize))
+                        .default_value("5")
+                )
+                .args(filter_args())
+        )
+        .subcommand(
+            Command::new("detect-change-points")
+                .about("Find points in time where the execution time of a view permanently changed")
+                .arg(
+                    Arg::new("database")
+                        .help("The target SQLite database")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::new("view")
+                        .help("Only analyze the view with this ID")
+                        .long("view")
+                        .value_parser(value_parser!(i32))
+                )
+                .args(aggregation_args("1D"))
+                .mut_arg("aggregation", |arg| {
+                    arg.help("How to aggregate the samples in each time window (mean, min, q1, median, q3, max or count)")
+                })
+                .arg(
+                    Arg::new("penalty")
+                        .help("Penalty factor for adding a change point, default 6. Larger values give fewer change points")
+                        .long("penalty")
+                        .value_parser(value_parser!(f64))
+                )
+                .arg(
+                    Arg::new("minsegment")
+                        .help("Minimum number of time windows between change points, default 2")
+                        .long("minsegment")
+                        .value_parser(value_parser!(usize))
+                )
+                .args(filter_args())
+        )
+        .subcommand(
+            Command::new("correlate")
+                .about("Compute correlations between metrics aggregated into common time windows")
+                .arg(
+                    Arg::new("database")
+                        .help("The target SQLite database")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::new("series")
+                        .help("A series to correlate, as table.column, optionally followed by :mode and :rate. Give at least two")
+                        .long("series")
+                        .required(true)
+                        .action(ArgAction::Append)
+                )
+                .args(aggregation_args("1h"))
+                .mut_arg("aggregation", |arg| {
+                    arg.help("Default aggregation mode for the series, which can be overridden with :mode")
+                })
+                .arg(
+                    Arg::new("maxlag")
+                        .help("Maximum lag, in time windows, for the lagged cross-correlation")
+                        .long("maxlag")
+                        .value_parser(value_parser!(i64))
+                        .default_value("0")
+                )
+                .arg(
+                    Arg::new("perview")
+                        .help("Also correlate per view, for series from tables with a view ID")
+                        .num_args(0)
+                        .long("perview")
+                )
+                .args(filter_args())
+        )
         .subcommand(
             Command::new("convert-unit")
                 .about("Provide unit conversions for writing manual SQL queries")
                 .arg(
                     Arg::new("value")
-                        .help("The value to convert. Can be a timestamp or a duration with unit suffix")
+                        .help("The value to convert. Can be a timestamp, a time such as 2024-03, last monday or -7d, or a duration with unit suffix")
                         .required(true)
+                        .allow_hyphen_values(true)
                 )
         )
         .subcommand(
@@ -200,9 +405,46 @@ fn run() -> Result<()> {
             command::import_data(target, &sources)?;
         }
         Some(("show", matches)) => {
-            let plot_configuration = matches.get_one::<String>("plot.yaml").unwrap();
-            println!("Showing plot data from {}", plot_configuration);
-            command::plot(plot_configuration)?;
+            let plot_configurations: Vec<&String> =
+                matches.get_many::<String>("plot.yaml").unwrap().collect();
+            let output = matches.get_one::<String>("output").map(Path::new);
+            let format: &String = matches.get_one("format").unwrap();
+            let output_dir = output.filter(|output| output.is_dir());
+            let variables = get_variables(matches)?;
+            if output.is_some() && output_dir.is_none() && plot_configurations.len() > 1 {
+                return Err("The output must be a directory when showing several plots".into());
+            }
+            for plot_configuration in plot_configurations {
+                eprintln!("Showing plot data from {}", plot_configuration);
+                let output = match output_dir {
+                    Some(output_dir) => {
+                        let file_name = Path::new(plot_configuration).with_extension(format);
+                        Some(output_dir.join(file_name.file_name().unwrap()))
+                    }
+                    None => output.map(Path::to_path_buf),
+                };
+                command::plot(plot_configuration, output.as_deref(), &variables)?;
+            }
+        }
+        Some(("validate-config", matches)) => {
+            let plot_configurations: Vec<&String> =
+                matches.get_many::<String>("plot.yaml").unwrap().collect();
+            let variables = get_variables(matches)?;
+            let mut invalid = 0;
+            for plot_configuration in &plot_configurations {
+                if let Err(e) = command::validate_config(plot_configuration, &variables) {
+                    eprintln!("{}", e);
+                    invalid += 1;
+                }
+            }
+            if invalid > 0 {
+                return Err(format!(
+                    "{} of {} configurations are invalid",
+                    invalid,
+                    plot_configurations.len()
+                )
+                .into());
+            }
         }
         Some(("compute-overlap", matches)) => {
             let database = matches.get_one::<String>("database").unwrap();
@@ -221,15 +463,83 @@ fn run() -> Result<()> {
         }
         Some(("view-statistics", matches)) => {
             let database: &String = matches.get_one("database").unwrap();
-            let start: Option<&String> = matches.get_one("start");
-            let end: Option<&String> = matches.get_one("end");
-            command::view_statistics(database, start.map(|s| s.as_str()), end.map(|s| s.as_str()))?;
+            let filter = get_filter(matches)?;
+            command::view_statistics(database, &filter)?;
         }
         Some(("form-statistics", matches)) => {
             let database: &String = matches.get_one("database").unwrap();
-            let start: Option<&String> = matches.get_one("start");
-            let end: Option<&String> = matches.get_one("end");
-            command::form_statistics(database, start.map(|s| s.as_str()), end.map(|s| s.as_str()))?;
+            let filter = get_filter(matches)?;
+            command::form_statistics(database, &filter)?;
+        }
+        Some(("diff-db", matches)) => {
+            let before: &String = matches.get_one("before").unwrap();
+            let after: &String = matches.get_one("after").unwrap();
+            let threshold: &f64 = matches.get_one("threshold").unwrap();
+            command::diff_db(before, after, *threshold)?;
+        }
+        Some(("test-regression", matches)) => {
+            let database: &String = matches.get_one("database").unwrap();
+            let view: &i32 = matches.get_one("view").unwrap();
+            let before: &String = matches.get_one("before").unwrap();
+            let after: &String = matches.get_one("after").unwrap();
+            let confidence: &f64 = matches.get_one("confidence").unwrap();
+            let resamples: &usize = matches.get_one("resamples").unwrap();
+            command::test_regression(database, *view, before, after, *confidence, *resamples)?;
+        }
+        Some(("detect-anomalies", matches)) => {
+            let database: &String = matches.get_one("database").unwrap();
+            let table: &String = matches.get_one("table").unwrap();
+            let column: &String = matches.get_one("column").unwrap();
+            let threshold: &f64 = matches.get_one("threshold").unwrap();
+            let top: &usize = matches.get_one("top").unwrap();
+            let filter = get_filter(matches)?;
+            let aggregation = get_aggregation(matches)?;
+            command::detect_anomalies(
+                database,
+                table,
+                column,
+                &filter,
+                &aggregation,
+                matches.get_flag("rate").then_some(config::Transform::Rate),
+                *threshold,
+                *top,
+            )?;
+        }
+        Some(("detect-change-points", matches)) => {
+            let database: &String = matches.get_one("database").unwrap();
+            let mut filter = get_filter(matches)?;
+            if let Some(view) = matches.get_one::<i32>("view") {
+                let view_criteria = format!("view_id = {}", view);
+                filter.sql_where = Some(match filter.sql_where {
+                    Some(sql_where) => format!("{} and ({})", view_criteria, sql_where),
+                    None => view_criteria,
+                });
+            }
+            let aggregation = get_aggregation(matches)?;
+            let change_points = config::ChangePoints {
+                penalty: matches.get_one::<f64>("penalty").copied(),
+                min_segment: matches.get_one::<usize>("minsegment").copied(),
+            };
+            command::detect_change_points(database, &filter, &aggregation, &change_points)?;
+        }
+        Some(("correlate", matches)) => {
+            let database: &String = matches.get_one("database").unwrap();
+            let series: Vec<String> = matches
+                .get_many::<String>("series")
+                .unwrap()
+                .cloned()
+                .collect();
+            let max_lag: &i64 = matches.get_one("maxlag").unwrap();
+            let filter = get_filter(matches)?;
+            let aggregation = get_aggregation(matches)?;
+            command::correlate(
+                database,
+                &series,
+                &filter,
+                &aggregation,
+                *max_lag,
+                matches.get_flag("perview"),
+            )?;
         }
         Some(("convert-unit", matches)) => {
             let value: &String = matches.get_one("value").unwrap();
@@ -261,6 +571,111 @@ fn run() -> Result<()> {
     Ok(())
 }
 
+/// Get the variables given with `--set`, as name and value.
+fn get_variables(matches: &clap::ArgMatches) -> Result<Vec<(String, String)>> {
+    matches
+        .get_many::<String>("set")
+        .unwrap_or_default()
+        .map(|variable| match variable.split_once('=') {
+            Some((name, value)) => Ok((name.to_string(), value.to_string())),
+            None => Err(format!("Expected name=value for --set, got {}", variable).into()),
+        })
+        .collect()
+}
+
+/// The arguments that filter the samples, like the `filter` of a plot, read with [`get_filter`].
+fn filter_args() -> Vec<Arg> {
+    vec![
+        Arg::new("start")
+            .help("Start of time period, e.g. 2024-03, 2024-W12, last monday, -7d or latest-24h")
+            .long("start")
+            .allow_hyphen_values(true),
+        Arg::new("end")
+            .help("End of time period, e.g. 2024-03, 2024-W12, last monday, now or latest")
+            .long("end")
+            .allow_hyphen_values(true),
+        Arg::new("where")
+            .help("Additional SQL criteria for the samples")
+            .long("where"),
+        Arg::new("workhours")
+            .help("Only include samples within work hours")
+            .num_args(0)
+            .long("workhours"),
+        Arg::new("calendars")
+            .help("YAML file with work-hour calendars under calendars, as in the plot configuration")
+            .long("calendars"),
+        Arg::new("calendar")
+            .help("Name of the calendar in the calendars file that defines the work hours, instead of 08 to 17 on weekdays")
+            .long("calendar")
+            .requires("workhours")
+            .requires("calendars"),
+    ]
+}
+
+/// Get the filter given with the [`filter_args`].
+fn get_filter(matches: &clap::ArgMatches) -> Result<config::Filter> {
+    Ok(config::Filter {
+        start: matches.get_one::<String>("start").cloned(),
+        end: matches.get_one::<String>("end").cloned(),
+        sql_where: matches.get_one::<String>("where").cloned(),
+        work_hours: Some(matches.get_flag("workhours")),
+        calendar: get_calendar(matches)?,
+    })
+}
+
+/// Load the calendar selected with `--calendar` from the file given with `--calendars`.
+fn get_calendar(matches: &clap::ArgMatches) -> Result<Option<config::CalendarReference>> {
+    let Some(name) = matches.get_one::<String>("calendar") else {
+        return Ok(None);
+    };
+    let path: &String = matches.get_one("calendars").unwrap();
+    let calendar = config::load_calendar(path, name)?;
+    Ok(Some(config::CalendarReference::Calendar(Box::new(
+        calendar,
+    ))))
+}
+
+/// The arguments that select how samples are aggregated into time windows, read with
+/// [`get_aggregation`].
+///
+/// # Arguments
+/// * `default_size` - The default size of the time windows.
+fn aggregation_args(default_size: &'static str) -> Vec<Arg> {
+    vec![
+        Arg::new("aggregation")
+            .help("How to aggregate the samples in each time window (mean, min, q1, median, q3, max, count, or the time-weighted twmean, last or integral)")
+            .long("aggregation")
+            .default_value("median"),
+        Arg::new("size")
+            .help("Size of each time window, e.g. 1h")
+            .long("size")
+            .default_value(default_size),
+        Arg::new("timezone")
+            .help("Time zone that days, weeks, months and years are aligned to, e.g. local or Europe/Stockholm (default UTC)")
+            .long("timezone"),
+        Arg::new("mincount")
+            .help("Minimum number of samples in a time window for it to be considered")
+            .long("mincount")
+            .value_parser(value_parser!(usize)),
+    ]
+}
+
+/// Get the aggregation given with the [`aggregation_args`].
+fn get_aggregation(matches: &clap::ArgMatches) -> Result<config::Aggregation> {
+    let mode: &String = matches.get_one("aggregation").unwrap();
+    let size: &String = matches.get_one("size").unwrap();
+    Ok(config::Aggregation {
+        mode: serde_yaml::from_str(mode)
+            .map_err(|_| format!("Invalid aggregation mode {}", mode))?,
+        size: size.parse()?,
+        min_count: matches.get_one::<usize>("mincount").copied(),
+        timezone: matches
+            .get_one::<String>("timezone")
+            .map(|timezone| timezone.parse())
+            .transpose()?,
+    })
+}
+
 fn main() {
     if let Err(e) = run() {
         eprintln!("{}", e);
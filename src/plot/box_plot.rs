@@ -0,0 +1,191 @@
+use plotly::common::Line;
+use plotly::{BoxPlot, Trace};
+use serde::Serialize;
+
+use crate::config::GroupOrder;
+use crate::{config, plot::*, util, util::Result};
+
+/// The maximum number of values per group passed on to a violin plot. Larger groups are reduced
+/// to evenly spaced quantiles, which keeps the size of the plot manageable without changing the
+/// shape of the distribution.
+const MAX_VIOLIN_SAMPLES: usize = 1000;
+
+/// The samples of one group, e.g. one view.
+struct Group {
+    key: String,
+    /// The label of the group on the x-axis.
+    label: String,
+    /// The sorted values, converted to the unit of the plot.
+    values: Vec<f64>,
+    statistics: util::Statistics<f64>,
+}
+
+pub fn box_plot(
+    context: &PlotContext,
+    plot_config: &config::BoxPlot,
+) -> Result<Vec<Box<dyn Trace>>> {
+    let common_cfg = &context.plot_config;
+    let groups = get_groups(context, plot_config)?;
+
+    // Only the statistics are passed to plotly rather than all samples. The whiskers extend to
+    // the most extreme values within 1.5 IQR of the box, like plotly does for raw samples.
+    let mut lower_fence = Vec::with_capacity(groups.len());
+    let mut upper_fence = Vec::with_capacity(groups.len());
+    for group in groups.iter() {
+        let stats = &group.statistics;
+        let lower_limit = stats.q1 - 1.5 * stats.iqr;
+        let upper_limit = stats.q3 + 1.5 * stats.iqr;
+        lower_fence.push(*group.values.iter().find(|v| **v >= lower_limit).unwrap());
+        upper_fence.push(
+            *group
+                .values
+                .iter()
+                .rev()
+                .find(|v| **v <= upper_limit)
+                .unwrap(),
+        );
+    }
+
+    let line_color = util::get_line_color(context, common_cfg);
+    let mut trace = Box::new(BoxPlot::<String, f64>::default())
+        .x(groups.iter().map(|g| g.label.clone()).collect())
+        .q1(groups.iter().map(|g| g.statistics.q1).collect())
+        .median(groups.iter().map(|g| g.statistics.median).collect())
+        .q3(groups.iter().map(|g| g.statistics.q3).collect())
+        .lower_fence(lower_fence)
+        .upper_fence(upper_fence)
+        .mean(groups.iter().map(|g| g.statistics.mean).collect())
+        .name(&common_cfg.name)
+        .line(Line::new().color(line_color.to_owned()));
+    if let Some(visible) = &common_cfg.visible {
+        trace = trace.visible(visible.into());
+    }
+    if let Some(x_axis) = &common_cfg.x_axis {
+        trace = trace.x_axis(x_axis);
+    }
+    if let Some(y_axis) = &common_cfg.y_axis {
+        trace = trace.y_axis(y_axis);
+    }
+
+    Ok(vec![trace])
+}
+
+pub fn violin_plot(
+    context: &PlotContext,
+    plot_config: &config::BoxPlot,
+) -> Result<Vec<Box<dyn Trace>>> {
+    let common_cfg = &context.plot_config;
+    let groups = get_groups(context, plot_config)?;
+
+    let mut x = Vec::new();
+    let mut y = Vec::new();
+    for group in groups.iter() {
+        let n = group.values.len();
+        let samples = n.min(MAX_VIOLIN_SAMPLES);
+        for i in 0..samples {
+            let index = if samples > 1 {
+                i * (n - 1) / (samples - 1)
+            } else {
+                0
+            };
+            x.push(group.label.clone());
+            y.push(group.values[index]);
+        }
+    }
+
+    let line_color = util::get_line_color(context, common_cfg);
+    Ok(vec![Box::new(Violin {
+        r#type: "violin",
+        x,
+        y,
+        name: common_cfg.name.clone(),
+        visible: common_cfg.visible.as_ref().map(|v| v.into()),
+        x_axis: common_cfg.x_axis.clone(),
+        y_axis: common_cfg.y_axis.clone(),
+        line: Line::new().color(line_color.to_owned()),
+        box_: ViolinVisible { visible: true },
+        mean_line: ViolinVisible { visible: true },
+        points: false,
+        span_mode: "hard",
+    })])
+}
+
+/// Load the samples of each group and select the groups to plot.
+fn get_groups(context: &PlotContext, plot_config: &config::BoxPlot) -> Result<Vec<Group>> {
+    let common_cfg = &context.plot_config;
+    let samples_by_group = util::get_timed_samples_by_group(
+        &context.conn,
+        common_cfg.filter.as_ref().and_then(|f| f.start.as_deref()),
+        common_cfg.filter.as_ref().and_then(|f| f.end.as_deref()),
+        common_cfg.filter.as_ref(),
+        &plot_config.column,
+        &plot_config.table,
+        &util::get_group_expression(&plot_config.group_by, common_cfg.timezone()),
+    )?;
+
+    let mut groups: Vec<Group> = Vec::with_capacity(samples_by_group.len());
+    for (key, samples) in samples_by_group {
+        let mut value = samples.value;
+        value.sort();
+        let values = util::nanoseconds_duration_to_unit(&value, plot_config.unit);
+        let statistics = util::get_statistics(&values);
+        groups.push(Group {
+            label: util::get_group_label(&plot_config.group_by, &key),
+            key,
+            values,
+            statistics,
+        });
+    }
+    eprintln!("Found {} groups", groups.len());
+
+    // Select the top groups by the sort order, then present them in that order. Groups are
+    // sorted by key when no top is given, so that e.g. hours of the day come in order.
+    let sort_by = plot_config.sort_by.unwrap_or(if plot_config.top.is_some() {
+        GroupOrder::Q3
+    } else {
+        GroupOrder::Key
+    });
+    util::sort_groups(
+        &mut groups,
+        sort_by,
+        plot_config.top,
+        |g| &g.key,
+        |g| &g.statistics,
+    );
+    Ok(groups)
+}
+
+/// A violin trace, which the plotly crate does not provide. Only the options we need are
+/// included.
+#[derive(Serialize, Clone)]
+struct Violin {
+    r#type: &'static str,
+    x: Vec<String>,
+    y: Vec<f64>,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    visible: Option<plotly::common::Visible>,
+    #[serde(rename = "xaxis", skip_serializing_if = "Option::is_none")]
+    x_axis: Option<String>,
+    #[serde(rename = "yaxis", skip_serializing_if = "Option::is_none")]
+    y_axis: Option<String>,
+    line: Line,
+    #[serde(rename = "box")]
+    box_: ViolinVisible,
+    #[serde(rename = "meanline")]
+    mean_line: ViolinVisible,
+    points: bool,
+    #[serde(rename = "spanmode")]
+    span_mode: &'static str,
+}
+
+#[derive(Serialize, Clone)]
+struct ViolinVisible {
+    visible: bool,
+}
+
+impl Trace for Violin {
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate, Timelike};
+use plotly::{HeatMap, Trace};
+
+use crate::config::{AggregationMode, CalendarRows};
+use crate::{config, plot::*, util, util::Result};
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+pub fn calendar_heatmap_plot(
+    context: &PlotContext,
+    plot_config: &config::CalendarHeatmapPlot,
+) -> Result<Vec<Box<dyn Trace>>> {
+    let common_cfg = &context.plot_config;
+    if plot_config.statistic.is_time_weighted() {
+        return Err("Time-weighted statistics are not supported for calendar heatmaps".into());
+    }
+    let (timestamp, value) = util::get_samples(
+        &context.conn,
+        common_cfg.filter.as_ref().and_then(|f| f.start.as_deref()),
+        common_cfg.filter.as_ref().and_then(|f| f.end.as_deref()),
+        common_cfg.filter.as_ref(),
+        &plot_config.column,
+        &plot_config.table,
+    )?;
+
+    // Group the samples into cells by row (weekday or date) and hour of the day, in the time
+    // zone of the plot, which lines up with the work hours filter.
+    let rows = plot_config.rows.unwrap_or(CalendarRows::Weekday);
+    let timezone = common_cfg.timezone();
+    let mut cells: BTreeMap<(i32, u32), Vec<u64>> = BTreeMap::new();
+    for (timestamp, value) in timestamp.iter().zip(value.iter()) {
+        let local_time = util::nanoseconds_epoch_to_local_datetime(*timestamp, timezone);
+        let row = match rows {
+            CalendarRows::Weekday => local_time.weekday().num_days_from_monday() as i32,
+            CalendarRows::Date => local_time.date().num_days_from_ce(),
+        };
+        cells
+            .entry((row, local_time.hour()))
+            .or_default()
+            .push(*value);
+    }
+    if let Some(min_count) = plot_config.min_count {
+        cells.retain(|_, values| values.len() >= min_count);
+    }
+
+    let row_keys: Vec<i32> = match rows {
+        CalendarRows::Weekday => (0..7).collect(),
+        CalendarRows::Date => match (cells.keys().next(), cells.keys().next_back()) {
+            (Some((first, _)), Some((last, _))) => (*first..=*last).collect(),
+            _ => Vec::new(),
+        },
+    };
+
+    let (cell_keys, mut cell_values): (Vec<(i32, u32)>, Vec<Vec<u64>>) = cells.into_iter().unzip();
+    util::sort_bins_inplace(&mut cell_values);
+    let statistics_per_cell = util::get_statistics_per_bin(&cell_values);
+    let cell_statistic = util::extract_statistic(&statistics_per_cell, &plot_config.statistic)?;
+    let cell_statistic = if plot_config.statistic == AggregationMode::Count {
+        cell_statistic
+    } else {
+        util::nanoseconds_duration_to_unit(&cell_statistic, plot_config.unit)
+    };
+
+    // Cells without samples are left empty rather than shown as zero.
+    let first_row = row_keys.first().copied().unwrap_or(0);
+    let mut z: Vec<Vec<Option<f64>>> = vec![vec![None; 24]; row_keys.len()];
+    for ((row, hour), statistic) in cell_keys.iter().zip(cell_statistic) {
+        z[(row - first_row) as usize][*hour as usize] = Some(statistic);
+    }
+
+    let row_labels: Vec<String> = row_keys
+        .iter()
+        .map(|row| match rows {
+            CalendarRows::Weekday => WEEKDAY_NAMES[*row as usize].to_string(),
+            CalendarRows::Date => NaiveDate::from_num_days_from_ce_opt(*row)
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+        })
+        .collect();
+
+    // Plotly draws the first row at the bottom, so reverse the rows to read from top to bottom.
+    let row_labels: Vec<String> = row_labels.into_iter().rev().collect();
+    let z: Vec<Vec<Option<f64>>> = z.into_iter().rev().collect();
+    let hours: Vec<u32> = (0..24).collect();
+
+    let mut trace = HeatMap::new(hours, row_labels, z).name(&common_cfg.name);
+    if let Some(visible) = &common_cfg.visible {
+        trace = trace.visible(visible.into());
+    }
+    if let Some(x_axis) = &common_cfg.x_axis {
+        trace = trace.x_axis(x_axis);
+    }
+    if let Some(y_axis) = &common_cfg.y_axis {
+        trace = trace.y_axis(y_axis);
+    }
+
+    Ok(vec![trace])
+}
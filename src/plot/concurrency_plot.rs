@@ -0,0 +1,80 @@
+use plotly::common::LineShape;
+use plotly::{Scatter, Trace};
+
+use crate::{config, plot::*, util, util::Result};
+
+pub fn concurrency_plot(
+    context: &PlotContext,
+    plot_config: &config::ConcurrencyPlot,
+) -> Result<Vec<Box<dyn Trace>>> {
+    let common_cfg = &context.plot_config;
+    let filter = common_cfg.filter.as_ref();
+    if filter.and_then(|f| f.work_hours).unwrap_or(false) {
+        return Err("The workhours filter is not supported for concurrency plots".into());
+    }
+
+    // The active_query_count table covers all queries, so a filtered subset of the queries has
+    // to be counted from the executions.
+    let has_where = filter.and_then(|f| f.sql_where.as_ref()).is_some();
+    let compute = match plot_config.compute {
+        Some(false) if has_where => {
+            return Err(
+                "A where filter requires computing the concurrency, set compute: true".into(),
+            )
+        }
+        Some(compute) => compute,
+        None => has_where || !util::table_exists(&context.conn, "active_query_count")?,
+    };
+
+    // Queries that started before the start of the filter but were still running count as
+    // active from the start, so the intervals and steps are read from before the start and
+    // then clipped to the filter.
+    let (start, end) = util::get_time_range(
+        &context.conn,
+        filter.and_then(|f| f.start.as_deref()),
+        filter.and_then(|f| f.end.as_deref()),
+    )?;
+    let (timestamp, count) = if compute {
+        let (start_time, duration) = util::get_interval_samples(
+            &context.conn,
+            start,
+            end,
+            filter,
+            "wallclock_time_ns",
+            "item_view_executor_execute",
+        )?;
+        let (mut timestamp, mut count) = util::get_active_query_counts(&start_time, &duration);
+        // No queries are active before the first one starts.
+        if let Some(start) = start {
+            if timestamp.first().is_none_or(|first| *first > start) {
+                timestamp.insert(0, start);
+                count.insert(0, 0);
+            }
+        }
+        (timestamp, count)
+    } else {
+        util::get_step_samples(&context.conn, start, end, "count", "active_query_count")?
+    };
+    let (timestamp, count) = util::clip_step_function(&timestamp, &count, start, end);
+
+    let segments = util::aggregate_and_segment_step_function(common_cfg, timestamp, count)?;
+
+    let line_color = util::get_line_color(context, common_cfg);
+    let mut traces: Vec<Box<dyn Trace>> = Vec::with_capacity(segments.len());
+    let mut first = true;
+    for (x, y) in segments {
+        let x = util::nanoseconds_epoch_to_plotly_time(&x);
+        let mut trace = Scatter::new(x, y);
+        trace = util::apply_common_plot_configuration(trace, common_cfg, line_color);
+
+        // Each value lasts until the next one.
+        trace = util::apply_default_line_shape(trace, common_cfg, line_color, LineShape::Hv);
+
+        trace = trace.show_legend(first);
+        first = false;
+
+        traces.push(trace)
+    }
+
+    Ok(traces)
+}
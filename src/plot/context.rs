@@ -10,6 +10,9 @@ pub struct PlotContext {
     pub color_gen: RefCell<DefaultColorGenerator>,
     /// The plot configuration.
     pub plot_config: config::PlotCommon,
+    /// Shapes to add to the layout once all traces have been plotted, such as
+    /// vertical lines marking change points.
+    pub shapes: RefCell<Vec<plotly::layout::Shape>>,
 }
 
 lazy_static! {
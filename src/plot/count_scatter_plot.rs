@@ -7,16 +7,63 @@ pub fn count_scatter_plot(
     plot_config: &config::CountScatterPlot,
 ) -> Result<Vec<Box<dyn Trace>>> {
     let common_cfg = &context.plot_config;
-    let (timestamp, count) = util::get_count_samples(&context.conn, common_cfg, plot_config)?;
-    let segments = util::aggregate_and_segment(common_cfg, timestamp, count);
+    let Some(group_by) = &plot_config.group_by else {
+        if plot_config.top.is_some() || plot_config.sort_by.is_some() {
+            return Err("top and sortby require groupby to be set".into());
+        }
+        let (timestamp, count) = util::get_count_samples(&context.conn, common_cfg, plot_config)?;
+        return get_traces(context, None, timestamp, count);
+    };
+
+    let groups = util::get_top_groups(
+        &context.conn,
+        common_cfg,
+        &plot_config.column,
+        &plot_config.table,
+        group_by,
+        plot_config.top,
+        plot_config.sort_by,
+    )?;
+    let mut traces: Vec<Box<dyn Trace>> = Vec::new();
+    for (key, samples) in groups {
+        let name = util::get_group_name(&common_cfg.name, group_by, &key);
+        traces.extend(get_traces(
+            context,
+            Some(&name),
+            samples.timestamp,
+            samples.value,
+        )?);
+    }
+    Ok(traces)
+}
+
+/// Get the traces of a single series.
+///
+/// # Arguments
+/// * `context` - The plot context.
+/// * `group_name` - The name of the series, if the samples are grouped.
+/// * `timestamp` - The timestamps of the samples.
+/// * `count` - The values of the samples.
+fn get_traces(
+    context: &PlotContext,
+    group_name: Option<&str>,
+    timestamp: Vec<i64>,
+    count: Vec<u64>,
+) -> Result<Vec<Box<dyn Trace>>> {
+    let common_cfg = &context.plot_config;
+    let segments = util::aggregate_and_segment(common_cfg, timestamp, count)?;
 
     let line_color = util::get_line_color(context, common_cfg);
+    util::mark_change_points(context, common_cfg, &segments, line_color)?;
     let mut traces: Vec<Box<dyn Trace>> = Vec::with_capacity(segments.len());
     let mut first = true;
     for (x, y) in segments {
         let x = util::nanoseconds_epoch_to_plotly_time(&x);
         let mut trace = Scatter::new(x, y);
         trace = util::apply_common_plot_configuration(trace, common_cfg, line_color);
+        if let Some(group_name) = group_name {
+            trace = trace.name(group_name).legend_group(group_name);
+        }
         trace = trace.show_legend(first);
         first = false;
 
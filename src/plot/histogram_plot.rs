@@ -0,0 +1,113 @@
+use plotly::common::LineShape;
+use plotly::{Scatter, Trace};
+
+use crate::config::HistogramNormalization;
+use crate::{config, plot::*, util, util::Result};
+
+pub fn histogram_plot(
+    context: &PlotContext,
+    plot_config: &config::HistogramPlot,
+) -> Result<Vec<Box<dyn Trace>>> {
+    let common_cfg = &context.plot_config;
+    let (timestamp, duration) = util::get_samples(
+        &context.conn,
+        common_cfg.filter.as_ref().and_then(|f| f.start.as_deref()),
+        common_cfg.filter.as_ref().and_then(|f| f.end.as_deref()),
+        common_cfg.filter.as_ref(),
+        &plot_config.column,
+        &plot_config.table,
+    )?;
+
+    // If an aggregation is configured, this is a histogram of the aggregated values, e.g. of
+    // the hourly median.
+    let values: Vec<f64> = util::aggregate_and_segment(common_cfg, timestamp, duration)?
+        .into_iter()
+        .flat_map(|(_, y)| util::nanoseconds_duration_to_unit(&y, plot_config.unit))
+        .collect();
+
+    let log_bins = plot_config.log_bins.unwrap_or(false);
+    let values: Vec<f64> = if log_bins {
+        let positive: Vec<f64> = values.iter().copied().filter(|v| *v > 0.0).collect();
+        if positive.len() < values.len() {
+            eprintln!(
+                "Ignoring {} non-positive values for logarithmic bins",
+                values.len() - positive.len()
+            );
+        }
+        positive
+    } else {
+        values
+    };
+    if values.is_empty() {
+        eprintln!("No values to plot in histogram");
+        return Ok(Vec::new());
+    }
+
+    let min = plot_config
+        .min
+        .unwrap_or_else(|| values.iter().copied().fold(f64::INFINITY, f64::min));
+    let max = plot_config
+        .max
+        .unwrap_or_else(|| values.iter().copied().fold(f64::NEG_INFINITY, f64::max));
+    let edges = util::get_bin_edges(
+        min,
+        max,
+        plot_config.bins.unwrap_or(100),
+        plot_config.bin_width,
+        log_bins,
+    )?;
+    let counts = util::make_histogram(&values, &edges);
+
+    let total = values.len() as f64;
+    let normalization = plot_config
+        .normalization
+        .unwrap_or(HistogramNormalization::Count);
+    let (y, line_shape) = match normalization {
+        HistogramNormalization::Count => {
+            (to_steps(counts.iter().map(|c| *c as f64)), LineShape::Hv)
+        }
+        HistogramNormalization::Probability => (
+            to_steps(counts.iter().map(|c| *c as f64 / total)),
+            LineShape::Hv,
+        ),
+        HistogramNormalization::Density => (
+            to_steps(
+                counts
+                    .iter()
+                    .zip(edges.windows(2))
+                    .map(|(c, edge)| *c as f64 / total / (edge[1] - edge[0])),
+            ),
+            LineShape::Hv,
+        ),
+        HistogramNormalization::Cdf => {
+            // The value at each edge is the fraction of samples below it, so the CDF starts at
+            // zero on the first edge. Values outside the range count towards the CDF as well.
+            let below = values.iter().filter(|v| **v < edges[0]).count() as f64;
+            let mut cumulative = below;
+            let mut y = vec![below / total];
+            for count in counts.iter() {
+                cumulative += *count as f64;
+                y.push(cumulative / total);
+            }
+            (y, LineShape::Linear)
+        }
+    };
+
+    let line_color = util::get_line_color(context, common_cfg);
+    let mut trace = Scatter::new(edges, y);
+    trace = util::apply_common_plot_configuration(trace, common_cfg, line_color);
+
+    trace = util::apply_default_line_shape(trace, common_cfg, line_color, line_shape);
+
+    Ok(vec![trace])
+}
+
+/// Convert per-bin values to the y values of a step line through the bin edges. The last value
+/// is repeated so that the last bin is drawn up to its upper edge.
+fn to_steps<I: Iterator<Item = f64>>(values: I) -> Vec<f64> {
+    let mut y: Vec<f64> = values.collect();
+    if let Some(last) = y.last() {
+        y.push(*last);
+    }
+    y
+}
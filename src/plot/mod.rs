@@ -1,9 +1,21 @@
+pub use box_plot::*;
+pub use calendar_heatmap_plot::*;
+pub use concurrency_plot::*;
 pub use context::*;
 pub use count_scatter_plot::*;
+pub use histogram_plot::*;
 pub use overlap_plot::*;
+pub use sql_plot::*;
 pub use time_scatter_plot::*;
+pub use xy_plot::*;
 
+mod box_plot;
+mod calendar_heatmap_plot;
+mod concurrency_plot;
 mod context;
 mod count_scatter_plot;
+mod histogram_plot;
 mod overlap_plot;
+mod sql_plot;
 mod time_scatter_plot;
+mod xy_plot;
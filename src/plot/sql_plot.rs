@@ -0,0 +1,37 @@
+use plotly::{Scatter, Trace};
+
+use crate::{config, plot::*, util, util::Result};
+
+pub fn sql_plot(
+    context: &PlotContext,
+    plot_config: &config::SqlPlot,
+) -> Result<Vec<Box<dyn Trace>>> {
+    let common_cfg = &context.plot_config;
+    let series = util::get_sql_samples(&context.conn, common_cfg.filter.as_ref(), plot_config)?;
+
+    let mut traces: Vec<Box<dyn Trace>> = Vec::new();
+    for series in series {
+        let segments = util::aggregate_and_segment(common_cfg, series.timestamp, series.value)?;
+
+        // Each series gets its own color, unless the color is set in the configuration.
+        let line_color = util::get_line_color(context, common_cfg);
+        util::mark_change_points(context, common_cfg, &segments, line_color)?;
+        let name = match (common_cfg.name.is_empty(), series.name.is_empty()) {
+            (_, true) => common_cfg.name.clone(),
+            (true, false) => series.name,
+            (false, false) => format!("{} {}", common_cfg.name, series.name),
+        };
+        let mut first = true;
+        for (x, y) in segments {
+            let x = util::nanoseconds_epoch_to_plotly_time(&x);
+            let mut trace = Scatter::new(x, y);
+            trace = util::apply_common_plot_configuration(trace, common_cfg, line_color);
+            trace = trace.name(&name).legend_group(&name).show_legend(first);
+            first = false;
+
+            traces.push(trace)
+        }
+    }
+
+    Ok(traces)
+}
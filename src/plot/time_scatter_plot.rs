@@ -7,21 +7,65 @@ pub fn time_scatter_plot(
     plot_config: &config::TimeScatterPlot,
 ) -> Result<Vec<Box<dyn Trace>>> {
     let common_cfg = &context.plot_config;
-    let (timestamp, duration) = util::get_samples(
+    let Some(group_by) = &plot_config.group_by else {
+        if plot_config.top.is_some() || plot_config.sort_by.is_some() {
+            return Err("top and sortby require groupby to be set".into());
+        }
+        let (timestamp, duration) = util::get_samples(
+            &context.conn,
+            common_cfg.filter.as_ref().and_then(|f| f.start.as_deref()),
+            common_cfg.filter.as_ref().and_then(|f| f.end.as_deref()),
+            common_cfg.filter.as_ref(),
+            &plot_config.column,
+            &plot_config.table,
+        )?;
+
+        return get_traces(context, plot_config, None, timestamp, duration);
+    };
+
+    let groups = util::get_top_groups(
         &context.conn,
-        common_cfg.filter.as_ref().and_then(|f| f.start.as_deref()),
-        common_cfg.filter.as_ref().and_then(|f| f.end.as_deref()),
-        common_cfg.filter.as_ref(),
+        common_cfg,
         &plot_config.column,
         &plot_config.table,
+        group_by,
+        plot_config.top,
+        plot_config.sort_by,
     )?;
+    let mut traces: Vec<Box<dyn Trace>> = Vec::new();
+    for (key, samples) in groups {
+        let name = util::get_group_name(&common_cfg.name, group_by, &key);
+        traces.extend(get_traces(
+            context,
+            plot_config,
+            Some(&name),
+            samples.timestamp,
+            samples.value,
+        )?);
+    }
+    Ok(traces)
+}
 
-    let (timestamp, duration) =
-        util::apply_workday_filter(timestamp, duration, common_cfg.filter.as_ref());
-
-    let segments = util::aggregate_and_segment(common_cfg, timestamp, duration);
+/// Get the traces of a single series.
+///
+/// # Arguments
+/// * `context` - The plot context.
+/// * `plot_config` - The configuration of the plot.
+/// * `group_name` - The name of the series, if the samples are grouped.
+/// * `timestamp` - The timestamps of the samples.
+/// * `duration` - The durations of the samples, in nanoseconds.
+fn get_traces(
+    context: &PlotContext,
+    plot_config: &config::TimeScatterPlot,
+    group_name: Option<&str>,
+    timestamp: Vec<i64>,
+    duration: Vec<u64>,
+) -> Result<Vec<Box<dyn Trace>>> {
+    let common_cfg = &context.plot_config;
+    let segments = util::aggregate_and_segment(common_cfg, timestamp, duration)?;
 
     let line_color = util::get_line_color(context, common_cfg);
+    util::mark_change_points(context, common_cfg, &segments, line_color)?;
     let mut traces: Vec<Box<dyn Trace>> = Vec::with_capacity(segments.len());
     let mut first = true;
     for (x, y) in segments.iter() {
@@ -31,6 +75,9 @@ pub fn time_scatter_plot(
         let mut trace = Scatter::new(x, y);
 
         trace = util::apply_common_plot_configuration(trace, common_cfg, line_color);
+        if let Some(group_name) = group_name {
+            trace = trace.name(group_name).legend_group(group_name);
+        }
 
         trace = trace.show_legend(first);
         first = false;
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use plotly::common::{
+    ColorBar, ColorScale, ColorScalePalette, DashType, Line, Marker, Mode, Title,
+};
+use plotly::{Scatter, Trace};
+use rusqlite::types::Value;
+use serde::Serialize;
+
+use crate::config::XyStyle;
+use crate::{config, plot::*, util, util::Result};
+
+/// A numeric value on the color scale of a scatter plot, see `colorby`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(transparent)]
+struct ColorValue(f64);
+
+impl plotly::color::Color for ColorValue {}
+
+pub fn xy_plot(context: &PlotContext, plot_config: &config::XyPlot) -> Result<Vec<Box<dyn Trace>>> {
+    let common_cfg = &context.plot_config;
+    let samples = util::get_xy_samples(&context.conn, common_cfg.filter.as_ref(), plot_config)?;
+    if samples.x.is_empty() {
+        eprintln!("No values to plot in xy plot");
+        return Ok(vec![]);
+    }
+
+    // Unlike the other plots, the values are not necessarily durations, so they are only
+    // converted if a unit is given.
+    let x = match plot_config.x_unit {
+        Some(unit) => util::nanoseconds_duration_to_unit(&samples.x, Some(unit)),
+        None => samples.x,
+    };
+    let y = match plot_config.y_unit {
+        Some(unit) => util::nanoseconds_duration_to_unit(&samples.y, Some(unit)),
+        None => samples.y,
+    };
+
+    let mut traces: Vec<Box<dyn Trace>> = Vec::new();
+    match plot_config.style.unwrap_or(XyStyle::Scatter) {
+        XyStyle::Scatter => {
+            traces.extend(get_scatter_traces(
+                context,
+                plot_config,
+                &x,
+                &y,
+                samples.color,
+            ));
+        }
+        XyStyle::Histogram => {
+            if plot_config.color_by.is_some() {
+                return Err("colorby is only supported for xy plots with the scatter style".into());
+            }
+            let histogram = util::make_histogram_2d(&x, &y, &plot_config.bins)?;
+            let trace = util::get_log_count_heat_map(histogram, common_cfg);
+            traces.push(trace);
+        }
+    }
+
+    if plot_config.regression.unwrap_or(false) {
+        traces.push(get_regression_trace(context, &x, &y));
+    }
+    Ok(traces)
+}
+
+/// Get the traces of a scatter plot. Samples colored by a numeric column are drawn on a color
+/// scale, and samples colored by any other column get one trace per distinct value.
+fn get_scatter_traces(
+    context: &PlotContext,
+    plot_config: &config::XyPlot,
+    x: &[f64],
+    y: &[f64],
+    color: Vec<Value>,
+) -> Vec<Box<dyn Trace>> {
+    let common_cfg = &context.plot_config;
+    let new_trace = |x: Vec<f64>, y: Vec<f64>, line_color: &str| {
+        let trace = Scatter::new(x, y).mode(Mode::Markers);
+        util::apply_common_plot_configuration(trace, common_cfg, line_color)
+    };
+
+    let Some(color_by) = &plot_config.color_by else {
+        let line_color = util::get_line_color(context, common_cfg);
+        let trace = new_trace(x.to_vec(), y.to_vec(), line_color)
+            .marker(Marker::new().color(line_color.to_owned()));
+        return vec![trace];
+    };
+
+    let numeric = color
+        .iter()
+        .all(|value| matches!(value, Value::Integer(_) | Value::Real(_) | Value::Null));
+    if numeric {
+        let color = color
+            .iter()
+            .map(|value| match value {
+                Value::Integer(value) => ColorValue(*value as f64),
+                Value::Real(value) => ColorValue(*value),
+                _ => ColorValue(f64::NAN),
+            })
+            .collect();
+        let line_color = util::get_line_color(context, common_cfg);
+        let marker = Marker::new()
+            .color_array(color)
+            .color_scale(ColorScale::Palette(ColorScalePalette::Viridis))
+            .show_scale(true)
+            .color_bar(ColorBar::new().title(Title::new(color_by)));
+        return vec![new_trace(x.to_vec(), y.to_vec(), line_color).marker(marker)];
+    }
+
+    // The groups are kept in the order in which they first appear.
+    let mut groups: Vec<(String, Vec<f64>, Vec<f64>)> = Vec::new();
+    let mut group_index: HashMap<String, usize> = HashMap::new();
+    for ((x, y), value) in x.iter().zip(y).zip(color) {
+        let key = match value {
+            Value::Null => "null".to_string(),
+            Value::Integer(value) => value.to_string(),
+            Value::Real(value) => value.to_string(),
+            Value::Text(value) => value,
+            Value::Blob(_) => "blob".to_string(),
+        };
+        let index = *group_index.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, Vec::new(), Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(*x);
+        groups[index].2.push(*y);
+    }
+    groups
+        .into_iter()
+        .map(|(key, x, y)| {
+            let line_color = util::get_line_color(context, common_cfg);
+            let name = util::get_group_name(&common_cfg.name, color_by, &key);
+            new_trace(x, y, line_color)
+                .marker(Marker::new().color(line_color.to_owned()))
+                .name(&name)
+                .legend_group(&name) as Box<dyn Trace>
+        })
+        .collect()
+}
+
+/// Get a least-squares regression line of y on x, over the range of the x values. The name of
+/// the line gives its coefficient of determination.
+fn get_regression_trace(context: &PlotContext, x: &[f64], y: &[f64]) -> Box<dyn Trace> {
+    let n = x.len() as f64;
+    let mean_x = util::kahan_sum(x.iter()) / n;
+    let mean_y = util::kahan_sum(y.iter()) / n;
+    let (mut sum_xy, mut sum_xx, mut sum_yy) = (0.0, 0.0, 0.0);
+    for (x, y) in x.iter().zip(y) {
+        sum_xy += (x - mean_x) * (y - mean_y);
+        sum_xx += (x - mean_x) * (x - mean_x);
+        sum_yy += (y - mean_y) * (y - mean_y);
+    }
+    let slope = if sum_xx > 0.0 { sum_xy / sum_xx } else { 0.0 };
+    let intercept = mean_y - slope * mean_x;
+    let r_squared = if sum_xx > 0.0 && sum_yy > 0.0 {
+        sum_xy * sum_xy / (sum_xx * sum_yy)
+    } else {
+        0.0
+    };
+
+    let x_min = x.iter().copied().fold(f64::INFINITY, f64::min);
+    let x_max = x.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let common_cfg = &context.plot_config;
+    let name = match common_cfg.name.is_empty() {
+        true => format!("Linear fit (R² = {:.3})", r_squared),
+        false => format!("{} linear fit (R² = {:.3})", common_cfg.name, r_squared),
+    };
+    let mut trace = Scatter::new(
+        vec![x_min, x_max],
+        vec![intercept + slope * x_min, intercept + slope * x_max],
+    )
+    .mode(Mode::Lines)
+    .name(name)
+    .line(
+        Line::new()
+            .color(util::get_line_color(context, common_cfg).to_owned())
+            .dash(DashType::Dash),
+    );
+    if let Some(x_axis) = &common_cfg.x_axis {
+        trace = trace.x_axis(x_axis);
+    }
+    if let Some(y_axis) = &common_cfg.y_axis {
+        trace = trace.y_axis(y_axis);
+    }
+    trace
+}
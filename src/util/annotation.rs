@@ -0,0 +1,281 @@
+use std::path::Path;
+
+use plotly::layout::{Annotation, Shape, ShapeLayer, ShapeLine, ShapeType};
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+
+use crate::config;
+use crate::util::{self, Result};
+
+/// The color of annotations without a `color`.
+const DEFAULT_ANNOTATION_COLOR: &str = "#7f7f7f";
+
+/// An event to mark in the plots, at a point in time or over a range of time.
+#[derive(Debug, PartialEq)]
+pub(crate) struct TimeAnnotation {
+    /// The start of the event, in nanoseconds since the Unix epoch.
+    pub start: i64,
+    /// The end of the event, if it is a range rather than a point in time.
+    pub end: Option<i64>,
+    pub label: Option<String>,
+    pub color: String,
+}
+
+/// Read the annotations of an entry under `annotations` in the configuration.
+///
+/// # Arguments
+/// * `conn` - A connection to the database, for `sql` and for times relative to its data.
+/// * `annotation` - The configuration of the entry.
+/// * `base_dir` - The directory that a `csv` file is relative to.
+pub(crate) fn get_annotations(
+    conn: &Connection,
+    annotation: &config::Annotation,
+    base_dir: &Path,
+) -> Result<Vec<TimeAnnotation>> {
+    let color = annotation
+        .color
+        .as_deref()
+        .unwrap_or(DEFAULT_ANNOTATION_COLOR);
+    if annotation.start.is_none() && (annotation.end.is_some() || annotation.label.is_some()) {
+        return Err("Annotations from csv or sql get their end and label from the columns".into());
+    }
+    let annotations = match (&annotation.start, &annotation.csv, &annotation.sql) {
+        (Some(start), None, None) => {
+            let start = parse_annotation_time(conn, start, false)?;
+            let end = annotation
+                .end
+                .as_deref()
+                .map(|end| parse_annotation_time(conn, end, true))
+                .transpose()?;
+            vec![new_annotation(start, end, annotation.label.clone(), color)?]
+        }
+        (None, Some(csv), None) => read_csv_annotations(conn, &base_dir.join(csv), color)?,
+        (None, None, Some(sql)) => read_sql_annotations(conn, sql, color)?,
+        _ => {
+            return Err(
+                "An annotation needs exactly one of start, csv or sql to give its times".into(),
+            )
+        }
+    };
+    Ok(annotations)
+}
+
+fn new_annotation(
+    start: i64,
+    end: Option<i64>,
+    label: Option<String>,
+    color: &str,
+) -> Result<TimeAnnotation> {
+    if end.is_some_and(|end| end < start) {
+        return Err(format!(
+            "The annotation {} ends before it starts",
+            label.as_deref().unwrap_or_default()
+        )
+        .into());
+    }
+    Ok(TimeAnnotation {
+        start,
+        end,
+        label: label.filter(|label| !label.is_empty()),
+        color: color.to_string(),
+    })
+}
+
+/// Parse the time of an annotation, either a time expression or a timestamp in nanoseconds since
+/// the Unix epoch. The end of a range is the end of the period that its time expression covers.
+fn parse_annotation_time(conn: &Connection, time: &str, is_end: bool) -> Result<i64> {
+    let time = time.trim();
+    // A year has four digits, anything longer is a timestamp.
+    if time.len() > 4 {
+        if let Ok(timestamp) = time.parse::<i64>() {
+            return Ok(timestamp);
+        }
+    }
+    let datetime = if is_end {
+        util::parse_time_ceil(Some(conn), time)
+    } else {
+        util::parse_time_floor(Some(conn), time)
+    }
+    .map_err(|e| format!("Invalid annotation time {}: {}", time, e))?;
+    datetime
+        .timestamp_nanos_opt()
+        .ok_or_else(|| format!("The annotation time {} is out of range", time).into())
+}
+
+fn read_csv_annotations(
+    conn: &Connection,
+    path: &Path,
+    color: &str,
+) -> Result<Vec<TimeAnnotation>> {
+    let error = |e: &dyn std::fmt::Display| {
+        format!("Error reading annotations from {}: {}", path.display(), e)
+    };
+    let mut reader = csv::Reader::from_path(path).map_err(|e| error(&e))?;
+    let headers = reader.headers().map_err(|e| error(&e))?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim() == name);
+    let start_column = column("start").ok_or_else(|| error(&"There is no start column"))?;
+    let (end_column, label_column) = (column("end"), column("label"));
+
+    let mut annotations = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| error(&e))?;
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| record.get(index))
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+        };
+        let Some(start) = field(Some(start_column)) else {
+            continue;
+        };
+        let start = parse_annotation_time(conn, start, false).map_err(|e| error(&e))?;
+        let end = field(end_column)
+            .map(|end| parse_annotation_time(conn, end, true))
+            .transpose()
+            .map_err(|e| error(&e))?;
+        let label = field(label_column).map(str::to_string);
+        annotations.push(new_annotation(start, end, label, color).map_err(|e| error(&e))?);
+    }
+    Ok(annotations)
+}
+
+fn read_sql_annotations(conn: &Connection, sql: &str, color: &str) -> Result<Vec<TimeAnnotation>> {
+    eprintln!("Executing query: {}", sql);
+    let mut stmt = conn
+        .prepare(sql)
+        .map_err(|e| format!("Error in annotation query {}: {}", sql, e))?;
+    let column = |name: &str| stmt.column_index(name).ok();
+    let start_column =
+        column("start").ok_or("The annotation query does not return a start column")?;
+    let (end_column, label_column) = (column("end"), column("label"));
+
+    let mut annotations = Vec::new();
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let time = |index: usize, is_end: bool| -> Result<Option<i64>> {
+            match row.get_ref(index)? {
+                ValueRef::Null => Ok(None),
+                ValueRef::Integer(timestamp) => Ok(Some(timestamp)),
+                ValueRef::Real(timestamp) => Ok(Some(timestamp as i64)),
+                ValueRef::Text(time) => {
+                    parse_annotation_time(conn, &String::from_utf8_lossy(time), is_end).map(Some)
+                }
+                ValueRef::Blob(_) => Err("Annotation times can not be blobs".into()),
+            }
+        };
+        let Some(start) = time(start_column, false)? else {
+            continue;
+        };
+        let end = match end_column {
+            Some(index) => time(index, true)?,
+            None => None,
+        };
+        let label = match label_column {
+            Some(index) => row.get::<_, Option<String>>(index)?,
+            None => None,
+        };
+        annotations.push(new_annotation(start, end, label, color)?);
+    }
+    Ok(annotations)
+}
+
+/// Add annotations to a plot layout, as vertical lines or shaded ranges with their labels at the
+/// top, in each of the given subplots.
+///
+/// # Arguments
+/// * `layout` - The plot layout.
+/// * `annotations` - The annotations to add.
+/// * `axes` - The x- and y-axis of each subplot with a time axis, e.g. `("x", "y2")`.
+pub(crate) fn add_annotations(
+    layout: &mut plotly::Layout,
+    annotations: &[TimeAnnotation],
+    axes: &[(String, String)],
+) {
+    for (x_ref, y_ref) in axes {
+        let y_domain = format!("{} domain", y_ref);
+        for annotation in annotations {
+            let start = util::nanoseconds_epoch_to_milliseconds(annotation.start);
+            let shape = Shape::new()
+                .x_ref(x_ref)
+                .y_ref(&y_domain)
+                .x0(start)
+                .y0(0.0)
+                .y1(1.0);
+            let shape = match annotation.end {
+                Some(end) => shape
+                    .shape_type(ShapeType::Rect)
+                    .x1(util::nanoseconds_epoch_to_milliseconds(end))
+                    .layer(ShapeLayer::Below)
+                    .fill_color(annotation.color.clone())
+                    .opacity(0.2)
+                    .line(ShapeLine::new().width(0.0)),
+                None => shape
+                    .shape_type(ShapeType::Line)
+                    .x1(start)
+                    .line(ShapeLine::new().color(annotation.color.clone()).width(1.0)),
+            };
+            layout.add_shape(shape);
+
+            if let Some(label) = &annotation.label {
+                layout.add_annotation(
+                    Annotation::new()
+                        .x_ref(x_ref)
+                        .y_ref(&y_domain)
+                        .x(start)
+                        .y(1.0)
+                        .x_anchor(plotly::common::Anchor::Left)
+                        .y_anchor(plotly::common::Anchor::Top)
+                        .text(label)
+                        .show_arrow(false)
+                        .font(plotly::common::Font::new().color(annotation.color.clone())),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_annotations() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "create table events (timestamp integer, message text);
+             insert into events values (1704067200000000000, 'Upgrade'), (null, 'Ignored');",
+        )
+        .unwrap();
+        let annotation =
+            |start: Option<&str>, end: Option<&str>, sql: Option<&str>| config::Annotation {
+                start: start.map(str::to_string),
+                end: end.map(str::to_string),
+                label: None,
+                csv: None,
+                sql: sql.map(str::to_string),
+                color: None,
+            };
+
+        let range = annotation(Some("2024-01-01"), Some("2024-01-01"), None);
+        let range = get_annotations(&conn, &range, Path::new(".")).unwrap();
+        assert_eq!(range[0].start, 1704067200000000000);
+        assert_eq!(range[0].end, Some(1704153599000000000));
+
+        let sql = "select timestamp as start, message as label from events";
+        let events = get_annotations(&conn, &annotation(None, None, Some(sql)), Path::new("."));
+        assert_eq!(
+            events.unwrap(),
+            vec![TimeAnnotation {
+                start: 1704067200000000000,
+                end: None,
+                label: Some("Upgrade".to_string()),
+                color: DEFAULT_ANNOTATION_COLOR.to_string(),
+            }]
+        );
+
+        let backwards = annotation(Some("2024-02"), Some("2024-01"), None);
+        assert!(get_annotations(&conn, &backwards, Path::new(".")).is_err());
+        let ambiguous = annotation(Some("2024-02"), None, Some(sql));
+        assert!(get_annotations(&conn, &ambiguous, Path::new(".")).is_err());
+    }
+}
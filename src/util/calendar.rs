@@ -0,0 +1,209 @@
+use std::path::Path;
+
+use chrono::{Duration, NaiveDate, TimeZone};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::Connection;
+
+use crate::util::Result;
+
+/// Register the SQL functions that the filters of work-hour calendars use.
+///
+/// `local_datetime(timestamp, timezone)` converts a timestamp in nanoseconds since the Unix epoch
+/// to the local date and time `YYYY-MM-DD HH:MM:SS` in a time zone from the IANA time zone
+/// database, since SQLite itself only knows about UTC and the time zone of the computer.
+pub(crate) fn register_calendar_functions(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function(
+        "local_datetime",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let timestamp: Option<i64> = ctx.get(0)?;
+            let timezone: String = ctx.get(1)?;
+            let timezone: chrono_tz::Tz = timezone.parse().map_err(|_| {
+                rusqlite::Error::UserFunctionError(format!("Invalid time zone {}", timezone).into())
+            })?;
+            Ok(timestamp.map(|timestamp| {
+                timezone
+                    .timestamp_nanos(timestamp)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            }))
+        },
+    )?;
+    Ok(())
+}
+
+/// Parse the work hours of a day, as comma-separated time ranges such as
+/// `"08:00-12:00, 13:00-17:00"`. Minutes are optional, and `24:00` ends a range at midnight.
+///
+/// # Returns
+/// * The start and end of each range, formatted as `HH:MM:SS` so that they can be compared with
+///   the output of SQLite's `time` function.
+pub(crate) fn parse_work_hours(hours: &str) -> Result<Vec<(String, String)>> {
+    let mut ranges = Vec::new();
+    for range in hours.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid work hours {}, expected e.g. 08:00-17:00", range))?;
+        let start = parse_time_of_day(start.trim())?;
+        let end = parse_time_of_day(end.trim())?;
+        if start >= end {
+            return Err(format!(
+                "Invalid work hours {}, the end is not after the start",
+                range
+            )
+            .into());
+        }
+        ranges.push((format_time_of_day(start), format_time_of_day(end)));
+    }
+    Ok(ranges)
+}
+
+/// Parse a time of day `HH[:MM]` into minutes since midnight.
+fn parse_time_of_day(time: &str) -> Result<u32> {
+    let (hours, minutes) = time.split_once(':').unwrap_or((time, "0"));
+    let hours: u32 = hours
+        .parse()
+        .map_err(|_| format!("Invalid time of day {}", time))?;
+    let minutes: u32 = minutes
+        .parse()
+        .map_err(|_| format!("Invalid time of day {}", time))?;
+    if minutes >= 60 || hours * 60 + minutes > 24 * 60 {
+        return Err(format!("Invalid time of day {}", time).into());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+fn format_time_of_day(minutes: u32) -> String {
+    format!("{:02}:{:02}:00", minutes / 60, minutes % 60)
+}
+
+/// Parse a holiday `YYYY-MM-DD`.
+pub(crate) fn parse_holiday(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|e| format!("Invalid holiday {}: {}", date, e).into())
+}
+
+/// Read holidays from a file. iCalendar files (`.ics`) add the dates of every event, where the
+/// end date of an all-day event is exclusive. Recurrence rules are not expanded, so recurring
+/// holidays have to be listed for every year. Any other file is read as CSV with the date in
+/// the first column, with an optional header row.
+///
+/// # Returns
+/// * The holidays, formatted as `YYYY-MM-DD`.
+pub(crate) fn read_holiday_file(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Error reading holiday file {}: {}", path.display(), e))?;
+    let is_ical = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ics"));
+    let holidays = if is_ical {
+        read_ical_holidays(&content)
+    } else {
+        read_csv_holidays(&content)
+    };
+    holidays.map_err(|e| format!("Error reading holiday file {}: {}", path.display(), e).into())
+}
+
+fn read_ical_holidays(content: &str) -> Result<Vec<String>> {
+    // Long lines are folded by starting the continuation lines with a space or a tab.
+    let unfolded = content
+        .replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+    let mut holidays = Vec::new();
+    let mut start: Option<NaiveDate> = None;
+    let mut end: Option<NaiveDate> = None;
+    for line in unfolded.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Parameters such as `;VALUE=DATE` follow the property name.
+        let name = name.split(';').next().unwrap();
+        match name {
+            "BEGIN" if value == "VEVENT" => {
+                start = None;
+                end = None;
+            }
+            "DTSTART" => start = Some(parse_ical_date(value)?),
+            // Only all-day events have an end date that is not a day of the event.
+            "DTEND" if value.len() == 8 => end = Some(parse_ical_date(value)?),
+            "END" if value == "VEVENT" => {
+                let start = start.ok_or("Event without DTSTART")?;
+                let end = end.unwrap_or(start + Duration::days(1));
+                let mut date = start;
+                while date < end {
+                    holidays.push(date.format("%Y-%m-%d").to_string());
+                    date += Duration::days(1);
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(holidays)
+}
+
+/// Parse the date of an iCalendar date `YYYYMMDD` or date-time `YYYYMMDDTHHMMSS`.
+fn parse_ical_date(value: &str) -> Result<NaiveDate> {
+    value
+        .get(..8)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .ok_or_else(|| format!("Invalid date {}", value).into())
+}
+
+fn read_csv_holidays(content: &str) -> Result<Vec<String>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let mut holidays = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let Some(date) = record.get(0).filter(|date| !date.trim().is_empty()) else {
+            continue;
+        };
+        match parse_holiday(date) {
+            Ok(date) => holidays.push(date.format("%Y-%m-%d").to_string()),
+            // The first row may be a header.
+            Err(_) if index == 0 => (),
+            Err(e) => return Err(format!("Line {}: {}", index + 1, e).into()),
+        }
+    }
+    Ok(holidays)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_work_hours() {
+        let ranges = parse_work_hours("8-12, 13:30-24").unwrap();
+        assert_eq!(
+            ranges,
+            vec![
+                ("08:00:00".to_string(), "12:00:00".to_string()),
+                ("13:30:00".to_string(), "24:00:00".to_string())
+            ]
+        );
+        assert!(parse_work_hours("17-8").is_err());
+        assert!(parse_work_hours("08:60-17").is_err());
+    }
+
+    #[test]
+    fn test_read_holidays() {
+        let ical = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20241224\r\n\
+                    DTEND;VALUE=DATE:20241227\r\nSUMMARY:Christmas\r\nEND:VEVENT\r\n\
+                    BEGIN:VEVENT\r\nDTSTART:20250101T000000Z\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        assert_eq!(
+            read_ical_holidays(ical).unwrap(),
+            vec!["2024-12-24", "2024-12-25", "2024-12-26", "2025-01-01"]
+        );
+        let csv = "date,name\n2024-12-25,Christmas\n\n2025-01-01,New Year\n";
+        assert_eq!(
+            read_csv_holidays(csv).unwrap(),
+            vec!["2024-12-25", "2025-01-01"]
+        );
+        assert!(read_csv_holidays("2024-12-25\nChristmas\n").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{config, util};
+
+/// The default multiplier for `get_change_point_penalty`. The BIC (2.0) reports many spurious
+/// change points on the short and noisy series we get from aggregated execution times.
+pub(crate) const DEFAULT_PENALTY_FACTOR: f64 = 6.0;
+
+/// The default minimum number of values between two change points.
+pub(crate) const DEFAULT_MIN_SEGMENT_LENGTH: usize = 2;
+
+/// Detect relative changes in a series of non-negative values, such as execution times or
+/// counts, using the settings from the configuration.
+///
+/// Such values typically vary multiplicatively, so the change points are detected in the
+/// logarithm of the values. This also makes the noise level similar before and after a change.
+///
+/// # Returns
+/// The indices of the values that start a new segment, in increasing order.
+pub(crate) fn detect_relative_change_points(
+    values: &[f64],
+    change_point_cfg: &config::ChangePoints,
+) -> Vec<usize> {
+    let log_values: Vec<f64> = values.iter().map(|v| v.ln_1p()).collect();
+    let penalty = get_change_point_penalty(
+        &log_values,
+        change_point_cfg.penalty.unwrap_or(DEFAULT_PENALTY_FACTOR),
+    );
+    detect_change_points(
+        &log_values,
+        penalty,
+        change_point_cfg
+            .min_segment
+            .unwrap_or(DEFAULT_MIN_SEGMENT_LENGTH),
+    )
+}
+
+/// Detect change points in the mean of a series, using the PELT (Pruned Exact Linear Time)
+/// algorithm by Killick et al. The series is split into segments so that the total squared
+/// deviation from each segment's mean, plus `penalty` for each additional segment, is minimized.
+/// A larger penalty results in fewer change points.
+///
+/// # Arguments
+/// * `values` - The series to find change points in, e.g. the daily median execution time.
+/// * `penalty` - The cost of adding a change point, in the same unit as the squared values. See
+///   `get_change_point_penalty` for a reasonable default.
+/// * `min_segment_length` - The minimum number of values in each segment.
+///
+/// # Returns
+/// The indices of the values that start a new segment, in increasing order.
+pub(crate) fn detect_change_points(
+    values: &[f64],
+    penalty: f64,
+    min_segment_length: usize,
+) -> Vec<usize> {
+    let n = values.len();
+    let min_segment_length = min_segment_length.max(1);
+    if n < 2 * min_segment_length {
+        return Vec::new();
+    }
+
+    // Prefix sums make the cost of any segment computable in constant time.
+    let mut sum = vec![0.0; n + 1];
+    let mut sum_of_squares = vec![0.0; n + 1];
+    for (i, value) in values.iter().enumerate() {
+        sum[i + 1] = sum[i] + value;
+        sum_of_squares[i + 1] = sum_of_squares[i] + value * value;
+    }
+    let cost = |start: usize, end: usize| {
+        let segment_sum = sum[end] - sum[start];
+        (sum_of_squares[end] - sum_of_squares[start])
+            - segment_sum * segment_sum / (end - start) as f64
+    };
+
+    // best_cost[t] is the minimal cost of segmenting values[0..t], and last_change[t] is the start
+    // of the last segment in that segmentation.
+    let mut best_cost = vec![f64::INFINITY; n + 1];
+    let mut last_change = vec![0; n + 1];
+    best_cost[0] = -penalty;
+    let mut candidates: Vec<usize> = vec![0];
+
+    for end in min_segment_length..=n {
+        for &start in candidates.iter() {
+            if end - start < min_segment_length {
+                continue;
+            }
+            let total = best_cost[start] + cost(start, end) + penalty;
+            if total < best_cost[end] {
+                best_cost[end] = total;
+                last_change[end] = start;
+            }
+        }
+
+        // Prune candidates that can never be the start of an optimal last segment.
+        candidates.retain(|&start| {
+            end - start < min_segment_length
+                || best_cost[start] + cost(start, end) <= best_cost[end]
+        });
+        if best_cost[end].is_finite() {
+            candidates.push(end);
+        }
+    }
+
+    let mut change_points = Vec::new();
+    let mut end = n;
+    while end > 0 {
+        let start = last_change[end];
+        if start > 0 {
+            change_points.push(start);
+        }
+        end = start;
+    }
+    change_points.reverse();
+    change_points
+}
+
+/// Get a default penalty for `detect_change_points`, based on the Bayesian information
+/// criterion. The noise variance is estimated robustly from the differences between consecutive
+/// values, so that the estimate is not inflated by the very shifts we are trying to detect. If
+/// more than half of the differences are zero, the standard deviation of the differences is used
+/// instead.
+///
+/// # Arguments
+/// * `values` - The series that change points will be detected in.
+/// * `factor` - Multiplier for the penalty. 2.0 corresponds to the BIC; larger values make the
+///   detection more conservative. See `DEFAULT_PENALTY_FACTOR`.
+pub(crate) fn get_change_point_penalty(values: &[f64], factor: f64) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mut differences: Vec<f64> = values.windows(2).map(|w| w[1] - w[0]).collect();
+    differences.sort_by(|a, b| a.total_cmp(b));
+    let mut spread = 1.4826 * util::get_median_absolute_deviation(&differences);
+    if spread == 0.0 {
+        // Most values repeat, as in a step-like series, so the standard deviation is the only
+        // estimate of the noise. It includes the shifts, which makes the penalty conservative.
+        spread = util::get_statistics(&differences).std_dev;
+    }
+    let sigma = spread / std::f64::consts::SQRT_2;
+    factor * sigma * sigma * (values.len() as f64).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_change_points() {
+        let mut values = vec![1.0, 1.1, 0.9, 1.0, 1.2, 0.8, 1.0, 1.1];
+        values.extend([5.0, 5.1, 4.9, 5.0, 5.2, 4.8, 5.0]);
+        values.extend([2.0, 2.1, 1.9, 2.0, 2.2, 1.8]);
+        let penalty = get_change_point_penalty(&values, 2.0);
+        assert_eq!(detect_change_points(&values, penalty, 2), vec![8, 15]);
+
+        // A constant series has no change points.
+        assert!(detect_change_points(&[1.0; 20], 1.0, 2).is_empty());
+
+        // A step with noise on a few windows, where most consecutive windows are equal.
+        let mut values = vec![10.0; 20];
+        values.extend([20.0; 20]);
+        values[5] = 10.5;
+        values[12] = 9.6;
+        values[30] = 20.4;
+        values[31] = 19.8;
+        let penalty = get_change_point_penalty(&values, 6.0);
+        assert!(penalty > 0.0);
+        assert_eq!(detect_change_points(&values, penalty, 2), vec![20]);
+
+        // Too short for two segments.
+        assert!(detect_change_points(&[1.0, 5.0, 1.0], 1.0, 2).is_empty());
+    }
+}
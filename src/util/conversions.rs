@@ -1,4 +1,6 @@
-use crate::config::TimeUnit;
+use chrono::{NaiveDate, NaiveDateTime, TimeZone};
+
+use crate::config::{TimeUnit, Timezone};
 use crate::util::TypeCast;
 
 /// Convert time values from nanoseconds to milliseconds.
@@ -6,7 +8,7 @@ use crate::util::TypeCast;
 /// # Arguments
 ///
 /// * `nanoseconds` - A vector of `i64` values, where each value represents a time in nanoseconds
-/// from the Unix epoch.
+///   from the Unix epoch.
 ///
 /// # Returns
 ///
@@ -16,16 +18,92 @@ pub(crate) fn nanoseconds_epoch_to_plotly_time(nanoseconds: &[i64]) -> Vec<f64>
     // with f64 values.
     nanoseconds
         .iter()
-        .map(|x| *x as f64 / 1_000_000.0)
+        .copied()
+        .map(nanoseconds_epoch_to_milliseconds)
         .collect()
 }
 
+/// Convert a single time value from nanoseconds to milliseconds, the unit that Plotly uses for
+/// time, see `nanoseconds_epoch_to_plotly_time`.
+pub(crate) fn nanoseconds_epoch_to_milliseconds(nanoseconds: i64) -> f64 {
+    nanoseconds as f64 / 1_000_000.0
+}
+
+/// Format a timestamp in the same format (and time zone) as accepted by the `start` and `end`
+/// filters, so that times printed by tracetool can be used directly in further queries.
+///
+/// # Arguments
+///
+/// * `nanoseconds` - The time in nanoseconds from the Unix epoch.
+pub(crate) fn nanoseconds_epoch_to_string(nanoseconds: i64) -> String {
+    chrono::Utc
+        .timestamp_nanos(nanoseconds)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Get the calendar date of a timestamp in a time zone.
+///
+/// # Arguments
+///
+/// * `nanoseconds` - The time in nanoseconds from the Unix epoch.
+/// * `timezone` - The time zone to get the date in.
+pub(crate) fn nanoseconds_epoch_to_date(nanoseconds: i64, timezone: Timezone) -> NaiveDate {
+    nanoseconds_epoch_to_local_datetime(nanoseconds, timezone).date()
+}
+
+/// Get the local date and time of a timestamp in a time zone.
+///
+/// # Arguments
+///
+/// * `nanoseconds` - The time in nanoseconds from the Unix epoch.
+/// * `timezone` - The time zone to get the date and time in.
+pub(crate) fn nanoseconds_epoch_to_local_datetime(
+    nanoseconds: i64,
+    timezone: Timezone,
+) -> NaiveDateTime {
+    match timezone {
+        Timezone::Local => chrono::Local.timestamp_nanos(nanoseconds).naive_local(),
+        Timezone::Named(tz) => tz.timestamp_nanos(nanoseconds).naive_local(),
+    }
+}
+
+/// Get the time at which a calendar date starts in a time zone. If midnight does not exist on
+/// that date, because a daylight saving time transition skips it, the first hour that does exist
+/// is used instead.
+///
+/// # Arguments
+///
+/// * `date` - The calendar date.
+/// * `timezone` - The time zone of the date.
+///
+/// # Returns
+///
+/// * The time in nanoseconds from the Unix epoch.
+pub(crate) fn date_to_nanoseconds_epoch(date: NaiveDate, timezone: Timezone) -> i64 {
+    match timezone {
+        Timezone::Local => get_start_of_day(&chrono::Local, date),
+        Timezone::Named(tz) => get_start_of_day(&tz, date),
+    }
+}
+
+fn get_start_of_day<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> i64 {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    (0..24)
+        .find_map(|hour| {
+            tz.from_local_datetime(&(midnight + chrono::Duration::hours(hour)))
+                .earliest()
+        })
+        .and_then(|time| time.timestamp_nanos_opt())
+        .expect("Date out of range")
+}
+
 /// Convert time values from nanoseconds to seconds.
 ///
 /// # Arguments
 ///
 /// * `nanoseconds` - A vector of `i64` values, where each value represents a time in
-/// nanoseconds from the Unix epoch.
+///   nanoseconds from the Unix epoch.
 ///
 /// # Returns
 ///
@@ -46,7 +124,7 @@ where
 ///
 /// * `duration` - A vector of `T` values, where each value represents a time in nanoseconds.
 /// * `unit` - The unit to convert the time values to. If `None`, the time values are
-/// converted to seconds.
+///   converted to seconds.
 ///
 /// # Returns
 ///
@@ -0,0 +1,135 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use plotly::Plot;
+
+use crate::util::Result;
+
+/// The environment variable that can be set to the path of the kaleido executable, if it is not
+/// in the `PATH`.
+const KALEIDO_VARIABLE: &str = "KALEIDO";
+
+/// The image size used when the layout does not set the width or height.
+const DEFAULT_IMAGE_WIDTH: usize = 1024;
+const DEFAULT_IMAGE_HEIGHT: usize = 768;
+
+/// Write a plot to a file instead of showing it in the browser. The format is given by the file
+/// extension: `html` writes a self-contained HTML page that includes plotly.js, while `png`,
+/// `jpeg`, `webp`, `svg` and `pdf` write a static image rendered by kaleido.
+///
+/// # Arguments
+/// * `plot` - The plot to write.
+/// * `path` - The file to write the plot to.
+/// * `width` - The width of static images, if set in the layout.
+/// * `height` - The height of static images, if set in the layout.
+pub(crate) fn write_plot(
+    plot: &mut Plot,
+    path: &Path,
+    width: Option<i32>,
+    height: Option<i32>,
+) -> Result<()> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+        .unwrap_or_default();
+    let contents = match extension.as_str() {
+        "html" | "htm" => {
+            plot.use_local_plotly();
+            // The page also loads MathJax from a CDN, which is only needed for LaTeX in labels.
+            // Leave it out so that the page works offline.
+            plot.to_html()
+                .lines()
+                .filter(|line| !line.contains("cdn.jsdelivr.net/npm/mathjax"))
+                .collect::<Vec<&str>>()
+                .join("\n")
+                .into_bytes()
+        }
+        "png" | "jpg" | "jpeg" | "webp" | "svg" | "pdf" => render_image(
+            plot,
+            if extension == "jpg" {
+                "jpeg"
+            } else {
+                &extension
+            },
+            width.map_or(DEFAULT_IMAGE_WIDTH, |width| width as usize),
+            height.map_or(DEFAULT_IMAGE_HEIGHT, |height| height as usize),
+        )?,
+        _ => {
+            return Err(format!(
+                "Unsupported output format for {}, expected html, png, jpeg, webp, svg or pdf",
+                path.display()
+            )
+            .into())
+        }
+    };
+    std::fs::write(path, contents)
+        .map_err(|e| format!("Error writing plot to {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Render a plot to a static image with kaleido, the renderer that plotly uses for image export.
+/// Kaleido is a separate executable, which is run with the plot on its standard input and
+/// responds with the image on its standard output.
+fn render_image(plot: &Plot, format: &str, width: usize, height: usize) -> Result<Vec<u8>> {
+    let kaleido = std::env::var(KALEIDO_VARIABLE).unwrap_or_else(|_| "kaleido".to_string());
+    let mut process = Command::new(&kaleido)
+        .args([
+            "plotly",
+            "--disable-gpu",
+            "--allow-file-access-from-files",
+            "--disable-extensions",
+            "--disable-local-file-accesses",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| {
+            format!(
+                "Static image export requires kaleido, which could not be started ({}). Install \
+                 it, e.g. with pip install kaleido==0.2.1, and add it to the PATH or set {} to \
+                 the path of the executable, or export to html instead",
+                e, KALEIDO_VARIABLE
+            )
+        })?;
+
+    let request = serde_json::json!({
+        "format": format,
+        "width": width,
+        "height": height,
+        "scale": 1.0,
+        "data": serde_json::from_str::<serde_json::Value>(&plot.to_json())?,
+    });
+    {
+        let mut stdin = process.stdin.take().unwrap();
+        writeln!(stdin, "{}", request)?;
+    }
+
+    // Kaleido first reports that it has started, and then responds to the request, each on a
+    // line of its own. Only the response has a result.
+    let stdout = process.stdout.take().unwrap();
+    let mut image: Option<Result<Vec<u8>>> = None;
+    for line in BufReader::new(stdout).lines() {
+        let response: serde_json::Value = serde_json::from_str(&line?)?;
+        if response["code"].as_i64().unwrap_or(0) != 0 {
+            image = Some(Err(format!(
+                "Kaleido failed to render the plot: {}",
+                response["message"].as_str().unwrap_or("unknown error")
+            )
+            .into()));
+            break;
+        }
+        if let Some(result) = response["result"].as_str() {
+            image = Some(match format {
+                "svg" => Ok(result.as_bytes().to_vec()),
+                _ => base64::decode(result).map_err(|e| e.into()),
+            });
+            break;
+        }
+    }
+    let _ = process.kill();
+    let _ = process.wait();
+    image.unwrap_or_else(|| Err("Kaleido exited without rendering the plot".into()))
+}
@@ -1,51 +1,310 @@
-use chrono::{Datelike, TimeZone, Timelike};
+use rusqlite::Connection;
 
-use crate::config::Filter;
+use crate::config::{Aggregation, Calendar, CalendarReference, Filter, Timezone, Transform};
+use crate::util::{self, Result};
 
-/// Filter data points that are not within work hours, if the filter configuration specifies that
-/// work hours should be used. Work hours are defined as 08 to 17 on weekdays.
+/*
+ * All filtering of samples is done in SQL, by adding the criteria from the functions below to the
+ * `where` clause of the queries that read the samples. This way every plot type and statistics
+ * command applies the filter configuration in the same way, and samples that are filtered out
+ * are never read from the database.
+ */
+
+/// Generate SQL criteria for common filter configuration.
 ///
 /// # Arguments
-/// * `timestamps` - A vector of `i64` values, where each value represents a time in nanoseconds
-/// from the Unix epoch.
-/// * `data` - A vector of `T` values, where each value represents a data point.
+/// * `conn` - A connection to the database, for start and end times relative to its data.
+/// * `start` - The start time for the query, see `parse_time_floor`. If `None`, the samples start
+///   at the beginning of the data.
+/// * `end` - The end time for the query, see `parse_time_ceil`. If `None`, the samples end at the
+///   end of the data.
 /// * `filter` - An optional `Filter` object that contains the filter configuration.
-pub(crate) fn apply_workday_filter<T: Copy>(
-    timestamps: Vec<i64>,
-    data: Vec<T>,
+/// * `timestamp_table` - The name of the table that contains the timestamp column. If `None`,
+///   no table qualifier is used, which would typically mean that SQLite gets the column from
+///   the only table in the query.
+///
+/// # Returns
+/// * A vector of strings, where each string represents an SQL criteria. These should be
+///   joined with `and` to form a complete SQL `where` clause.
+pub(crate) fn get_common_criteria(
+    conn: &Connection,
+    start: Option<&str>,
+    end: Option<&str>,
     filter: Option<&Filter>,
-) -> (Vec<i64>, Vec<T>) {
-    let filter_work_hours = match filter {
-        Some(filter) => filter.work_hours.unwrap_or(false),
-        None => false,
+    timestamp_table: Option<&str>,
+) -> Result<Vec<String>> {
+    let timestamp_column = match timestamp_table {
+        Some(table) => format!("{}.timestamp", table),
+        None => "timestamp".to_string(),
     };
-    if !filter_work_hours {
-        return (timestamps, data);
-    }
-
-    let mut result_timestamps = Vec::with_capacity(timestamps.len());
-    let mut result_data = Vec::with_capacity(data.len());
-    for i in 0..timestamps.len() {
-        let timestamp = timestamps[i];
-        let data = data[i];
-        if is_work_hours(timestamp) {
-            result_timestamps.push(timestamp);
-            result_data.push(data);
+    get_filter_criteria(conn, start, end, filter, &timestamp_column)
+}
+
+/// Generate SQL criteria for common filter configuration, for a query where the timestamp is
+/// not in the `timestamp` column.
+///
+/// # Arguments
+/// * `conn` - A connection to the database, for start and end times relative to its data.
+/// * `start` - The start time for the query, see `parse_time_floor`. If `None`, the samples start
+///   at the beginning of the data.
+/// * `end` - The end time for the query, see `parse_time_ceil`. If `None`, the samples end at the
+///   end of the data.
+/// * `filter` - An optional `Filter` object that contains the filter configuration.
+/// * `timestamp_column` - The column that contains the timestamp, in nanoseconds since the
+///   Unix epoch, possibly qualified with a table name.
+///
+/// # Returns
+/// * A vector of strings, where each string represents an SQL criteria.
+pub(crate) fn get_filter_criteria(
+    conn: &Connection,
+    start: Option<&str>,
+    end: Option<&str>,
+    filter: Option<&Filter>,
+    timestamp_column: &str,
+) -> Result<Vec<String>> {
+    let mut criteria: Vec<String> = Vec::new();
+    if let Some(sql_where) = filter.and_then(|f| f.sql_where.as_ref()) {
+        // The parentheses keep e.g. an `or` in the filter from applying to the other criteria.
+        criteria.push(format!("({})", sql_where));
+    }
+    let (start, end) = get_time_range(conn, start, end)?;
+    if let Some(start) = start {
+        criteria.push(format!("{} >= {}", timestamp_column, start));
+    }
+    if let Some(end) = end {
+        criteria.push(format!("{} <= {}", timestamp_column, end));
+    }
+    if filter.and_then(|f| f.work_hours).unwrap_or(false) {
+        let calendar = match filter.and_then(|f| f.calendar.as_ref()) {
+            Some(CalendarReference::Calendar(calendar)) => calendar,
+            Some(CalendarReference::Name(name)) => {
+                return Err(format!("Unknown calendar {}", name).into())
+            }
+            None => &Calendar::default(),
+        };
+        criteria.push(get_work_hours_criteria(calendar, timestamp_column)?);
+    }
+    Ok(criteria)
+}
+
+/// Parse the start and end of a filter into nanoseconds since the Unix epoch.
+///
+/// # Arguments
+/// * `conn` - A connection to the database, for start and end times relative to its data.
+/// * `start` - The start time, see `parse_time_floor`.
+/// * `end` - The end time, see `parse_time_ceil`.
+pub(crate) fn get_time_range(
+    conn: &Connection,
+    start: Option<&str>,
+    end: Option<&str>,
+) -> Result<(Option<i64>, Option<i64>)> {
+    let start = start
+        .map(|start| -> Result<i64> {
+            let time = util::parse_time_floor(Some(conn), start)
+                .map_err(|e| format!("Invalid start time {}: {}", start, e))?;
+            time.timestamp_nanos_opt()
+                .ok_or_else(|| format!("The start time {} is out of range", start).into())
+        })
+        .transpose()?;
+    let end = end
+        .map(|end| -> Result<i64> {
+            let time = util::parse_time_ceil(Some(conn), end)
+                .map_err(|e| format!("Invalid end time {}: {}", end, e))?;
+            time.timestamp_nanos_opt()
+                .ok_or_else(|| format!("The end time {} is out of range", end).into())
+        })
+        .transpose()?;
+    Ok((start, end))
+}
+
+/// Generate an SQL expression for the local date and time `YYYY-MM-DD HH:MM:SS` of a timestamp
+/// column in a time zone.
+///
+/// # Arguments
+/// * `timestamp_column` - The column that contains the timestamp, in nanoseconds since the
+///   Unix epoch.
+/// * `timezone` - The time zone of the local time.
+pub(crate) fn get_local_datetime_expression(timestamp_column: &str, timezone: Timezone) -> String {
+    match timezone {
+        Timezone::Local => format!(
+            "datetime({} / 1000000000, 'unixepoch', 'localtime')",
+            timestamp_column
+        ),
+        // SQLite only knows about UTC and local time, see `register_calendar_functions`.
+        Timezone::Named(timezone) => format!(
+            "local_datetime({}, '{}')",
+            timestamp_column,
+            timezone.name()
+        ),
+    }
+}
+
+/// Generate an SQL criteria that selects samples within the work hours of a calendar. Without
+/// `hours`, work hours are 08 to 17 on weekdays, and without `timezone`, they are in local time.
+///
+/// # Arguments
+/// * `calendar` - The calendar with the work hours and holidays.
+/// * `timestamp_column` - The column that contains the timestamp, in nanoseconds since the
+///   Unix epoch.
+fn get_work_hours_criteria(calendar: &Calendar, timestamp_column: &str) -> Result<String> {
+    let local_time = get_local_datetime_expression(
+        timestamp_column,
+        calendar.timezone.unwrap_or(Timezone::Local),
+    );
+
+    let default_hours = "08:00-17:00".to_string();
+    let hours = calendar.hours.clone().unwrap_or_default();
+    let weekdays = match (&calendar.hours, &hours.weekdays) {
+        (None, _) => Some(&default_hours),
+        (Some(_), weekdays) => weekdays.as_ref(),
+    };
+    // The days are numbered as by `strftime('%w')`, starting with Sunday.
+    let days = [
+        hours.sunday.as_ref(),
+        hours.monday.as_ref().or(weekdays),
+        hours.tuesday.as_ref().or(weekdays),
+        hours.wednesday.as_ref().or(weekdays),
+        hours.thursday.as_ref().or(weekdays),
+        hours.friday.as_ref().or(weekdays),
+        hours.saturday.as_ref(),
+    ];
+    let mut cases = Vec::new();
+    for (day, hours) in days.iter().enumerate() {
+        let Some(hours) = hours else {
+            continue;
+        };
+        let ranges: Vec<String> = util::parse_work_hours(hours)?
+            .into_iter()
+            .map(|(start, end)| {
+                format!(
+                    "(time({}) >= '{}' and time({}) < '{}')",
+                    local_time, start, local_time, end
+                )
+            })
+            .collect();
+        if !ranges.is_empty() {
+            cases.push(format!("when '{}' then {}", day, ranges.join(" or ")));
         }
     }
-    (result_timestamps, result_data)
+    let mut criteria = if cases.is_empty() {
+        "0".to_string()
+    } else {
+        format!(
+            "case strftime('%w', {}) {} else 0 end",
+            local_time,
+            cases.join(" ")
+        )
+    };
+
+    if let Some(holidays) = &calendar.holidays {
+        let holidays = holidays
+            .iter()
+            .map(|holiday| {
+                Ok(format!(
+                    "'{}'",
+                    util::parse_holiday(holiday)?.format("%Y-%m-%d")
+                ))
+            })
+            .collect::<Result<Vec<String>>>()?;
+        if !holidays.is_empty() {
+            criteria = format!(
+                "({}) and date({}) not in ({})",
+                criteria,
+                local_time,
+                holidays.join(", ")
+            );
+        }
+    }
+    Ok(criteria)
 }
 
-/// Determine if a timestamp is within work hours. Work hours are defined as 08 to 17 on weekdays,
-/// local time.
+/// Check that a filter can be combined with the rest of the plot configuration. Filtering on work
+/// hours leaves gaps in the samples, which is not supported when the samples are treated as a
+/// continuous series: a time-weighted aggregation would let the last value before a gap hold
+/// across it, and a rate would be computed across it.
 ///
 /// # Arguments
-/// * `timestamp` - A `i64` value, where the value represents a time in nanoseconds from the Unix
-/// epoch.
-fn is_work_hours(timestamp: i64) -> bool {
-    // Work hours are 08 to 17 on weekdays
-    let local_time = chrono::Local.timestamp_nanos(timestamp);
-    let hour = local_time.hour();
-    let weekday = local_time.weekday();
-    (8..17).contains(&hour) && weekday != chrono::Weekday::Sat && weekday != chrono::Weekday::Sun
+/// * `filter` - An optional `Filter` object that contains the filter configuration.
+/// * `aggregation` - The aggregation configuration, if any.
+/// * `transform` - The transform of the samples, if any.
+pub(crate) fn check_filter_support(
+    filter: Option<&Filter>,
+    aggregation: Option<&Aggregation>,
+    transform: Option<Transform>,
+) -> Result<()> {
+    if !filter.and_then(|f| f.work_hours).unwrap_or(false) {
+        return Ok(());
+    }
+    if aggregation.is_some_and(|a| a.mode.is_time_weighted()) {
+        return Err(
+            "The workhours filter is not supported with time-weighted aggregation modes".into(),
+        );
+    }
+    if let Some(Transform::Rate) = transform {
+        return Err("The workhours filter is not supported with the rate transform".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WorkHours;
+
+    #[test]
+    fn test_get_filter_criteria() {
+        let filter = Filter {
+            sql_where: Some("view_id = 1 or view_id = 2".to_string()),
+            work_hours: Some(true),
+            ..Default::default()
+        };
+        let conn = Connection::open_in_memory().unwrap();
+        let criteria =
+            get_common_criteria(&conn, Some("2023"), None, Some(&filter), Some("e")).unwrap();
+        assert_eq!(criteria.len(), 3);
+        assert_eq!(criteria[0], "(view_id = 1 or view_id = 2)");
+        assert_eq!(criteria[1], "e.timestamp >= 1672531200000000000");
+        assert!(criteria[2].contains(
+            "strftime('%w', datetime(e.timestamp / 1000000000, 'unixepoch', 'localtime'))"
+        ));
+        assert!(get_common_criteria(&conn, Some("2023-13"), None, None, None).is_err());
+    }
+
+    #[test]
+    fn test_get_work_hours_criteria() {
+        let calendar = Calendar {
+            timezone: Some("America/New_York".parse().unwrap()),
+            hours: Some(WorkHours {
+                weekdays: Some("09:00-17:00".to_string()),
+                saturday: Some("10-12".to_string()),
+                friday: Some("09:00-12:00".to_string()),
+                ..Default::default()
+            }),
+            holidays: Some(vec!["2024-12-25".to_string()]),
+            holiday_file: None,
+        };
+        let criteria = get_work_hours_criteria(&calendar, "timestamp").unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        util::register_calendar_functions(&conn).unwrap();
+        let in_work_hours = |time: &str| -> bool {
+            let timestamp = chrono::DateTime::parse_from_rfc3339(time)
+                .unwrap()
+                .timestamp_nanos_opt()
+                .unwrap();
+            let sql = format!(
+                "select {} from (select {} as timestamp)",
+                criteria, timestamp
+            );
+            conn.query_row(&sql, [], |row| row.get(0)).unwrap()
+        };
+        // Monday 2024-12-23 at 09:30 and 08:30 in New York.
+        assert!(in_work_hours("2024-12-23T14:30:00Z"));
+        assert!(!in_work_hours("2024-12-23T13:30:00Z"));
+        // Christmas day, a Wednesday.
+        assert!(!in_work_hours("2024-12-25T15:00:00Z"));
+        // Friday and Saturday afternoon, and Sunday.
+        assert!(!in_work_hours("2024-12-27T18:00:00Z"));
+        assert!(in_work_hours("2024-12-28T15:30:00Z"));
+        assert!(!in_work_hours("2024-12-29T15:30:00Z"));
+    }
 }
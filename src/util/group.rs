@@ -0,0 +1,165 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+use rusqlite::Connection;
+
+use crate::config::{GroupOrder, PlotCommon, Timezone};
+use crate::util::{self, Result, Statistics, TimedSamples, TypeCast};
+
+/// Translate the `groupby` setting into an SQL expression. `hour` groups by the hour of the day
+/// in the given time zone, anything else is used as is, e.g. a column name.
+pub(crate) fn get_group_expression(group_by: &str, timezone: Timezone) -> String {
+    match group_by {
+        "hour" => format!(
+            "strftime('%H', {})",
+            util::get_local_datetime_expression("timestamp", timezone)
+        ),
+        _ => group_by.to_string(),
+    }
+}
+
+/// Get the label of a group, e.g. on the x-axis of a box plot. Numeric keys are prefixed with the
+/// `groupby` setting, since plotly would otherwise place the groups on a numeric axis rather than
+/// in order.
+pub(crate) fn get_group_label(group_by: &str, key: &str) -> String {
+    if key.parse::<f64>().is_ok() {
+        format!("{} {}", group_by, key)
+    } else {
+        key.to_string()
+    }
+}
+
+/// Get the name of the series of a group in a plot with one series per group, e.g. `View 123`
+/// for a plot named `View` grouped by view, or the label of the group if the plot has no name.
+pub(crate) fn get_group_name(name: &str, group_by: &str, key: &str) -> String {
+    if name.is_empty() {
+        get_group_label(group_by, key)
+    } else {
+        format!("{} {}", name, key)
+    }
+}
+
+/// Sort groups by the given order and keep the first ones.
+///
+/// # Arguments
+/// * `groups` - The groups to sort.
+/// * `sort_by` - The order of the groups. `Key` sorts by the group key, numerically if all keys
+///   are numbers. The other orders put the group with the largest statistic first.
+/// * `top` - The number of groups to keep, or `None` to keep all of them.
+/// * `get_key` - Get the key of a group.
+/// * `get_statistics` - Get the statistics of the samples of a group.
+pub(crate) fn sort_groups<G, T>(
+    groups: &mut Vec<G>,
+    sort_by: GroupOrder,
+    top: Option<usize>,
+    get_key: impl Fn(&G) -> &str,
+    get_statistics: impl Fn(&G) -> &Statistics<T>,
+) where
+    T: Debug + Clone + TypeCast<f64>,
+{
+    let numeric_keys = groups.iter().all(|g| get_key(g).parse::<f64>().is_ok());
+    groups.sort_by(|a, b| {
+        let (a_stats, b_stats) = (get_statistics(a), get_statistics(b));
+        match sort_by {
+            GroupOrder::Key if numeric_keys => {
+                let a = get_key(a).parse::<f64>().unwrap();
+                let b = get_key(b).parse::<f64>().unwrap();
+                a.total_cmp(&b)
+            }
+            GroupOrder::Key => get_key(a).cmp(get_key(b)),
+            GroupOrder::Count => b_stats.count.cmp(&a_stats.count),
+            GroupOrder::Mean => b_stats.mean.total_cmp(&a_stats.mean),
+            GroupOrder::Median => b_stats.median.total_cmp(&a_stats.median),
+            GroupOrder::Q3 => b_stats.q3.total_cmp(&a_stats.q3),
+            GroupOrder::Max => b_stats.max.cast().total_cmp(&a_stats.max.cast()),
+        }
+        .then_with(|| match sort_by {
+            GroupOrder::Key => Ordering::Equal,
+            _ => get_key(a).cmp(get_key(b)),
+        })
+    });
+    if let Some(top) = top {
+        groups.truncate(top);
+    }
+}
+
+/// Read time-based samples from the database, split into one series per group, and select the
+/// groups to show.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `common_cfg` - The common plot configuration, with the filter and the time zone of the
+///   `hour` group.
+/// * `column` - The name of the column to read from the database.
+/// * `table` - The name of the table to read from the database.
+/// * `group_by` - The `groupby` setting, see `get_group_expression`.
+/// * `top` - The number of groups to keep, or `None` to keep all of them.
+/// * `sort_by` - The order of the groups. Defaults to `Count` if `top` is set, so that the groups
+///   with the most samples are shown, and to `Key` otherwise.
+///
+/// # Returns
+/// * The key and samples of each group, in the sort order.
+pub(crate) fn get_top_groups(
+    conn: &Connection,
+    common_cfg: &PlotCommon,
+    column: &str,
+    table: &str,
+    group_by: &str,
+    top: Option<usize>,
+    sort_by: Option<GroupOrder>,
+) -> Result<Vec<(String, TimedSamples)>> {
+    let filter = common_cfg.filter.as_ref();
+    let samples_by_group = util::get_timed_samples_by_group(
+        conn,
+        filter.and_then(|f| f.start.as_deref()),
+        filter.and_then(|f| f.end.as_deref()),
+        filter,
+        column,
+        table,
+        &get_group_expression(group_by, common_cfg.timezone()),
+    )?;
+
+    let mut groups: Vec<(String, TimedSamples, Statistics<u64>)> =
+        Vec::with_capacity(samples_by_group.len());
+    for (key, samples) in samples_by_group {
+        let mut sorted = samples.value.clone();
+        sorted.sort();
+        let statistics = util::get_statistics(&sorted);
+        groups.push((key, samples, statistics));
+    }
+    eprintln!("Found {} groups", groups.len());
+
+    let sort_by = sort_by.unwrap_or(if top.is_some() {
+        GroupOrder::Count
+    } else {
+        GroupOrder::Key
+    });
+    sort_groups(&mut groups, sort_by, top, |g| &g.0, |g| &g.2);
+    Ok(groups
+        .into_iter()
+        .map(|(key, samples, _)| (key, samples))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_group_expression() {
+        let conn = Connection::open_in_memory().unwrap();
+        util::register_calendar_functions(&conn).unwrap();
+        // Midnight UTC on 2024-01-01 is 01:00 in Stockholm and 19:00 the day before in New York.
+        let hour = |timezone: Timezone| -> String {
+            let sql = format!(
+                "select {} from (select 1704067200000000000 as timestamp)",
+                get_group_expression("hour", timezone)
+            );
+            conn.query_row(&sql, [], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(hour(Timezone::Named(chrono_tz::UTC)), "00");
+        assert_eq!(hour(Timezone::Named(chrono_tz::Europe::Stockholm)), "01");
+        assert_eq!(hour(Timezone::Named(chrono_tz::America::New_York)), "19");
+        assert_eq!(get_group_expression("view_id", Timezone::Local), "view_id");
+    }
+}
@@ -0,0 +1,272 @@
+use crate::config::Bins2d;
+use crate::util::{self, Result};
+
+/// The default number of bins along each axis of a 2D histogram.
+const DEFAULT_BINS_2D: u32 = 256;
+
+/// The maximum number of bins of a histogram, or along each axis of a 2D histogram, to avoid
+/// running out of memory when the bin width is far too small for the range.
+const MAX_BINS: usize = 10_000;
+
+/// Compute the edges of histogram bins covering the range `min..max`.
+///
+/// # Arguments
+/// * `min` - The lower edge of the first bin.
+/// * `max` - The upper edge of the last bin. Must not be less than `min`.
+/// * `bins` - The number of bins. Ignored if `bin_width` is given. At most `MAX_BINS`, which
+///   also limits the number of bins that `bin_width` gives.
+/// * `bin_width` - The width of each bin. For logarithmic bins, this is the width in powers of
+///   ten, e.g. 0.1 gives ten bins per decade.
+/// * `log` - Whether the bins are evenly spaced on a logarithmic scale. Requires `min > 0`.
+///
+/// # Returns
+/// The bin edges in increasing order, i.e. one more than the number of bins.
+pub(crate) fn get_bin_edges(
+    min: f64,
+    max: f64,
+    bins: usize,
+    bin_width: Option<f64>,
+    log: bool,
+) -> Result<Vec<f64>> {
+    if !(min.is_finite() && max.is_finite()) {
+        return Err(format!("Invalid histogram range {}..{}", min, max).into());
+    }
+    if min > max {
+        return Err(format!("The range {}..{} is empty", min, max).into());
+    }
+    if log && min <= 0.0 {
+        return Err(format!(
+            "Logarithmic bins require a positive range, got {}..{}",
+            min, max
+        )
+        .into());
+    }
+    let (scaled_min, scaled_max) = if log {
+        (min.log10(), max.log10())
+    } else {
+        (min, max)
+    };
+
+    let (bins, width) = match bin_width {
+        Some(width) if width > 0.0 => {
+            let bins = ((scaled_max - scaled_min) / width).ceil().max(1.0);
+            if bins > MAX_BINS as f64 {
+                return Err(format!(
+                    "Bin width {} gives {} bins over {}..{}, more than the maximum of {}",
+                    width, bins, min, max, MAX_BINS
+                )
+                .into());
+            }
+            (bins as usize, width)
+        }
+        Some(width) => return Err(format!("Invalid bin width {}", width).into()),
+        None if bins == 0 => return Err("The number of bins must be positive".into()),
+        None if bins > MAX_BINS => {
+            return Err(format!("{} bins is more than the maximum of {}", bins, MAX_BINS).into())
+        }
+        // A range of a single value still gets one bin of non-zero width.
+        None if scaled_max == scaled_min => (bins, 1.0 / bins as f64),
+        None => (bins, (scaled_max - scaled_min) / bins as f64),
+    };
+
+    Ok((0..=bins)
+        .map(|i| {
+            let edge = scaled_min + i as f64 * width;
+            if log {
+                10f64.powf(edge)
+            } else {
+                edge
+            }
+        })
+        .collect())
+}
+
+/// Count the number of values in each histogram bin. Bins include their lower edge, and the last
+/// bin also includes its upper edge. Values outside the bins are ignored.
+///
+/// # Arguments
+/// * `values` - The values to count.
+/// * `edges` - The bin edges in increasing order, as returned by `get_bin_edges`.
+pub(crate) fn make_histogram(values: &[f64], edges: &[f64]) -> Vec<u64> {
+    let mut counts = vec![0; edges.len().saturating_sub(1)];
+    for value in values {
+        if let Some(bin) = get_bin_index(*value, edges) {
+            counts[bin] += 1;
+        }
+    }
+    counts
+}
+
+/// Get the index of the bin that a value falls in, see `make_histogram`, or `None` if it is
+/// outside the bins.
+fn get_bin_index(value: f64, edges: &[f64]) -> Option<usize> {
+    let bins = edges.len().checked_sub(1).filter(|bins| *bins > 0)?;
+    if !(value >= edges[0] && value <= edges[bins]) {
+        return None;
+    }
+    let bin = edges.partition_point(|edge| *edge <= value);
+    Some((bin.max(1) - 1).min(bins - 1))
+}
+
+/// A histogram of pairs of values.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Histogram2d {
+    /// The edges of the bins along the x-axis, in increasing order.
+    pub x_edges: Vec<f64>,
+    /// The edges of the bins along the y-axis, in increasing order.
+    pub y_edges: Vec<f64>,
+    /// The counts, indexed by the y bin and then the x bin, which is the order that plotly heat
+    /// maps expect.
+    pub counts: Vec<Vec<u64>>,
+}
+
+/// Count pairs of values in a 2D histogram. Along each axis, the bins are evenly spaced on a
+/// linear or logarithmic scale, over the configured range or, where it is not given, over the
+/// range of the values, less the `clip` percentage of the values at each end. Pairs outside the
+/// bins, including non-positive values with logarithmic bins, are ignored.
+///
+/// # Arguments
+/// * `x` - The x values.
+/// * `y` - The y values, one for each x value.
+/// * `bins` - The configuration of the bins.
+pub(crate) fn make_histogram_2d(x: &[f64], y: &[f64], bins: &Bins2d) -> Result<Histogram2d> {
+    assert_eq!(x.len(), y.len());
+    let clip = bins.clip.unwrap_or(0.0);
+    if !(0.0..50.0).contains(&clip) {
+        return Err(format!(
+            "The clip percentage must be from 0 to less than 50, got {}",
+            clip
+        )
+        .into());
+    }
+    let x_edges = get_axis_edges(
+        x,
+        bins.x_bins.unwrap_or(DEFAULT_BINS_2D),
+        bins.x_log_bins.unwrap_or(false),
+        (bins.x_min, bins.x_max),
+        clip,
+    )
+    .map_err(|e| format!("Invalid x bins: {}", e))?;
+    let y_edges = get_axis_edges(
+        y,
+        bins.y_bins.unwrap_or(DEFAULT_BINS_2D),
+        bins.y_log_bins.unwrap_or(false),
+        (bins.y_min, bins.y_max),
+        clip,
+    )
+    .map_err(|e| format!("Invalid y bins: {}", e))?;
+
+    let mut counts = vec![vec![0; x_edges.len() - 1]; y_edges.len() - 1];
+    let mut ignored = 0;
+    for (x, y) in x.iter().zip(y) {
+        match (get_bin_index(*x, &x_edges), get_bin_index(*y, &y_edges)) {
+            (Some(x_bin), Some(y_bin)) => counts[y_bin][x_bin] += 1,
+            _ => ignored += 1,
+        }
+    }
+    if ignored > 0 {
+        eprintln!("Ignoring {} values outside the histogram range", ignored);
+    }
+    Ok(Histogram2d {
+        x_edges,
+        y_edges,
+        counts,
+    })
+}
+
+/// Get the bin edges along one axis of a 2D histogram, see `make_histogram_2d`.
+fn get_axis_edges(
+    values: &[f64],
+    bins: u32,
+    log: bool,
+    range: (Option<f64>, Option<f64>),
+    clip: f64,
+) -> Result<Vec<f64>> {
+    let mut sorted: Vec<f64> = values
+        .iter()
+        .copied()
+        .filter(|value| !log || *value > 0.0)
+        .collect();
+    sorted.sort_by(f64::total_cmp);
+    let get_limit = |limit: Option<f64>, percentile: f64| match limit {
+        Some(limit) => Ok(limit),
+        None if sorted.is_empty() => Err("There are no values to get the range from"),
+        None => Ok(util::get_percentile(&sorted, percentile)),
+    };
+    let min = get_limit(range.0, clip / 100.0)?;
+    let max = get_limit(range.1, 1.0 - clip / 100.0)?;
+    if min > max {
+        return Err(format!("The range {}..{} is empty", min, max).into());
+    }
+    get_bin_edges(min, max, bins as usize, None, log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_bin_edges() {
+        assert_eq!(
+            get_bin_edges(0.0, 1.0, 4, None, false).unwrap(),
+            vec![0.0, 0.25, 0.5, 0.75, 1.0]
+        );
+        assert_eq!(
+            get_bin_edges(0.0, 1.0, 4, Some(0.4), false).unwrap(),
+            vec![0.0, 0.4, 0.8, 1.2000000000000002]
+        );
+        let edges = get_bin_edges(0.01, 10.0, 3, None, true).unwrap();
+        for (edge, expected) in edges.iter().zip([0.01, 0.1, 1.0, 10.0]) {
+            assert!((edge - expected).abs() < 1e-12);
+        }
+        assert!(get_bin_edges(0.0, 10.0, 3, None, true).is_err());
+        assert!(get_bin_edges(2.0, 1.0, 4, None, false).is_err());
+        assert!(get_bin_edges(2.0, 1.0, 4, Some(0.5), false).is_err());
+        assert!(get_bin_edges(0.0, 1e9, 4, Some(1e-9), false).is_err());
+        assert!(get_bin_edges(0.0, 1.0, MAX_BINS + 1, None, false).is_err());
+        assert_eq!(
+            get_bin_edges(0.0, 1.0, MAX_BINS, None, false)
+                .unwrap()
+                .len(),
+            MAX_BINS + 1
+        );
+    }
+
+    #[test]
+    fn test_make_histogram() {
+        let edges = [0.0, 1.0, 2.0, 3.0];
+        let values = [-1.0, 0.0, 0.5, 1.0, 2.5, 3.0, 4.0];
+        assert_eq!(make_histogram(&values, &edges), vec![2, 1, 2]);
+    }
+
+    #[test]
+    fn test_make_histogram_2d() {
+        let bins = Bins2d {
+            x_bins: Some(2),
+            y_bins: Some(3),
+            x_log_bins: None,
+            y_log_bins: Some(true),
+            x_min: None,
+            x_max: None,
+            y_min: None,
+            y_max: Some(1000.0),
+            clip: None,
+        };
+        let x = [10.0, 12.0, 14.0, 11.0, 13.0];
+        let y = [1.0, 10.0, 100.0, 0.0, 5000.0];
+        let histogram = make_histogram_2d(&x, &y, &bins).unwrap();
+        assert_eq!(histogram.x_edges, vec![10.0, 12.0, 14.0]);
+        for (edge, expected) in histogram.y_edges.iter().zip([1.0, 10.0, 100.0, 1000.0]) {
+            assert!((edge - expected).abs() < 1e-9);
+        }
+        // The 0 is not on the logarithmic scale and 5000 is outside the range.
+        assert_eq!(histogram.counts, vec![vec![1, 0], vec![0, 1], vec![0, 1]]);
+
+        let clipped = Bins2d {
+            clip: Some(25.0),
+            ..bins
+        };
+        let histogram = make_histogram_2d(&x, &y, &clipped).unwrap();
+        assert_eq!(histogram.x_edges, vec![11.0, 12.0, 13.0]);
+    }
+}
@@ -12,9 +12,9 @@ use crate::{config, util, util::Result};
 /// # Arguments
 /// * `conn` - A connection to the database.
 /// * `start` - The start time for the samples. If `None`, the query starts at the
-/// beginning of the data.
+///   beginning of the data.
 /// * `end` - The end time for the samples. If `None`, the query ends at the end of the
-/// data.
+///   data.
 /// * `filter` - An optional `Filter` object that contains the filter configuration.
 /// * `column` - The name of the column to read from the database.
 /// * `table` - The name of the table to read from the database.
@@ -28,7 +28,7 @@ pub(crate) fn get_samples(
 ) -> Result<(Vec<i64>, Vec<u64>)> {
     // TODO we can get the start and end from the Filter instead.
     let mut sql = format!("select timestamp, {} from {}", column, table);
-    let criteria = get_common_criteria(start, end, filter, None);
+    let criteria = util::get_common_criteria(conn, start, end, filter, None)?;
     if !criteria.is_empty() {
         sql.push_str(" where ");
         sql.push_str(&criteria.join(" and "));
@@ -50,8 +50,8 @@ pub(crate) fn get_samples(
         + counts.capacity() * std::mem::size_of::<u64>();
     let total_size =
         timestamps.len() * std::mem::size_of::<f64>() + counts.len() * std::mem::size_of::<u64>();
-    println!("Data points: {}", timestamps.len());
-    println!(
+    eprintln!("Data points: {}", timestamps.len());
+    eprintln!(
         "Memory use: {} (heap {})",
         ByteSize(total_size as u64),
         ByteSize(total_capacity as u64)
@@ -59,34 +59,84 @@ pub(crate) fn get_samples(
     Ok((timestamps, counts))
 }
 
-/// Read time-based samples from the database, assuming that the sample values are counters
-/// (e.g., the number of requests per second).
+/// Read intervals from the database, such as query executions with their duration, that overlap
+/// a time range. Unlike `get_samples`, this includes intervals that started before the range
+/// but were still going on at its start.
 ///
 /// # Arguments
 /// * `conn` - A connection to the database.
-/// * `common_plot_config` - The common configuration for the plot.
-/// * `plot_config` - The configuration for the count scatter plot.
-pub(crate) fn get_count_samples(
+/// * `start` - The start of the range, in nanoseconds since the Unix epoch. If `None`, the
+///   query starts at the beginning of the data.
+/// * `end` - The end of the range, in nanoseconds since the Unix epoch. If `None`, the query
+///   ends at the end of the data.
+/// * `filter` - An optional `Filter` object with further criteria. Its start and end are not
+///   used.
+/// * `duration_column` - The column with the duration of each interval, in nanoseconds.
+/// * `table` - The name of the table to read from the database.
+///
+/// # Returns
+/// The start time and duration of each interval, ordered by start time.
+pub(crate) fn get_interval_samples(
     conn: &Connection,
-    common_plot_config: &config::PlotCommon,
-    plot_config: &config::CountScatterPlot,
+    start: Option<i64>,
+    end: Option<i64>,
+    filter: Option<&Filter>,
+    duration_column: &str,
+    table: &str,
 ) -> Result<(Vec<i64>, Vec<u64>)> {
-    let mut sql = format!(
-        "select timestamp, {} from {}",
-        plot_config.column, plot_config.table
-    );
-    let criteria = get_common_criteria(
-        common_plot_config
-            .filter
-            .as_ref()
-            .and_then(|f| f.start.as_deref()),
-        common_plot_config
-            .filter
-            .as_ref()
-            .and_then(|f| f.end.as_deref()),
-        None,
-        None,
-    );
+    let mut criteria = util::get_common_criteria(conn, None, None, filter, None)?;
+    if let Some(start) = start {
+        criteria.push(format!("timestamp + {} >= {}", duration_column, start));
+    }
+    if let Some(end) = end {
+        criteria.push(format!("timestamp <= {}", end));
+    }
+    read_samples(conn, &criteria, duration_column, table)
+}
+
+/// Read a step function from the database, such as the `active_query_count` table, where each
+/// value holds from its timestamp until the next one. Unlike `get_samples`, this includes the
+/// steps that are in effect at the start and at the end of the time range.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `start` - The start of the range, in nanoseconds since the Unix epoch. If `None`, the
+///   query starts at the beginning of the data.
+/// * `end` - The end of the range, in nanoseconds since the Unix epoch. If `None`, the query
+///   ends at the end of the data.
+/// * `column` - The name of the column with the values.
+/// * `table` - The name of the table to read from the database.
+pub(crate) fn get_step_samples(
+    conn: &Connection,
+    start: Option<i64>,
+    end: Option<i64>,
+    column: &str,
+    table: &str,
+) -> Result<(Vec<i64>, Vec<u64>)> {
+    let mut criteria: Vec<String> = Vec::new();
+    if let Some(start) = start {
+        criteria.push(format!(
+            "timestamp >= coalesce((select max(timestamp) from {} where timestamp <= {}), {})",
+            table, start, start
+        ));
+    }
+    if let Some(end) = end {
+        criteria.push(format!(
+            "timestamp <= coalesce((select min(timestamp) from {} where timestamp >= {}), {})",
+            table, end, end
+        ));
+    }
+    read_samples(conn, &criteria, column, table)
+}
+
+/// Read the timestamp and a column of the rows that match the criteria, ordered by timestamp.
+fn read_samples(
+    conn: &Connection,
+    criteria: &[String],
+    column: &str,
+    table: &str,
+) -> Result<(Vec<i64>, Vec<u64>)> {
+    let mut sql = format!("select timestamp, {} from {}", column, table);
     if !criteria.is_empty() {
         sql.push_str(" where ");
         sql.push_str(&criteria.join(" and "));
@@ -94,78 +144,39 @@ pub(crate) fn get_count_samples(
     sql.push_str(" order by timestamp");
     eprintln!("Executing query: {}", sql);
 
-    let mut stmt = conn.prepare(sql.as_str())?;
+    let mut stmt = conn.prepare(&sql)?;
     let mut rows = stmt.query([])?;
     let mut timestamps: Vec<i64> = Vec::new();
-    let mut counts: Vec<u64> = Vec::new();
+    let mut values: Vec<u64> = Vec::new();
     while let Some(row) = rows.next()? {
         timestamps.push(row.get(0)?);
-        counts.push(row.get(1)?);
+        values.push(row.get(1)?);
     }
-
-    // Show amount of memory consumed by the vectors
-    let total_capacity = timestamps.capacity() * std::mem::size_of::<f64>()
-        + counts.capacity() * std::mem::size_of::<u64>();
-    let total_size =
-        timestamps.len() * std::mem::size_of::<f64>() + counts.len() * std::mem::size_of::<u64>();
-    println!("Data points: {}", timestamps.len());
-    println!(
-        "Memory use: {} (heap {})",
-        ByteSize(total_size as u64),
-        ByteSize(total_capacity as u64)
-    );
-    Ok((timestamps, counts))
+    eprintln!("Data points: {}", timestamps.len());
+    Ok((timestamps, values))
 }
 
-/// Generate SQL criteria for common filter configuration.
+/// Read time-based samples from the database, assuming that the sample values are counters
+/// (e.g., the number of requests per second).
 ///
 /// # Arguments
-/// * `start` - The start time for the query. If `None`, the samples start at the
-/// beginning of the data.
-/// * `end` - The end time for the query. If `None`, the samples end at the end of the
-/// data.
-/// * `filter` - An optional `Filter` object that contains the filter configuration.
-/// * `timestamp_table` - The name of the table that contains the timestamp column. If `None`,
-/// no table qualifier is used, which would typically mean that SQLite gets the column from
-/// the only table in the query.
-///
-/// # Returns
-/// * A vector of strings, where each string represents an SQL criteria. These should be
-/// joined with `and` to form a complete SQL `where` clause.
-pub(crate) fn get_common_criteria(
-    start: Option<&str>,
-    end: Option<&str>,
-    filter: Option<&Filter>,
-    timestamp_table: Option<&str>,
-) -> Vec<String> {
-    let mut criteria: Vec<String> = Vec::new();
-    if let Some(filter) = filter {
-        if let Some(sql_where) = &filter.sql_where {
-            criteria.push(sql_where.to_string());
-        }
-    }
-    let qualifier = match timestamp_table {
-        Some(table) => format!("{}.", table),
-        None => String::new(),
-    };
-    if let Some(start) = &start {
-        // FIXME need to report parse error up here instead of throwing a backtrace in the caller's face.
-        // Also perhaps document what format is expected!?
-        let start = util::parse_datetime_floor(start).unwrap();
-        let start = start
-            .timestamp_nanos_opt()
-            .expect("value can not be represented in a timestamp with nanosecond precision.");
-        criteria.push(format!("{}timestamp >= {}", qualifier, start));
-    }
-    if let Some(end) = &end {
-        // FIXME same here
-        let end = util::parse_datetime_ceil(end).unwrap();
-        let end = end
-            .timestamp_nanos_opt()
-            .expect("value can not be represented in a timestamp with nanosecond precision.");
-        criteria.push(format!("{}timestamp <= {}", qualifier, end));
-    }
-    criteria
+/// * `conn` - A connection to the database.
+/// * `common_plot_config` - The common configuration for the plot.
+/// * `plot_config` - The configuration for the count scatter plot.
+pub(crate) fn get_count_samples(
+    conn: &Connection,
+    common_plot_config: &config::PlotCommon,
+    plot_config: &config::CountScatterPlot,
+) -> Result<(Vec<i64>, Vec<u64>)> {
+    let filter = common_plot_config.filter.as_ref();
+    get_samples(
+        conn,
+        filter.and_then(|f| f.start.as_deref()),
+        filter.and_then(|f| f.end.as_deref()),
+        filter,
+        &plot_config.column,
+        &plot_config.table,
+    )
 }
 
 /// Read overlap information for a view from the database.
@@ -174,14 +185,14 @@ pub(crate) fn get_common_criteria(
 /// * `conn` - A connection to the database.
 /// * `view_id` - The ID of the view to read overlap information for.
 /// * `start` - The start time for the samples. If `None`, the query starts at the
-/// beginning of the data.
+///   beginning of the data.
 /// * `end` - The end time for the samples. If `None`, the query ends at the end of the
-/// data.
+///   data.
 /// * `filter` - An optional `Filter` object that contains the filter configuration.
 ///
 /// # Returns
 /// * A `ViewDurationVsOverlap` object that contains the wallclock time and overlap time
-/// for the view.
+///   for the view.
 pub(crate) fn get_overlap_samples_for_view(
     conn: &Connection,
     view_id: i32,
@@ -189,7 +200,7 @@ pub(crate) fn get_overlap_samples_for_view(
     end: Option<&str>,
     filter: Option<&Filter>,
 ) -> Result<ViewDurationVsOverlap> {
-    let mut criteria = get_common_criteria(start, end, filter, Some("e"));
+    let mut criteria = util::get_common_criteria(conn, start, end, filter, Some("e"))?;
 
     criteria.push(format!("e.view_id = {}", view_id));
 
@@ -229,27 +240,36 @@ pub(crate) struct ViewDurationVsOverlap {
     pub overlap: Vec<u64>,
 }
 
+/// Samples of a single view, ordered by timestamp.
+#[derive(Clone, Default)]
+pub(crate) struct TimedSamples {
+    /// Timestamps of the samples, in nanoseconds since the Unix epoch.
+    pub timestamp: Vec<i64>,
+    /// Values of the samples.
+    pub value: Vec<u64>,
+}
+
 /// Read overlap information for all views from the database.
 ///
 /// # Arguments
 /// * `conn` - A connection to the database.
 /// * `start` - The start time for the samples. If `None`, the query starts at the
-/// beginning of the data.
+///   beginning of the data.
 /// * `end` - The end time for the samples. If `None`, the query ends at the end of the
-/// data.
+///   data.
 /// * `filter` - An optional `Filter` object that contains the filter configuration.
 ///
 /// # Returns
 /// * A map with view IDs as keys and `ViewDurationVsOverlap` objects as values. Each
-/// `ViewDurationVsOverlap` object contains the wallclock time and overlap time for a
-/// view.
+///   `ViewDurationVsOverlap` object contains the wallclock time and overlap time for a
+///   view.
 pub(crate) fn get_overlap_samples(
     conn: &Connection,
     start: Option<&str>,
     end: Option<&str>,
     filter: Option<&Filter>,
 ) -> Result<HashMap<i32, ViewDurationVsOverlap>> {
-    let mut criteria = get_common_criteria(start, end, filter, Some("e"));
+    let mut criteria = util::get_common_criteria(conn, start, end, filter, Some("e"))?;
 
     let mut sql: Vec<String> = vec![
         "select e.view_id, e.wallclock_time_ns, o.overlap ".to_string(),
@@ -285,3 +305,297 @@ pub(crate) fn get_overlap_samples(
 
     Ok(by_view)
 }
+
+/// Determine whether a table with the given name exists in the database.
+pub(crate) fn table_exists(conn: &Connection, table: &str) -> Result<bool> {
+    Ok(conn.query_row(
+        "select count(1) > 0 from sqlite_master where type = 'table' and name = ?",
+        [table],
+        |row| row.get(0),
+    )?)
+}
+
+/// Determine whether a table in the database has a column with the given name.
+pub(crate) fn table_has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("pragma table_info({})", table))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(1)?;
+        if name == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Get the names of the tables in the database.
+pub(crate) fn get_table_names(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("select name from sqlite_master where type = 'table'")?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+/// Get the names of the columns of a table in the database.
+pub(crate) fn get_column_names(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("pragma table_info({})", table))?;
+    let names = stmt
+        .query_map([], |row| row.get(1))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+/// Read time-based samples from the database, grouped by view.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `start` - The start time for the samples. If `None`, the query starts at the
+///   beginning of the data.
+/// * `end` - The end time for the samples. If `None`, the query ends at the end of the
+///   data.
+/// * `filter` - An optional `Filter` object that contains the filter configuration.
+/// * `column` - The name of the column to read from the database.
+/// * `table` - The name of the table to read from the database. Must have a `view_id` column.
+///
+/// # Returns
+/// * A map with view IDs as keys and the samples of that view as values.
+pub(crate) fn get_timed_samples_by_view(
+    conn: &Connection,
+    start: Option<&str>,
+    end: Option<&str>,
+    filter: Option<&Filter>,
+    column: &str,
+    table: &str,
+) -> Result<HashMap<i32, TimedSamples>> {
+    let mut criteria = util::get_common_criteria(conn, start, end, filter, None)?;
+    criteria.push("view_id is not null".to_string());
+    let sql = format!(
+        "select view_id, timestamp, {} from {} where {} order by timestamp",
+        column,
+        table,
+        criteria.join(" and ")
+    );
+    eprintln!("Executing query: {}", sql);
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+    let mut by_view: HashMap<i32, TimedSamples> = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let view_id: i32 = row.get(0)?;
+        let samples = by_view.entry(view_id).or_default();
+        samples.timestamp.push(row.get(1)?);
+        samples.value.push(row.get(2)?);
+    }
+    Ok(by_view)
+}
+
+/// Read time-based samples from the database, grouped by the value of an SQL expression.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `start` - The start time for the samples. If `None`, the query starts at the
+///   beginning of the data.
+/// * `end` - The end time for the samples. If `None`, the query ends at the end of the
+///   data.
+/// * `filter` - An optional `Filter` object that contains the filter configuration.
+/// * `column` - The name of the column to read from the database.
+/// * `table` - The name of the table to read from the database.
+/// * `group_by` - An SQL expression to group the samples by, e.g. a column name. Samples where
+///   the expression is null are ignored.
+///
+/// # Returns
+/// * A map with the value of the expression, formatted as a string, as keys and the samples of
+///   that group as values.
+pub(crate) fn get_timed_samples_by_group(
+    conn: &Connection,
+    start: Option<&str>,
+    end: Option<&str>,
+    filter: Option<&Filter>,
+    column: &str,
+    table: &str,
+    group_by: &str,
+) -> Result<HashMap<String, TimedSamples>> {
+    let mut criteria = util::get_common_criteria(conn, start, end, filter, None)?;
+    criteria.push(format!("({}) is not null", group_by));
+    let sql = format!(
+        "select {}, timestamp, {} from {} where {} order by timestamp",
+        group_by,
+        column,
+        table,
+        criteria.join(" and ")
+    );
+    eprintln!("Executing query: {}", sql);
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+    let mut by_group: HashMap<String, TimedSamples> = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let key = match row.get::<_, rusqlite::types::Value>(0)? {
+            rusqlite::types::Value::Integer(value) => value.to_string(),
+            rusqlite::types::Value::Real(value) => value.to_string(),
+            rusqlite::types::Value::Text(value) => value,
+            value => return Err(format!("Can not group by value {:?}", value).into()),
+        };
+        let samples = by_group.entry(key).or_default();
+        samples.timestamp.push(row.get(1)?);
+        samples.value.push(row.get(2)?);
+    }
+    Ok(by_group)
+}
+
+/// Samples of a single series of an SQL plot, ordered by timestamp.
+#[derive(Default)]
+pub(crate) struct SqlSeries {
+    /// The value of the series column, or empty if the samples are not split into series.
+    pub name: String,
+    /// Timestamps of the samples, in nanoseconds since the Unix epoch.
+    pub timestamp: Vec<i64>,
+    /// Values of the samples.
+    pub value: Vec<f64>,
+}
+
+/// Read time-based samples from an SQL query given in the plot configuration, optionally split
+/// into series by the value of a column.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `filter` - An optional `Filter` object that contains the filter configuration. The start
+///   and end apply to the x column, and the `where` criteria may refer to any column returned
+///   by the query.
+/// * `plot_config` - The configuration for the SQL plot.
+///
+/// # Returns
+/// * The samples of each series, in the order in which the series first appear. If
+///   there is no series column, there is a single series with an empty name. Rows where the x
+///   or y value is null are ignored.
+pub(crate) fn get_sql_samples(
+    conn: &Connection,
+    filter: Option<&Filter>,
+    plot_config: &config::SqlPlot,
+) -> Result<Vec<SqlSeries>> {
+    let mut columns = vec![plot_config.x.as_str(), plot_config.y.as_str()];
+    if let Some(series) = &plot_config.series {
+        columns.push(series);
+    }
+    let mut sql = format!("select {} from ({})", columns.join(", "), plot_config.query);
+    let criteria = util::get_filter_criteria(
+        conn,
+        filter.and_then(|f| f.start.as_deref()),
+        filter.and_then(|f| f.end.as_deref()),
+        filter,
+        &plot_config.x,
+    )?;
+    if !criteria.is_empty() {
+        sql.push_str(" where ");
+        sql.push_str(&criteria.join(" and "));
+    }
+    sql.push_str(&format!(" order by {}", plot_config.x));
+    eprintln!("Executing query: {}", sql);
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| format!("Error in SQL plot query {}: {}", sql, e))?;
+    let mut rows = stmt.query([])?;
+    let mut series: Vec<SqlSeries> = Vec::new();
+    let mut series_index: HashMap<String, usize> = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let (Some(x), Some(y)) = (row.get::<_, Option<i64>>(0)?, row.get::<_, Option<f64>>(1)?)
+        else {
+            continue;
+        };
+        let key = match plot_config.series {
+            Some(_) => match row.get::<_, rusqlite::types::Value>(2)? {
+                rusqlite::types::Value::Null => "null".to_string(),
+                rusqlite::types::Value::Integer(value) => value.to_string(),
+                rusqlite::types::Value::Real(value) => value.to_string(),
+                rusqlite::types::Value::Text(value) => value,
+                value => return Err(format!("Can not split series by value {:?}", value).into()),
+            },
+            None => String::new(),
+        };
+        let index = match series_index.entry(key) {
+            hash_map::Entry::Occupied(entry) => *entry.get(),
+            hash_map::Entry::Vacant(entry) => {
+                series.push(SqlSeries {
+                    name: entry.key().clone(),
+                    ..Default::default()
+                });
+                *entry.insert(series.len() - 1)
+            }
+        };
+        series[index].timestamp.push(x);
+        series[index].value.push(y);
+    }
+    eprintln!(
+        "Data points: {}",
+        series.iter().map(|s| s.timestamp.len()).sum::<usize>()
+    );
+    Ok(series)
+}
+
+/// Samples of one value against another, with an optional value to color them by.
+#[derive(Default)]
+pub(crate) struct XySamples {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    /// The values to color the samples by, empty if the plot has no `colorby`.
+    pub color: Vec<rusqlite::types::Value>,
+}
+
+/// Read samples of one column or expression against another from a table, optionally joined
+/// with other tables on `timestamp` and `ordinal`.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `filter` - An optional `Filter` object that contains the filter configuration. The start
+///   and end apply to the timestamp of the main table.
+/// * `plot_config` - The configuration for the xy plot.
+///
+/// # Returns
+/// * The samples, in no particular order. Rows where the x or y value is null are ignored.
+pub(crate) fn get_xy_samples(
+    conn: &Connection,
+    filter: Option<&Filter>,
+    plot_config: &config::XyPlot,
+) -> Result<XySamples> {
+    let mut columns = vec![plot_config.x.as_str(), plot_config.y.as_str()];
+    if let Some(color_by) = &plot_config.color_by {
+        columns.push(color_by);
+    }
+    let mut sql = format!("select {} from {}", columns.join(", "), plot_config.table);
+    for table in plot_config.join.iter().flatten() {
+        sql.push_str(&format!(" inner join {} using (timestamp, ordinal)", table));
+    }
+    let criteria = util::get_common_criteria(
+        conn,
+        filter.and_then(|f| f.start.as_deref()),
+        filter.and_then(|f| f.end.as_deref()),
+        filter,
+        Some(&plot_config.table),
+    )?;
+    if !criteria.is_empty() {
+        sql.push_str(" where ");
+        sql.push_str(&criteria.join(" and "));
+    }
+    eprintln!("Executing query: {}", sql);
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| format!("Error in xy plot query {}: {}", sql, e))?;
+    let mut rows = stmt.query([])?;
+    let mut samples = XySamples::default();
+    while let Some(row) = rows.next()? {
+        let (Some(x), Some(y)) = (row.get::<_, Option<f64>>(0)?, row.get::<_, Option<f64>>(1)?)
+        else {
+            continue;
+        };
+        samples.x.push(x);
+        samples.y.push(y);
+        if plot_config.color_by.is_some() {
+            samples.color.push(row.get(2)?);
+        }
+    }
+    eprintln!("Data points: {}", samples.x.len());
+    Ok(samples)
+}
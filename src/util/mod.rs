@@ -1,23 +1,45 @@
+pub(crate) use annotation::*;
+pub(crate) use calendar::*;
+pub(crate) use change_point::*;
 pub(crate) use conversions::*;
+pub(crate) use export::*;
 pub(crate) use filter::*;
+pub(crate) use group::*;
+pub(crate) use histogram::*;
 pub(crate) use load::*;
 pub(crate) use normalize_sql::*;
 pub(crate) use overlap::*;
 pub(crate) use parse_csv::*;
 pub(crate) use plot::*;
+pub(crate) use rate::*;
 pub(crate) use read_stdin_string::*;
+pub(crate) use smoothing::*;
 pub(crate) use statistics::*;
+pub(crate) use step_function::*;
+pub(crate) use suggestion::*;
+pub(crate) use time_expression::*;
 pub(crate) use type_cast::*;
 
+mod annotation;
+mod calendar;
+mod change_point;
 mod conversions;
+mod export;
 mod filter;
+mod group;
+mod histogram;
 mod load;
 mod normalize_sql;
 mod overlap;
 mod parse_csv;
 mod plot;
+mod rate;
 mod read_stdin_string;
+mod smoothing;
 mod statistics;
+mod step_function;
+mod suggestion;
+mod time_expression;
 mod type_cast;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -54,6 +54,6 @@ pub(crate) fn normalize_sql(dialect: &dyn Dialect, sql: &str) -> Result<String>
             return Err(e.into());
         }
     };
-    ast.visit(&mut Normalizer);
+    let _ = ast.visit(&mut Normalizer);
     Ok(ast.to_string())
 }
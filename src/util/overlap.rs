@@ -5,9 +5,9 @@
 ///
 /// # Arguments
 /// * `wallclock_time` - A vector of `u64` values, where each value represents
-/// the wallclock duration of a query in nanoseconds.
+///   the wallclock duration of a query in nanoseconds.
 /// * `overlap` - A vector of `u64` values, where each value represents the
-/// total overlap with other queries in nanoseconds.
+///   total overlap with other queries in nanoseconds.
 pub(crate) fn overlap_to_percent(wallclock_time: &[u64], overlap: &[u64]) -> Vec<f64> {
     assert_eq!(wallclock_time.len(), overlap.len());
 
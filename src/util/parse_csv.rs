@@ -78,3 +78,19 @@ fn parse_datetime_arbitrary_precision(
 
     Ok(Utc.from_utc_datetime(&NaiveDateTime::new(date, time)))
 }
+
+/// Split a time range of the form `START..END` into its start and end. Either side may be
+/// omitted to leave the range open. A single date without `..` is a range covering that whole
+/// date at its given precision, so `2024-03` is the entire month of March 2024. The returned
+/// strings are meant to be passed to `parse_datetime_floor` and `parse_datetime_ceil`
+/// respectively.
+pub(crate) fn split_time_range(range: &str) -> (Option<&str>, Option<&str>) {
+    let (start, end) = match range.split_once("..") {
+        Some((start, end)) => (start.trim(), end.trim()),
+        None => (range.trim(), range.trim()),
+    };
+    (
+        Some(start).filter(|s| !s.is_empty()),
+        Some(end).filter(|s| !s.is_empty()),
+    )
+}
@@ -1,7 +1,9 @@
+use std::fmt::Debug;
 use std::ops::Add;
 
 use num_traits::One;
-use plotly::Scatter;
+use plotly::common::{ColorScale, ColorScaleElement};
+use plotly::{HeatMap, Scatter};
 
 use crate::{config, plot, util};
 
@@ -91,6 +93,34 @@ pub(crate) fn apply_common_plot_configuration(
     trace
 }
 
+/// Set the line shape of a trace, unless a line shape is set in the common plot configuration.
+/// This is used by plots that are naturally drawn as steps.
+///
+/// # Arguments
+/// * `trace` - The Plotly trace object, with the common configuration already applied.
+/// * `common_cfg` - The common plot configuration.
+/// * `line_color` - The color to use for the line of the trace.
+/// * `shape` - The default line shape.
+pub(crate) fn apply_default_line_shape(
+    trace: Box<Scatter<f64, f64>>,
+    common_cfg: &config::PlotCommon,
+    line_color: &str,
+    shape: plotly::common::LineShape,
+) -> Box<Scatter<f64, f64>> {
+    match &common_cfg.line {
+        Some(line) if line.shape.is_some() => trace,
+        Some(line) => {
+            let trace_line: plotly::common::Line = line.into();
+            trace.line(trace_line.color(line_color.to_owned()).shape(shape))
+        }
+        None => trace.line(
+            plotly::common::Line::new()
+                .color(line_color.to_owned())
+                .shape(shape),
+        ),
+    }
+}
+
 /// Get the color to use for the line of a trace. This either comes from the
 /// configuration, or is generated by the color generator in the plot context.
 pub(crate) fn get_line_color<'a>(
@@ -108,30 +138,225 @@ pub(crate) fn get_line_color<'a>(
     }
 }
 
-/// Aggregate and segment a set of data points according to the aggregation
-/// configuration in the common plot configuration. If no aggregation is
+/// Aggregate and segment a set of data points according to the transform and
+/// aggregation configuration in the common plot configuration. The transform,
+/// e.g. converting a counter to a rate, is applied first. If no aggregation is
 /// configured, the data is returned as a single segment. Otherwise,
 /// the data is aggregated according to the configuration and then split
-/// into segments where the x values are continuous.
-pub(crate) fn aggregate_and_segment(
+/// into segments where the x values are continuous. Time-weighted aggregation
+/// modes treat the samples as a step function, where each sample holds until
+/// the next one, see `aggregate_and_segment_step_function`.
+pub(crate) fn aggregate_and_segment<T>(
     common_cfg: &config::PlotCommon,
     timestamp: Vec<i64>,
-    value: Vec<u64>,
-) -> Vec<(Vec<i64>, Vec<f64>)> {
+    value: Vec<T>,
+) -> util::Result<Vec<(Vec<i64>, Vec<f64>)>>
+where
+    T: util::TypeCast<f64> + Copy + Default + Debug + PartialOrd,
+{
+    util::check_filter_support(
+        common_cfg.filter.as_ref(),
+        common_cfg.aggregation.as_ref(),
+        common_cfg.transform,
+    )?;
+    let segments = match common_cfg.transform {
+        Some(config::Transform::Rate) => {
+            let (timestamp, rate) = util::get_rate(&timestamp, &value);
+            aggregate_and_segment_values(common_cfg, timestamp, rate)?
+        }
+        None => aggregate_and_segment_values(common_cfg, timestamp, value)?,
+    };
+    util::smooth_segments(common_cfg.smoothing.as_ref(), segments)
+}
+
+/// Aggregate and segment a set of data points, after any transform has been applied.
+fn aggregate_and_segment_values<T>(
+    common_cfg: &config::PlotCommon,
+    timestamp: Vec<i64>,
+    value: Vec<T>,
+) -> util::Result<Vec<(Vec<i64>, Vec<f64>)>>
+where
+    T: util::TypeCast<f64> + Copy + Default + Debug + PartialOrd,
+{
     if let Some(ref aggregation) = &common_cfg.aggregation {
-        let binning_function = util::get_grouping_function_for_time_window(&aggregation.size);
+        if aggregation.mode.is_time_weighted() {
+            return aggregate_and_segment_steps(common_cfg, timestamp, value);
+        }
+
+        let binning_function =
+            util::get_grouping_function_for_time_window(&aggregation.size, aggregation.timezone);
         let (bin_indices, mut bin_values) =
             util::group_by_x(&timestamp, &value, aggregation.min_count, &binning_function);
 
         util::sort_bins_inplace(&mut bin_values);
         let statistics_per_bin = util::get_statistics_per_bin(&bin_values);
-        let value = util::extract_statistic(&statistics_per_bin, &aggregation.mode);
+        let value = util::extract_statistic(&statistics_per_bin, &aggregation.mode)?;
 
         let segments = split_discontinuities(bin_indices, value);
 
-        let unbinning_function = util::get_ungrouping_function_for_time_period(&aggregation.size);
-        util::ungroup_segments_by_x(&segments, &unbinning_function)
+        let unbinning_function =
+            util::get_ungrouping_function_for_time_period(&aggregation.size, aggregation.timezone);
+        Ok(util::ungroup_segments_by_x(&segments, &unbinning_function))
+    } else {
+        Ok(vec![(timestamp, value.iter().map(|x| x.cast()).collect())])
+    }
+}
+
+/// Aggregate and segment a step function, such as the number of active queries, according to
+/// the aggregation configuration in the common plot configuration. Unlike
+/// `aggregate_and_segment`, each value is weighted by how long it lasted, and a value that lasts
+/// across several time windows counts towards each of them. Windows where the statistic is
+/// undefined, e.g. the mean of a window that only contains the last value, are left out. The
+/// minimum count of the aggregation is ignored. Smoothing, if configured, is applied last.
+pub(crate) fn aggregate_and_segment_step_function<T>(
+    common_cfg: &config::PlotCommon,
+    timestamp: Vec<i64>,
+    value: Vec<T>,
+) -> util::Result<Vec<(Vec<i64>, Vec<f64>)>>
+where
+    T: util::TypeCast<f64>,
+{
+    let segments = aggregate_and_segment_steps(common_cfg, timestamp, value)?;
+    util::smooth_segments(common_cfg.smoothing.as_ref(), segments)
+}
+
+/// Aggregate and segment a step function, without smoothing.
+fn aggregate_and_segment_steps<T>(
+    common_cfg: &config::PlotCommon,
+    timestamp: Vec<i64>,
+    value: Vec<T>,
+) -> util::Result<Vec<(Vec<i64>, Vec<f64>)>>
+where
+    T: util::TypeCast<f64>,
+{
+    let value: Vec<f64> = value.iter().map(|x| x.cast()).collect();
+    if let Some(ref aggregation) = &common_cfg.aggregation {
+        let binning_function =
+            util::get_grouping_function_for_time_window(&aggregation.size, aggregation.timezone);
+        let unbinning_function =
+            util::get_ungrouping_function_for_time_period(&aggregation.size, aggregation.timezone);
+        let (window_indices, window_steps) = util::group_step_function_by_window(
+            &timestamp,
+            &value,
+            &binning_function,
+            &unbinning_function,
+        );
+        let mut indices = Vec::with_capacity(window_indices.len());
+        let mut statistics = Vec::with_capacity(window_indices.len());
+        for (index, steps) in window_indices.into_iter().zip(window_steps.iter()) {
+            let statistic = util::get_time_weighted_statistic(steps, &aggregation.mode)?;
+            if !statistic.is_nan() {
+                indices.push(index);
+                statistics.push(statistic);
+            }
+        }
+
+        let segments = split_discontinuities(indices, statistics);
+        Ok(util::ungroup_segments_by_x(&segments, &unbinning_function))
     } else {
-        vec![(timestamp, value.iter().map(|x| *x as f64).collect())]
+        Ok(vec![(timestamp, value)])
+    }
+}
+
+/// Detect change points in an aggregated series and mark them as vertical lines in the plot, if
+/// change points are enabled in the common plot configuration. The segments are treated as one
+/// continuous series, since gaps in the data should not by themselves count as changes.
+///
+/// # Arguments
+/// * `context` - The plot context to add the vertical lines to.
+/// * `common_cfg` - The common plot configuration.
+/// * `segments` - The aggregated segments, as returned by `aggregate_and_segment`, with x values
+///   in nanoseconds since the Unix epoch.
+/// * `line_color` - The color to use for the vertical lines.
+///
+/// # Errors
+/// If change points are enabled without an aggregation, since they are detected in the
+/// aggregated series.
+pub(crate) fn mark_change_points(
+    context: &plot::PlotContext,
+    common_cfg: &config::PlotCommon,
+    segments: &[(Vec<i64>, Vec<f64>)],
+    line_color: &str,
+) -> util::Result<()> {
+    let change_point_cfg = match &common_cfg.change_points {
+        Some(change_point_cfg) => change_point_cfg,
+        None => return Ok(()),
+    };
+    if common_cfg.aggregation.is_none() {
+        return Err("changepoints requires an aggregation".into());
+    }
+    let x: Vec<i64> = segments
+        .iter()
+        .flat_map(|(x, _)| x.iter().copied())
+        .collect();
+    let y: Vec<f64> = segments
+        .iter()
+        .flat_map(|(_, y)| y.iter().copied())
+        .collect();
+
+    let change_points = util::detect_relative_change_points(&y, change_point_cfg);
+    eprintln!("Found {} change points", change_points.len());
+
+    let x_ref = common_cfg.x_axis.as_deref().unwrap_or("x");
+    let mut shapes = context.shapes.borrow_mut();
+    for index in change_points {
+        let x = util::nanoseconds_epoch_to_milliseconds(x[index]);
+        shapes.push(
+            plotly::layout::Shape::new()
+                .shape_type(plotly::layout::ShapeType::Line)
+                .x_ref(x_ref)
+                .y_ref("paper")
+                .x0(x)
+                .x1(x)
+                .y0(0.0)
+                .y1(1.0)
+                .line(
+                    plotly::layout::ShapeLine::new()
+                        .color(line_color.to_owned())
+                        .dash(plotly::common::DashType::Dash),
+                ),
+        );
     }
+    Ok(())
+}
+
+/// Draw a 2D histogram as a heat map, with the common plot configuration applied. The colors
+/// follow the logarithm of the counts, to emphasize small values, and empty bins are black. The
+/// cells are placed by the bin edges, so they line up with the data for any range and for
+/// logarithmic bins alike.
+///
+/// # Arguments
+/// * `histogram` - The 2D histogram, as returned by `make_histogram_2d`.
+/// * `common_cfg` - The common plot configuration.
+pub(crate) fn get_log_count_heat_map(
+    histogram: util::Histogram2d,
+    common_cfg: &config::PlotCommon,
+) -> Box<HeatMap<f64, f64, Vec<f64>>> {
+    let z = histogram
+        .counts
+        .iter()
+        .map(|row| row.iter().map(|x| (*x as f64 + 1.0).ln()).collect())
+        .collect();
+
+    let custom_color_scale = ColorScale::Vector(vec![
+        ColorScaleElement(0.0, "black".to_string()),
+        ColorScaleElement(1e-6, "black".to_string()),
+        ColorScaleElement(1e-5, "white".to_string()),
+        ColorScaleElement(0.5, "orange".to_string()),
+        ColorScaleElement(1.0, "red".to_string()),
+    ]);
+
+    let mut trace = HeatMap::new(histogram.x_edges, histogram.y_edges, z)
+        .color_scale(custom_color_scale)
+        .name(&common_cfg.name);
+    if let Some(visible) = &common_cfg.visible {
+        trace = trace.visible(visible.into());
+    }
+    if let Some(x_axis) = &common_cfg.x_axis {
+        trace = trace.x_axis(x_axis);
+    }
+    if let Some(y_axis) = &common_cfg.y_axis {
+        trace = trace.y_axis(y_axis);
+    }
+    trace
 }
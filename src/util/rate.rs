@@ -0,0 +1,51 @@
+use crate::util::TypeCast;
+
+/// Convert the samples of a cumulative counter, such as the CPU time used by a process, to the
+/// rate at which the counter increases per second. The rate between two consecutive samples is
+/// assigned to the later one. If the counter decreases, it is assumed to have been reset, e.g.
+/// by a restart, and to have counted from zero up to the new value since the previous sample.
+///
+/// # Arguments
+/// * `timestamp` - The time of each sample, in nanoseconds since the Unix epoch, in increasing
+///   order.
+/// * `value` - The value of the counter at each sample.
+///
+/// # Returns
+/// The timestamps and rates, with one sample less than the input. Samples with the same
+/// timestamp as the previous one are skipped, since the rate between them is undefined.
+pub(crate) fn get_rate<T: TypeCast<f64>>(timestamp: &[i64], value: &[T]) -> (Vec<i64>, Vec<f64>) {
+    assert_eq!(timestamp.len(), value.len());
+    let mut rate_timestamp: Vec<i64> = Vec::with_capacity(timestamp.len().saturating_sub(1));
+    let mut rate: Vec<f64> = Vec::with_capacity(timestamp.len().saturating_sub(1));
+    for i in 1..timestamp.len() {
+        let elapsed = timestamp[i] - timestamp[i - 1];
+        if elapsed <= 0 {
+            continue;
+        }
+        let (previous, current): (f64, f64) = (value[i - 1].cast(), value[i].cast());
+        let increase = if current >= previous {
+            current - previous
+        } else {
+            current
+        };
+        rate_timestamp.push(timestamp[i]);
+        rate.push(increase / (elapsed as f64 / 1e9));
+    }
+    (rate_timestamp, rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_rate() {
+        let second = 1_000_000_000;
+        let (timestamp, rate) = get_rate(
+            &[0, 2 * second, 2 * second, 4 * second, 5 * second],
+            &[100u64, 120, 120, 5, 15],
+        );
+        assert_eq!(timestamp, vec![2 * second, 4 * second, 5 * second]);
+        assert_eq!(rate, vec![10.0, 2.5, 10.0]);
+    }
+}
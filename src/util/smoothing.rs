@@ -0,0 +1,305 @@
+use crate::config::{Smoothing, SmoothingMethod, SmoothingWindow};
+use crate::util::{self, Result};
+
+/// The default `span` of `loess`.
+const DEFAULT_LOESS_SPAN: f64 = 0.3;
+/// The maximum number of values that `loess` fits a line at.
+const MAX_LOESS_FITS: usize = 500;
+
+/// Smooth the values of a series according to the smoothing configuration, if any. The segments
+/// are smoothed as one series, so that rolling windows and trend lines continue across gaps, and
+/// are then split into the same segments again.
+///
+/// # Arguments
+/// * `smoothing` - The smoothing configuration.
+/// * `segments` - The segments of the series, with x values in nanoseconds since the Unix epoch.
+pub(crate) fn smooth_segments(
+    smoothing: Option<&Smoothing>,
+    segments: Vec<(Vec<i64>, Vec<f64>)>,
+) -> Result<Vec<(Vec<i64>, Vec<f64>)>> {
+    let Some(smoothing) = smoothing else {
+        return Ok(segments);
+    };
+    check_smoothing(smoothing)?;
+    let x: Vec<i64> = segments
+        .iter()
+        .flat_map(|(x, _)| x.iter().copied())
+        .collect();
+    let y: Vec<f64> = segments
+        .iter()
+        .flat_map(|(_, y)| y.iter().copied())
+        .collect();
+    let mut smoothed = smooth(smoothing, &x, &y).into_iter();
+    Ok(segments
+        .into_iter()
+        .map(|(x, y)| {
+            let y = smoothed.by_ref().take(y.len()).collect();
+            (x, y)
+        })
+        .collect())
+}
+
+/// Check that the settings of a smoothing configuration are valid and apply to its method.
+fn check_smoothing(smoothing: &Smoothing) -> Result<()> {
+    let method = smoothing.method;
+    let unused = |name: &str| -> Result<()> {
+        Err(format!(
+            "{} does not apply to the smoothing method {:?}",
+            name, method
+        )
+        .into())
+    };
+    let rolling = matches!(
+        method,
+        SmoothingMethod::Mean | SmoothingMethod::Median | SmoothingMethod::Percentile
+    );
+    match smoothing.window {
+        Some(SmoothingWindow::Values(0)) => return Err("The smoothing window is empty".into()),
+        Some(SmoothingWindow::Time(period)) if period.as_nanoseconds().unwrap_or(0) <= 0 => {
+            return Err(
+                "The smoothing window must be a positive time span, not in months or years".into(),
+            )
+        }
+        Some(_) if !rolling && method != SmoothingMethod::Ewma => unused("window")?,
+        None if rolling => {
+            return Err(format!("The smoothing method {:?} needs a window", method).into())
+        }
+        _ => (),
+    }
+    match smoothing.percentile {
+        Some(_) if method != SmoothingMethod::Percentile => unused("percentile")?,
+        Some(percentile) if !(0.0..=100.0).contains(&percentile) => {
+            return Err("The smoothing percentile must be from 0 to 100".into())
+        }
+        None if method == SmoothingMethod::Percentile => {
+            return Err("The smoothing method Percentile needs a percentile".into())
+        }
+        _ => (),
+    }
+    match (smoothing.alpha, smoothing.window) {
+        (Some(_), _) if method != SmoothingMethod::Ewma => unused("alpha")?,
+        (Some(_), Some(_)) => return Err("Set either alpha or window for ewma, not both".into()),
+        (Some(alpha), None) if !(alpha > 0.0 && alpha <= 1.0) => {
+            return Err("The smoothing alpha must be greater than 0 and at most 1".into())
+        }
+        (None, None) if method == SmoothingMethod::Ewma => {
+            return Err("The smoothing method Ewma needs alpha or a window".into())
+        }
+        _ => (),
+    }
+    match smoothing.span {
+        Some(_) if method != SmoothingMethod::Loess => unused("span")?,
+        Some(span) if !(span > 0.0 && span <= 1.0) => {
+            return Err("The smoothing span must be greater than 0 and at most 1".into())
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+fn smooth(smoothing: &Smoothing, x: &[i64], y: &[f64]) -> Vec<f64> {
+    match smoothing.method {
+        SmoothingMethod::Mean => rolling(x, y, smoothing.window.unwrap(), |window| {
+            util::kahan_sum(window.iter()) / window.len() as f64
+        }),
+        SmoothingMethod::Median => rolling(x, y, smoothing.window.unwrap(), |window| {
+            util::get_median(window)
+        }),
+        SmoothingMethod::Percentile => {
+            let percentile = smoothing.percentile.unwrap() / 100.0;
+            rolling(x, y, smoothing.window.unwrap(), |window| {
+                util::get_percentile(window, percentile)
+            })
+        }
+        SmoothingMethod::Ewma => ewma(x, y, smoothing.alpha, smoothing.window),
+        SmoothingMethod::Linear => linear_trend(x, y),
+        SmoothingMethod::Loess => loess(x, y, smoothing.span.unwrap_or(DEFAULT_LOESS_SPAN)),
+    }
+}
+
+/// Compute a statistic of the trailing window at each value. The window is kept sorted, so that
+/// order statistics such as the median can be computed directly.
+fn rolling(
+    x: &[i64],
+    y: &[f64],
+    window: SmoothingWindow,
+    statistic: impl Fn(&[f64]) -> f64,
+) -> Vec<f64> {
+    let mut sorted: Vec<f64> = Vec::new();
+    let mut start = 0;
+    let mut result = Vec::with_capacity(y.len());
+    for (i, &value) in y.iter().enumerate() {
+        let position = sorted.partition_point(|v| v.total_cmp(&value).is_lt());
+        sorted.insert(position, value);
+        while match window {
+            SmoothingWindow::Values(count) => i + 1 - start > count,
+            SmoothingWindow::Time(period) => x[i] - x[start] >= period.as_nanoseconds().unwrap(),
+        } {
+            let old = y[start];
+            let position = sorted.partition_point(|v| v.total_cmp(&old).is_lt());
+            sorted.remove(position);
+            start += 1;
+        }
+        result.push(statistic(&sorted));
+    }
+    result
+}
+
+/// The exponentially weighted moving average. A window of `n` values corresponds to an alpha of
+/// `2 / (n + 1)`. With a time window, the weight of the previous average decays by a factor `e`
+/// over the window, so that irregularly spaced values are weighted by the time between them.
+fn ewma(x: &[i64], y: &[f64], alpha: Option<f64>, window: Option<SmoothingWindow>) -> Vec<f64> {
+    let mut result = Vec::with_capacity(y.len());
+    let mut average = match y.first() {
+        Some(first) => *first,
+        None => return result,
+    };
+    for (i, &value) in y.iter().enumerate() {
+        let alpha = match (alpha, window) {
+            (Some(alpha), _) => alpha,
+            (None, Some(SmoothingWindow::Values(count))) => 2.0 / (count as f64 + 1.0),
+            (None, Some(SmoothingWindow::Time(period))) if i > 0 => {
+                let elapsed = (x[i] - x[i - 1]) as f64;
+                1.0 - (-elapsed / period.as_nanoseconds().unwrap() as f64).exp()
+            }
+            _ => 1.0,
+        };
+        average += alpha * (value - average);
+        result.push(average);
+    }
+    result
+}
+
+/// Fit a line with weighted least squares, and return its value at `x0` and its slope per
+/// nanosecond. The x values are relative to `x0`, since nanosecond timestamps lose precision when
+/// squared.
+fn weighted_linear_fit(x: &[i64], y: &[f64], weight: impl Fn(i64) -> f64, x0: i64) -> (f64, f64) {
+    let (mut sum_w, mut sum_x, mut sum_y) = (0.0, 0.0, 0.0);
+    for (&x, &y) in x.iter().zip(y) {
+        let w = weight(x);
+        sum_w += w;
+        sum_x += w * (x - x0) as f64;
+        sum_y += w * y;
+    }
+    let (mean_x, mean_y) = (sum_x / sum_w, sum_y / sum_w);
+    let (mut sum_xy, mut sum_xx) = (0.0, 0.0);
+    for (&x, &y) in x.iter().zip(y) {
+        let w = weight(x);
+        let dx = (x - x0) as f64 - mean_x;
+        sum_xy += w * dx * (y - mean_y);
+        sum_xx += w * dx * dx;
+    }
+    let slope = if sum_xx > 0.0 { sum_xy / sum_xx } else { 0.0 };
+    (mean_y - slope * mean_x, slope)
+}
+
+/// A least-squares linear trend line, evaluated at each x value.
+fn linear_trend(x: &[i64], y: &[f64]) -> Vec<f64> {
+    let Some(&x0) = x.first() else {
+        return Vec::new();
+    };
+    let (intercept, slope) = weighted_linear_fit(x, y, |_| 1.0, x0);
+    x.iter()
+        .map(|&x| intercept + slope * (x - x0) as f64)
+        .collect()
+}
+
+/// A LOESS trend curve: at each x value, a line is fitted to the `span` fraction of the values
+/// that are nearest to it, weighted by the tricube of their distance. For long series, the curve
+/// is only fitted at `MAX_LOESS_FITS` evenly spaced values and interpolated linearly in between.
+fn loess(x: &[i64], y: &[f64], span: f64) -> Vec<f64> {
+    let n = x.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let count = ((span * n as f64).ceil() as usize).clamp(2.min(n), n);
+    let step = n.div_ceil(MAX_LOESS_FITS);
+    let mut fitted: Vec<usize> = (0..n).step_by(step).collect();
+    if fitted.last() != Some(&(n - 1)) {
+        fitted.push(n - 1);
+    }
+
+    let mut start = 0;
+    let mut fits = Vec::with_capacity(fitted.len());
+    for &i in &fitted {
+        let x_i = x[i];
+        // The nearest values form a window around x_i, which moves forward with x_i.
+        while start + count < n && x[start + count] - x_i < x_i - x[start] {
+            start += 1;
+        }
+        let (window_x, window_y) = (&x[start..start + count], &y[start..start + count]);
+        let max_distance = (x_i - window_x[0]).max(window_x[count - 1] - x_i) as f64;
+        let tricube = |x: i64| {
+            if max_distance == 0.0 {
+                return 1.0;
+            }
+            let u = (x - x_i).abs() as f64 / max_distance;
+            (1.0 - u * u * u).powi(3)
+        };
+        fits.push(weighted_linear_fit(window_x, window_y, tricube, x_i).0);
+    }
+
+    let mut result = Vec::with_capacity(n);
+    for (pair, fit) in fitted.windows(2).zip(fits.windows(2)) {
+        let (i, j) = (pair[0], pair[1]);
+        for k in i..j {
+            let fraction = if x[j] > x[i] {
+                (x[k] - x[i]) as f64 / (x[j] - x[i]) as f64
+            } else {
+                0.0
+            };
+            result.push(fit[0] + fraction * (fit[1] - fit[0]));
+        }
+    }
+    result.push(*fits.last().unwrap());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{TimePeriod, TimeUnit};
+
+    #[test]
+    fn test_smooth_segments() {
+        let smoothing = |method, window| Smoothing {
+            method,
+            window,
+            percentile: None,
+            alpha: None,
+            span: None,
+        };
+        let segments = vec![
+            (vec![0, 1_000, 2_000], vec![1.0, 5.0, 3.0]),
+            (vec![10_000, 11_000], vec![7.0, 9.0]),
+        ];
+
+        let median = smoothing(SmoothingMethod::Median, Some(SmoothingWindow::Values(3)));
+        let smoothed = smooth_segments(Some(&median), segments.clone()).unwrap();
+        assert_eq!(smoothed[0], (vec![0, 1_000, 2_000], vec![1.0, 3.0, 3.0]));
+        assert_eq!(smoothed[1], (vec![10_000, 11_000], vec![5.0, 7.0]));
+
+        let period = TimePeriod {
+            quantity: 2,
+            unit: TimeUnit::Microseconds,
+        };
+        let mean = smoothing(SmoothingMethod::Mean, Some(SmoothingWindow::Time(period)));
+        let smoothed = smooth_segments(Some(&mean), segments.clone()).unwrap();
+        assert_eq!(smoothed[0].1, vec![1.0, 3.0, 4.0]);
+        assert_eq!(smoothed[1].1, vec![7.0, 8.0]);
+
+        let linear = smoothing(SmoothingMethod::Linear, None);
+        let line = smooth_segments(Some(&linear), vec![(vec![0, 10, 20], vec![1.0, 2.0, 3.0])]);
+        assert_eq!(line.unwrap()[0].1, vec![1.0, 2.0, 3.0]);
+
+        let x: Vec<i64> = (0..1000).collect();
+        let y: Vec<f64> = x.iter().map(|&x| (2 * x + 1) as f64).collect();
+        let loess = smoothing(SmoothingMethod::Loess, None);
+        let curve = smooth_segments(Some(&loess), vec![(x, y.clone())]).unwrap();
+        assert!(curve[0].1.iter().zip(&y).all(|(a, b)| (a - b).abs() < 1e-6));
+
+        assert!(
+            smooth_segments(Some(&smoothing(SmoothingMethod::Median, None)), segments).is_err()
+        );
+    }
+}
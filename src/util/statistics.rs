@@ -1,8 +1,10 @@
 use std::fmt::Debug;
 
-use crate::config;
-use crate::config::{AggregationMode, TimeUnit};
+use chrono::{Datelike, NaiveDate};
+
+use crate::config::{AggregationMode, TimeUnit, Timezone};
 use crate::util::TypeCast;
+use crate::{config, util};
 
 /// Given a set of x and y values, group the data into bins based on the x values.
 /// The x values are assumed to be sorted by the bin index that they map to.
@@ -11,7 +13,7 @@ use crate::util::TypeCast;
 /// * `x` - A vector of x values.
 /// * `y` - A vector of y values.
 /// * `min_count` - An optional minimum count for each bin. If a bin has fewer elements than this
-/// count, it is skipped.
+///   count, it is skipped.
 /// * `map_to_bin` - A function that maps an x value to a bin index.
 ///
 /// # Returns
@@ -40,30 +42,34 @@ where
     let mut current_bin_index: B = map_to_bin(x[0]);
     current_bin_values.push(y[0]);
 
+    let is_too_small = |values: &Vec<V>| {
+        min_count
+            .map(|min_count| values.len() < min_count)
+            .unwrap_or(false)
+    };
+
     for i in 1..x.len() {
         let bin_index = map_to_bin(x[i]);
-        if bin_index == current_bin_index {
-            current_bin_values.push(y[i]);
-        } else {
+        if bin_index != current_bin_index {
             assert!(
                 bin_index > current_bin_index,
                 "Values are not sorted by bin index"
             );
-            let skip = min_count
-                .map(|min_count| current_bin_values.len() < min_count)
-                .unwrap_or(false);
-            if !skip {
+            if !is_too_small(&current_bin_values) {
                 bin_indices.push(current_bin_index);
                 bin_values.push(current_bin_values.clone());
             }
             current_bin_index = bin_index;
             current_bin_values.clear();
         }
+        current_bin_values.push(y[i]);
     }
 
-    bin_indices.push(current_bin_index);
-    current_bin_values.shrink_to_fit();
-    bin_values.push(current_bin_values);
+    if !is_too_small(&current_bin_values) {
+        bin_indices.push(current_bin_index);
+        current_bin_values.shrink_to_fit();
+        bin_values.push(current_bin_values);
+    }
 
     (bin_indices, bin_values)
 }
@@ -74,14 +80,14 @@ where
 ///
 /// # Arguments
 /// * `segments` - A vector of tuples, where each tuple contains a vector of x values and a vector
-/// of y values.
+///   of y values.
 /// * `ungrouping_function` - A function that maps a bin index to an x value.
 ///
 /// # Returns
 /// The x and y values, where the x values are computed from the bin indices. The segments
 /// are preserved.
 pub(crate) fn ungroup_segments_by_x<X1, Y, X2, F>(
-    segments: &Vec<(Vec<X1>, Vec<Y>)>,
+    segments: &[(Vec<X1>, Vec<Y>)],
     ungrouping_function: &F,
 ) -> Vec<(Vec<X2>, Vec<Y>)>
 where
@@ -192,12 +198,19 @@ where
 }
 
 /// Dispatch function to extract the requested statistic from a vector of statistics.
-pub(crate) fn extract_statistic<T>(statistics: &[Statistics<T>], mode: &AggregationMode) -> Vec<f64>
+///
+/// # Errors
+/// For time-weighted aggregation modes, which cannot be computed from the statistics since they
+/// need the timestamps of the samples, see `get_time_weighted_statistic`.
+pub(crate) fn extract_statistic<T>(
+    statistics: &[Statistics<T>],
+    mode: &AggregationMode,
+) -> util::Result<Vec<f64>>
 where
     T: Copy + Default + Debug + TypeCast<f64>,
 {
     // Map to y values depending on which aggregation mode is requested.
-    match mode {
+    Ok(match mode {
         AggregationMode::Mean => statistics.iter().map(|s| s.mean).collect(),
         AggregationMode::Min => statistics.iter().map(|s| s.min.cast()).collect(),
         AggregationMode::Q1 => statistics.iter().map(|s| s.q1).collect(),
@@ -205,7 +218,12 @@ where
         AggregationMode::Q3 => statistics.iter().map(|s| s.q3).collect(),
         AggregationMode::Max => statistics.iter().map(|s| s.max.cast()).collect(),
         AggregationMode::Count => statistics.iter().map(|s| s.count as f64).collect(),
-    }
+        AggregationMode::TimeWeightedMean | AggregationMode::Last | AggregationMode::Integral => {
+            return Err(
+                "Time-weighted aggregation modes require the timestamps of the samples".into(),
+            )
+        }
+    })
 }
 
 /// Get the median of a sorted slice of numbers. If the slice has an even number of elements,
@@ -215,13 +233,43 @@ where
     T: TypeCast<f64> + Copy,
 {
     let len = sorted_slice.len();
-    if len % 2 == 0 {
+    if len.is_multiple_of(2) {
         (sorted_slice[len / 2].cast() + sorted_slice[len / 2 - 1].cast()) / 2.0
     } else {
         sorted_slice[len / 2].cast()
     }
 }
 
+/// Get the ratio of the median after a change to the median before it, where values above 1.0
+/// mean that the value increased. A median of zero before is treated as no change if it is still
+/// zero, and as an unbounded increase otherwise.
+pub(crate) fn get_median_ratio(before_median: f64, after_median: f64) -> f64 {
+    if before_median == 0.0 {
+        if after_median == 0.0 {
+            return 1.0;
+        }
+        return f64::INFINITY;
+    }
+    after_median / before_median
+}
+
+/// Get the median absolute deviation (MAD) of a sorted slice of numbers, i.e. the median of the
+/// absolute deviations from the median. This is a robust measure of spread that, unlike the
+/// standard deviation, is not inflated by a few extreme values. Multiply by 1.4826 to get an
+/// estimate of the standard deviation for normally distributed data.
+pub(crate) fn get_median_absolute_deviation<T>(sorted_slice: &[T]) -> f64
+where
+    T: TypeCast<f64> + Copy,
+{
+    let median = get_median(sorted_slice);
+    let mut deviations: Vec<f64> = sorted_slice
+        .iter()
+        .map(|x| (x.cast() - median).abs())
+        .collect();
+    deviations.sort_by(|a, b| a.total_cmp(b));
+    get_median(&deviations)
+}
+
 /// Calculate the sum of a sequence of numbers using the Kahan summation algorithm.
 /// This algorithm is used to reduce the error in the sum of a sequence of floating point numbers.
 /// The algorithm is described in detail at https://en.wikipedia.org/wiki/Kahan_summation_algorithm.
@@ -243,15 +291,24 @@ where
 
 /// Get the function to group timestamps into bins for a given time window.
 ///
+/// Weeks, months and years follow the calendar: weeks are ISO weeks starting on Monday, and
+/// months and years start on the first day of the month or year. Several weeks, months or years
+/// are grouped so that the bins are aligned with the epoch, e.g. `3M` gives quarters and `2W`
+/// gives pairs of weeks that start on every other Monday since 1970. Days follow the calendar
+/// only if a time zone is given, since they are otherwise aligned with UTC midnight anyway.
+///
 /// # Arguments
 /// * `time_period` - The time period to group by.
+/// * `timezone` - The time zone that calendar bins are aligned to. Defaults to UTC.
 ///
 /// # Returns
 /// A function that takes a timestamp and returns the bin index.
 pub(crate) fn get_grouping_function_for_time_window(
     time_period: &config::TimePeriod,
+    timezone: Option<Timezone>,
 ) -> Box<dyn Fn(i64) -> i64> {
     let quantity = time_period.quantity as i64;
+    let calendar_timezone = timezone.unwrap_or(Timezone::Named(chrono_tz::UTC));
     match time_period.unit {
         TimeUnit::Nanoseconds => {
             #[allow(clippy::identity_op)]
@@ -278,15 +335,27 @@ pub(crate) fn get_grouping_function_for_time_window(
             let factor = quantity * 3_600_000_000_000;
             Box::new(move |timestamp| timestamp / factor)
         }
+        TimeUnit::Days if timezone.is_some() => Box::new(move |timestamp| {
+            let date = util::nanoseconds_epoch_to_date(timestamp, calendar_timezone);
+            get_day_index(date).div_euclid(quantity)
+        }),
         TimeUnit::Days => {
             let factor = quantity * 86_400_000_000_000;
             Box::new(move |timestamp| timestamp / factor)
         }
-        TimeUnit::Weeks => {
-            todo!()
-        }
-        TimeUnit::Months => todo!(),
-        TimeUnit::Years => todo!(),
+        TimeUnit::Weeks => Box::new(move |timestamp| {
+            let date = util::nanoseconds_epoch_to_date(timestamp, calendar_timezone);
+            // The epoch is a Thursday, so the first ISO week starts three days before it.
+            (get_day_index(date) + 3).div_euclid(7).div_euclid(quantity)
+        }),
+        TimeUnit::Months => Box::new(move |timestamp| {
+            let date = util::nanoseconds_epoch_to_date(timestamp, calendar_timezone);
+            (date.year() as i64 * 12 + date.month0() as i64).div_euclid(quantity)
+        }),
+        TimeUnit::Years => Box::new(move |timestamp| {
+            let date = util::nanoseconds_epoch_to_date(timestamp, calendar_timezone);
+            (date.year() as i64).div_euclid(quantity)
+        }),
     }
 }
 
@@ -295,13 +364,16 @@ pub(crate) fn get_grouping_function_for_time_window(
 ///
 /// # Arguments
 /// * `time_period` - The time period to group by.
+/// * `timezone` - The time zone that calendar bins are aligned to. Defaults to UTC.
 ///
 /// # Returns
 /// A function that takes a bin index and returns the timestamp at which that bin starts.
 pub(crate) fn get_ungrouping_function_for_time_period(
     time_period: &config::TimePeriod,
+    timezone: Option<Timezone>,
 ) -> Box<dyn Fn(i64) -> i64> {
     let quantity = time_period.quantity as i64;
+    let calendar_timezone = timezone.unwrap_or(Timezone::Named(chrono_tz::UTC));
     match time_period.unit {
         TimeUnit::Nanoseconds => {
             #[allow(clippy::identity_op)]
@@ -328,14 +400,500 @@ pub(crate) fn get_ungrouping_function_for_time_period(
             let factor = quantity * 3_600_000_000_000;
             Box::new(move |timestamp| timestamp * factor)
         }
+        TimeUnit::Days if timezone.is_some() => Box::new(move |index| {
+            let date = get_date_from_day_index(index * quantity);
+            util::date_to_nanoseconds_epoch(date, calendar_timezone)
+        }),
         TimeUnit::Days => {
             let factor = quantity * 86_400_000_000_000;
             Box::new(move |timestamp| timestamp * factor)
         }
-        TimeUnit::Weeks => {
-            todo!()
+        TimeUnit::Weeks => Box::new(move |index| {
+            let date = get_date_from_day_index(index * quantity * 7 - 3);
+            util::date_to_nanoseconds_epoch(date, calendar_timezone)
+        }),
+        TimeUnit::Months => Box::new(move |index| {
+            let month = index * quantity;
+            let date = NaiveDate::from_ymd_opt(
+                month.div_euclid(12) as i32,
+                month.rem_euclid(12) as u32 + 1,
+                1,
+            )
+            .expect("Date out of range");
+            util::date_to_nanoseconds_epoch(date, calendar_timezone)
+        }),
+        TimeUnit::Years => Box::new(move |index| {
+            let date = NaiveDate::from_ymd_opt((index * quantity) as i32, 1, 1)
+                .expect("Date out of range");
+            util::date_to_nanoseconds_epoch(date, calendar_timezone)
+        }),
+    }
+}
+
+/// Get the number of days from the epoch to a date.
+fn get_day_index(date: NaiveDate) -> i64 {
+    (date - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days()
+}
+
+/// Get the date a number of days from the epoch.
+fn get_date_from_day_index(index: i64) -> NaiveDate {
+    NaiveDate::from_ymd_opt(1970, 1, 1).unwrap() + chrono::Duration::days(index)
+}
+
+/// Get a percentile of a sorted slice of numbers, using linear interpolation between the two
+/// closest ranks. Note that this is not the method used for the quartiles in `Statistics`, which
+/// are computed as the medians of the lower and upper halves of the data.
+///
+/// # Arguments
+/// * `sorted_slice` - A sorted, non-empty slice of numbers.
+/// * `percentile` - The percentile to get, as a fraction between 0.0 and 1.0.
+pub(crate) fn get_percentile<T>(sorted_slice: &[T], percentile: f64) -> f64
+where
+    T: TypeCast<f64> + Copy,
+{
+    assert!(!sorted_slice.is_empty());
+    let position = percentile.clamp(0.0, 1.0) * (sorted_slice.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let fraction = position - lower as f64;
+    let lower_value = sorted_slice[lower].cast();
+    lower_value + (sorted_slice[upper].cast() - lower_value) * fraction
+}
+
+/// The result of a Mann-Whitney U test.
+#[derive(Debug, Clone)]
+pub(crate) struct MannWhitneyU {
+    /// The U statistic for the first sample, i.e. the number of pairs where the value from the
+    /// first sample is greater than the value from the second sample (ties count as one half).
+    pub u: f64,
+    /// The standardized test statistic, using the normal approximation with tie and continuity
+    /// correction.
+    pub z: f64,
+    /// The two-sided p-value.
+    pub p_value: f64,
+    /// The rank-biserial correlation, between -1.0 and 1.0. Positive values mean that values in
+    /// the first sample tend to be greater than values in the second sample.
+    pub effect_size: f64,
+}
+
+/// Perform a two-sided Mann-Whitney U test of whether the values in `x` tend to be greater or
+/// smaller than the values in `y`. The p-value is computed using the normal approximation, which
+/// is accurate when both samples have more than about 20 elements.
+pub(crate) fn mann_whitney_u(x: &[f64], y: &[f64]) -> MannWhitneyU {
+    let n1 = x.len() as f64;
+    let n2 = y.len() as f64;
+    let n = n1 + n2;
+
+    let mut combined: Vec<(f64, bool)> = x
+        .iter()
+        .map(|v| (*v, true))
+        .chain(y.iter().map(|v| (*v, false)))
+        .collect();
+    combined.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // Assign ranks, using the average rank for ties.
+    let mut rank_sum_x = 0.0;
+    let mut tie_sum = 0.0;
+    let mut i = 0;
+    while i < combined.len() {
+        let mut j = i + 1;
+        while j < combined.len() && combined[j].0 == combined[i].0 {
+            j += 1;
+        }
+        let ties = (j - i) as f64;
+        let average_rank = (i + j + 1) as f64 / 2.0;
+        rank_sum_x += average_rank * combined[i..j].iter().filter(|(_, in_x)| *in_x).count() as f64;
+        tie_sum += ties * ties * ties - ties;
+        i = j;
+    }
+
+    let u = rank_sum_x - n1 * (n1 + 1.0) / 2.0;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_sum / (n * (n - 1.0)));
+    let (z, p_value) = if variance > 0.0 {
+        let delta = u - mean;
+        let corrected = (delta.abs() - 0.5).max(0.0) * delta.signum();
+        let z = corrected / variance.sqrt();
+        (z, erfc(z.abs() / std::f64::consts::SQRT_2))
+    } else {
+        (0.0, 1.0)
+    };
+
+    MannWhitneyU {
+        u,
+        z,
+        p_value,
+        effect_size: 2.0 * u / (n1 * n2) - 1.0,
+    }
+}
+
+/// The result of a two-sample Kolmogorov-Smirnov test.
+#[derive(Debug, Clone)]
+pub(crate) struct KolmogorovSmirnov {
+    /// The maximum distance between the empirical distribution functions of the two samples.
+    pub d: f64,
+    /// The p-value, using the asymptotic Kolmogorov distribution.
+    pub p_value: f64,
+}
+
+/// Perform a two-sample Kolmogorov-Smirnov test of whether two sorted samples come from the same
+/// distribution. Unlike the Mann-Whitney U test, this also detects changes in the shape of the
+/// distribution, such as a view becoming bimodal.
+pub(crate) fn kolmogorov_smirnov(sorted_x: &[f64], sorted_y: &[f64]) -> KolmogorovSmirnov {
+    let n1 = sorted_x.len();
+    let n2 = sorted_y.len();
+    let mut i = 0;
+    let mut j = 0;
+    let mut d: f64 = 0.0;
+    while i < n1 && j < n2 {
+        let value = sorted_x[i].min(sorted_y[j]);
+        while i < n1 && sorted_x[i] <= value {
+            i += 1;
+        }
+        while j < n2 && sorted_y[j] <= value {
+            j += 1;
+        }
+        d = d.max((i as f64 / n1 as f64 - j as f64 / n2 as f64).abs());
+    }
+
+    let effective_n = ((n1 * n2) as f64 / (n1 + n2) as f64).sqrt();
+    let lambda = (effective_n + 0.12 + 0.11 / effective_n) * d;
+    KolmogorovSmirnov {
+        d,
+        p_value: kolmogorov_survival(lambda),
+    }
+}
+
+/// The survival function of the Kolmogorov distribution, i.e. the probability that the
+/// (scaled) Kolmogorov-Smirnov statistic exceeds `lambda`.
+fn kolmogorov_survival(lambda: f64) -> f64 {
+    if lambda < 0.2 {
+        // The series converges too slowly here, but the value is 1.0 to within 1e-30.
+        return 1.0;
+    }
+    let mut sum = 0.0;
+    let mut sign = 1.0;
+    for j in 1..=100 {
+        let j = j as f64;
+        let term = sign * 2.0 * (-2.0 * j * j * lambda * lambda).exp();
+        sum += term;
+        if term.abs() < 1e-12 {
+            break;
         }
-        TimeUnit::Months => todo!(),
-        TimeUnit::Years => todo!(),
+        sign = -sign;
+    }
+    sum.clamp(0.0, 1.0)
+}
+
+/// The complementary error function, using the rational approximation from Numerical Recipes
+/// with a fractional error of less than 1.2e-7.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let polynomial = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let result = t * polynomial.exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+/// Get the rank of each value, starting at 1.0, using the average rank for ties.
+pub(crate) fn get_ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i + 1;
+        while j < order.len() && values[order[j]] == values[order[i]] {
+            j += 1;
+        }
+        let average_rank = (i + j + 1) as f64 / 2.0;
+        for index in &order[i..j] {
+            ranks[*index] = average_rank;
+        }
+        i = j;
+    }
+    ranks
+}
+
+/// Compute the Pearson correlation coefficient between two equally long series.
+///
+/// # Returns
+/// The correlation, between -1.0 and 1.0, or NaN if either series is constant or has fewer than
+/// two values.
+pub(crate) fn pearson_correlation(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len().min(y.len());
+    if n < 2 {
+        return f64::NAN;
+    }
+    let mean_x = kahan_sum(x[..n].iter().copied()) / n as f64;
+    let mean_y = kahan_sum(y[..n].iter().copied()) / n as f64;
+    let mut covariance = 0.0;
+    let mut variance_x = 0.0;
+    let mut variance_y = 0.0;
+    for i in 0..n {
+        let dx = x[i] - mean_x;
+        let dy = y[i] - mean_y;
+        covariance += dx * dy;
+        variance_x += dx * dx;
+        variance_y += dy * dy;
+    }
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return f64::NAN;
+    }
+    (covariance / (variance_x * variance_y).sqrt()).clamp(-1.0, 1.0)
+}
+
+/// Compute the Spearman rank correlation coefficient between two equally long series. Unlike the
+/// Pearson correlation, it captures any monotonic relationship and is not dominated by outliers.
+pub(crate) fn spearman_correlation(x: &[f64], y: &[f64]) -> f64 {
+    pearson_correlation(&get_ranks(x), &get_ranks(y))
+}
+
+/// A small, fast pseudo-random number generator (SplitMix64). It is used for bootstrap
+/// resampling, where we want reproducible output for a given seed rather than cryptographic
+/// quality.
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Get a random index in the range `0..n`.
+    pub(crate) fn next_index(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// Draw a sorted bootstrap resample, i.e. a sample of the same size drawn with replacement.
+fn sorted_resample(samples: &[f64], rng: &mut Rng, resample: &mut Vec<f64>) {
+    resample.clear();
+    resample.extend((0..samples.len()).map(|_| samples[rng.next_index(samples.len())]));
+    resample.sort_by(|a, b| a.total_cmp(b));
+}
+
+/// Compute a bootstrap confidence interval for a statistic of a sample, using the percentile
+/// method.
+///
+/// # Arguments
+/// * `samples` - The sample to compute the confidence interval for. Must not be empty.
+/// * `statistic` - A function that computes the statistic from a sorted slice, for example
+///   `get_median`.
+/// * `resamples` - The number of bootstrap resamples to draw. Must not be zero.
+/// * `confidence` - The confidence level, e.g. 0.95 for a 95% confidence interval. Must be
+///   between 0 and 1, exclusive.
+/// * `rng` - The random number generator to use for resampling.
+///
+/// # Returns
+/// The lower and upper bounds of the confidence interval.
+pub(crate) fn bootstrap_confidence_interval<F>(
+    samples: &[f64],
+    statistic: &F,
+    resamples: usize,
+    confidence: f64,
+    rng: &mut Rng,
+) -> (f64, f64)
+where
+    F: Fn(&[f64]) -> f64,
+{
+    let mut resample = Vec::with_capacity(samples.len());
+    let mut estimates: Vec<f64> = (0..resamples)
+        .map(|_| {
+            sorted_resample(samples, rng, &mut resample);
+            statistic(&resample)
+        })
+        .collect();
+    estimates.sort_by(|a, b| a.total_cmp(b));
+    confidence_bounds(&estimates, confidence)
+}
+
+/// Compute a bootstrap confidence interval for the difference `statistic(y) - statistic(x)`
+/// between two independent samples, using the percentile method. The arguments are the same as
+/// for `bootstrap_confidence_interval`.
+pub(crate) fn bootstrap_difference_confidence_interval<F>(
+    x: &[f64],
+    y: &[f64],
+    statistic: &F,
+    resamples: usize,
+    confidence: f64,
+    rng: &mut Rng,
+) -> (f64, f64)
+where
+    F: Fn(&[f64]) -> f64,
+{
+    let mut resample_x = Vec::with_capacity(x.len());
+    let mut resample_y = Vec::with_capacity(y.len());
+    let mut estimates: Vec<f64> = (0..resamples)
+        .map(|_| {
+            sorted_resample(x, rng, &mut resample_x);
+            sorted_resample(y, rng, &mut resample_y);
+            statistic(&resample_y) - statistic(&resample_x)
+        })
+        .collect();
+    estimates.sort_by(|a, b| a.total_cmp(b));
+    confidence_bounds(&estimates, confidence)
+}
+
+/// Get the bounds of the central `confidence` fraction of a sorted set of estimates.
+fn confidence_bounds(sorted_estimates: &[f64], confidence: f64) -> (f64, f64) {
+    let tail = (1.0 - confidence) / 2.0;
+    (
+        get_percentile(sorted_estimates, tail),
+        get_percentile(sorted_estimates, 1.0 - tail),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_by_x() {
+        let x = [0, 1, 10, 11, 12, 20, 30, 31];
+        let y = [1, 2, 3, 4, 5, 6, 7, 8];
+        let (bins, values) = group_by_x(&x, &y, None, &|x| x / 10);
+        assert_eq!(bins, vec![0, 1, 2, 3]);
+        assert_eq!(values, vec![vec![1, 2], vec![3, 4, 5], vec![6], vec![7, 8]]);
+
+        // Bins with too few values are skipped, including the last one.
+        let (bins, values) = group_by_x(&x, &y, Some(3), &|x| x / 10);
+        assert_eq!(bins, vec![1]);
+        assert_eq!(values, vec![vec![3, 4, 5]]);
+        let (bins, _) = group_by_x(&x[..6], &y[..6], Some(2), &|x| x / 10);
+        assert_eq!(bins, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_get_percentile() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(get_percentile(&values, 0.0), 1.0);
+        assert_eq!(get_percentile(&values, 0.5), 3.0);
+        assert_eq!(get_percentile(&values, 0.9), 4.6);
+        assert_eq!(get_percentile(&values, 1.0), 5.0);
+    }
+
+    #[test]
+    fn test_get_median_ratio() {
+        assert_eq!(get_median_ratio(2.0, 3.0), 1.5);
+        assert_eq!(get_median_ratio(0.0, 0.0), 1.0);
+        assert_eq!(get_median_ratio(0.0, 3.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_mann_whitney_u() {
+        // Reference p-values computed using the normal approximation with tie and continuity
+        // correction, as in scipy.stats.mannwhitneyu(method="asymptotic").
+        let result = mann_whitney_u(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]);
+        assert_eq!(result.u, 0.0);
+        assert_eq!(result.effect_size, -1.0);
+        assert!((result.p_value - 0.080856).abs() < 1e-5);
+
+        let result = mann_whitney_u(&[1.0, 2.0, 2.0, 3.0, 5.0], &[2.0, 3.0, 4.0, 4.0, 6.0]);
+        assert_eq!(result.u, 6.5);
+        assert!((result.p_value - 0.241844).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_kolmogorov_smirnov() {
+        let x = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let result = kolmogorov_smirnov(&x, &x);
+        assert_eq!(result.d, 0.0);
+        assert_eq!(result.p_value, 1.0);
+
+        let result = kolmogorov_smirnov(&x, &[6.0, 7.0, 8.0, 9.0, 10.0]);
+        assert_eq!(result.d, 1.0);
+        assert!(result.p_value < 0.01);
+    }
+
+    #[test]
+    fn test_correlation() {
+        assert_eq!(get_ranks(&[3.0, 1.0, 2.0, 1.0]), vec![4.0, 1.5, 3.0, 1.5]);
+
+        let x = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert!((pearson_correlation(&x, &[2.0, 4.0, 6.0, 8.0, 10.0]) - 1.0).abs() < 1e-12);
+        assert!((pearson_correlation(&x, &[5.0, 4.0, 3.0, 2.0, 1.0]) + 1.0).abs() < 1e-12);
+        assert!((pearson_correlation(&x, &[2.0, 1.0, 4.0, 3.0, 5.0]) - 0.8).abs() < 1e-12);
+        assert!(pearson_correlation(&x, &[1.0; 5]).is_nan());
+
+        // A monotonic but non-linear relationship has a perfect rank correlation.
+        let y = [1.0, 10.0, 100.0, 1000.0, 10000.0];
+        assert!(pearson_correlation(&x, &y) < 1.0);
+        assert!((spearman_correlation(&x, &y) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_calendar_grouping() {
+        let period = |s: &str| s.parse::<config::TimePeriod>().unwrap();
+        let time = |s: &str| {
+            util::parse_datetime_floor(s)
+                .unwrap()
+                .timestamp_nanos_opt()
+                .unwrap()
+        };
+
+        // 2024-01-01 is a Monday, so it starts both an ISO week and a quarter.
+        let group = get_grouping_function_for_time_window(&period("1W"), None);
+        let ungroup = get_ungrouping_function_for_time_period(&period("1W"), None);
+        assert_eq!(
+            group(time("2024-01-07 23:59:59")),
+            group(time("2024-01-01"))
+        );
+        assert_eq!(
+            ungroup(group(time("2024-01-03 12:00:00"))),
+            time("2024-01-01")
+        );
+        assert_eq!(ungroup(group(time("2024-01-03")) + 1), time("2024-01-08"));
+
+        let group = get_grouping_function_for_time_window(&period("3M"), None);
+        let ungroup = get_ungrouping_function_for_time_period(&period("3M"), None);
+        assert_eq!(
+            ungroup(group(time("2024-03-31 23:59:59"))),
+            time("2024-01-01")
+        );
+        assert_eq!(ungroup(group(time("2024-05-15")) + 1), time("2024-07-01"));
+
+        let group = get_grouping_function_for_time_window(&period("1Y"), None);
+        let ungroup = get_ungrouping_function_for_time_period(&period("1Y"), None);
+        assert_eq!(
+            ungroup(group(time("2023-12-31 23:59:59"))),
+            time("2023-01-01")
+        );
+
+        // Midnight in Stockholm is 23:00 UTC in winter.
+        let timezone = Some("Europe/Stockholm".parse::<Timezone>().unwrap());
+        let group = get_grouping_function_for_time_window(&period("1M"), timezone);
+        let ungroup = get_ungrouping_function_for_time_period(&period("1M"), timezone);
+        assert_eq!(
+            ungroup(group(time("2023-12-31 23:30:00"))),
+            time("2023-12-31 23:00:00")
+        );
+        let group = get_grouping_function_for_time_window(&period("1D"), timezone);
+        let ungroup = get_ungrouping_function_for_time_period(&period("1D"), timezone);
+        assert_eq!(
+            ungroup(group(time("2024-06-01 12:00:00"))),
+            time("2024-05-31 22:00:00")
+        );
     }
 }
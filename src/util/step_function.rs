@@ -0,0 +1,288 @@
+use crate::config::AggregationMode;
+use crate::util::Result;
+
+/// Compute the number of active queries over time from the start times and durations of the
+/// queries, in the same format as the `active_query_count` table.
+///
+/// # Arguments
+/// * `start_time` - The start time of each query, in nanoseconds since the Unix epoch.
+/// * `duration` - The duration of each query, in nanoseconds.
+///
+/// # Returns
+/// The times at which the count changed, in increasing order, and the count from that time until
+/// the next change.
+pub(crate) fn get_active_query_counts(
+    start_time: &[i64],
+    duration: &[u64],
+) -> (Vec<i64>, Vec<u64>) {
+    assert_eq!(start_time.len(), duration.len());
+    let mut events: Vec<(i64, i64)> = Vec::with_capacity(2 * start_time.len());
+    for (start, duration) in start_time.iter().zip(duration.iter()) {
+        events.push((*start, 1));
+        events.push((*start + *duration as i64, -1));
+    }
+    events.sort();
+
+    let mut timestamp: Vec<i64> = Vec::new();
+    let mut count: Vec<u64> = Vec::new();
+    let mut active: i64 = 0;
+    for (time, change) in events {
+        active += change;
+        // Only keep the last count for each point in time.
+        if timestamp.last() == Some(&time) {
+            *count.last_mut().unwrap() = active as u64;
+        } else {
+            timestamp.push(time);
+            count.push(active as u64);
+        }
+    }
+    (timestamp, count)
+}
+
+/// Clip a step function to a time range. The value in effect at the start of the range gets a
+/// step at the start, and if the function continues past the end of the range, the value in
+/// effect at the end gets a final step at the end.
+///
+/// # Arguments
+/// * `timestamp` - The times at which the value changed, in increasing order.
+/// * `value` - The value from each timestamp until the next.
+/// * `start` - The start of the range. If `None`, the range starts at the first step.
+/// * `end` - The end of the range. If `None`, the range ends at the last step.
+pub(crate) fn clip_step_function<T: Copy>(
+    timestamp: &[i64],
+    value: &[T],
+    start: Option<i64>,
+    end: Option<i64>,
+) -> (Vec<i64>, Vec<T>) {
+    assert_eq!(timestamp.len(), value.len());
+    let first = start.map_or(0, |start| timestamp.partition_point(|t| *t < start));
+    let last = end.map_or(timestamp.len(), |end| {
+        timestamp.partition_point(|t| *t <= end)
+    });
+    let mut clipped_timestamp: Vec<i64> = Vec::new();
+    let mut clipped_value: Vec<T> = Vec::new();
+    if let Some(start) = start {
+        if first > 0 && timestamp.get(first) != Some(&start) {
+            clipped_timestamp.push(start);
+            clipped_value.push(value[first - 1]);
+        }
+    }
+    for i in first..last.max(first) {
+        clipped_timestamp.push(timestamp[i]);
+        clipped_value.push(value[i]);
+    }
+    if let Some(end) = end {
+        if last > 0 && last < timestamp.len() && clipped_timestamp.last() != Some(&end) {
+            clipped_timestamp.push(end);
+            clipped_value.push(value[last - 1]);
+        }
+    }
+    (clipped_timestamp, clipped_value)
+}
+
+/// Split a step function into time windows. Each step lasts from its timestamp until the next
+/// one, so the last step has no duration. It is still included, so that the last value of the
+/// window it falls into is known. A step that spans several windows contributes to each of them,
+/// for the time it overlaps with the window.
+///
+/// # Arguments
+/// * `timestamp` - The times at which the value changed, in increasing order.
+/// * `value` - The value from each timestamp until the next.
+/// * `binning_function` - A function that maps a timestamp to a window index.
+/// * `unbinning_function` - A function that maps a window index to the start of the window.
+///
+/// # Returns
+/// The indices of the windows that are covered by the step function, in increasing order, and
+/// for each window the `(value, duration)` pairs of the steps within it.
+pub(crate) fn group_step_function_by_window<F, U>(
+    timestamp: &[i64],
+    value: &[f64],
+    binning_function: &F,
+    unbinning_function: &U,
+) -> (Vec<i64>, Vec<Vec<(f64, i64)>>)
+where
+    F: Fn(i64) -> i64 + ?Sized,
+    U: Fn(i64) -> i64 + ?Sized,
+{
+    assert_eq!(timestamp.len(), value.len());
+    let mut window_indices: Vec<i64> = Vec::new();
+    let mut window_steps: Vec<Vec<(f64, i64)>> = Vec::new();
+    for i in 1..timestamp.len() {
+        let mut current = timestamp[i - 1];
+        let end = timestamp[i];
+        while current < end {
+            let window = binning_function(current);
+            let window_end = unbinning_function(window + 1).min(end);
+            if window_indices.last() != Some(&window) {
+                window_indices.push(window);
+                window_steps.push(Vec::new());
+            }
+            window_steps
+                .last_mut()
+                .unwrap()
+                .push((value[i - 1], window_end - current));
+            current = window_end;
+        }
+    }
+    if let (Some(last_timestamp), Some(last_value)) = (timestamp.last(), value.last()) {
+        let window = binning_function(*last_timestamp);
+        if window_indices.last() != Some(&window) {
+            window_indices.push(window);
+            window_steps.push(Vec::new());
+        }
+        window_steps.last_mut().unwrap().push((*last_value, 0));
+    }
+    (window_indices, window_steps)
+}
+
+/// Compute a time-weighted statistic of the steps within a window, where each value counts in
+/// proportion to how long it lasted rather than once per change.
+///
+/// # Arguments
+/// * `steps` - The `(value, duration)` pairs of the steps within the window, in order.
+/// * `mode` - The statistic to compute. Every statistic of a step function is time-weighted, so
+///   `Mean` is the same as `TimeWeightedMean`, and the percentiles are the values that are not
+///   exceeded for that fraction of the time. `Count` is not supported, since a step
+///   function has no samples to count.
+///
+/// # Returns
+/// The statistic, or NaN if the steps within the window have no duration. `Last` is the value
+/// of the last step even if it has no duration, and `Integral` is in value-seconds.
+pub(crate) fn get_time_weighted_statistic(
+    steps: &[(f64, i64)],
+    mode: &AggregationMode,
+) -> Result<f64> {
+    if *mode == AggregationMode::Last {
+        return Ok(steps.last().map(|(value, _)| *value).unwrap_or(f64::NAN));
+    }
+
+    // Steps without duration, like the last one, have no weight.
+    let steps: Vec<(f64, i64)> = steps
+        .iter()
+        .copied()
+        .filter(|(_, duration)| *duration > 0)
+        .collect();
+    let total: i64 = steps.iter().map(|(_, duration)| duration).sum();
+    if steps.is_empty() || total == 0 {
+        return Ok(f64::NAN);
+    }
+    let percentile = match mode {
+        AggregationMode::Integral => {
+            let sum: f64 = steps
+                .iter()
+                .map(|(value, duration)| value * *duration as f64)
+                .sum();
+            return Ok(sum / 1e9);
+        }
+        AggregationMode::Mean | AggregationMode::TimeWeightedMean => {
+            let sum: f64 = steps
+                .iter()
+                .map(|(value, duration)| value * *duration as f64)
+                .sum();
+            return Ok(sum / total as f64);
+        }
+        AggregationMode::Min => {
+            return Ok(steps
+                .iter()
+                .map(|(value, _)| *value)
+                .fold(f64::INFINITY, f64::min))
+        }
+        AggregationMode::Max => {
+            return Ok(steps
+                .iter()
+                .map(|(value, _)| *value)
+                .fold(f64::NEG_INFINITY, f64::max))
+        }
+        AggregationMode::Q1 => 0.25,
+        AggregationMode::Median => 0.5,
+        AggregationMode::Q3 => 0.75,
+        AggregationMode::Count => {
+            return Err("Aggregation mode count is not supported for step functions".into())
+        }
+        AggregationMode::Last => unreachable!(),
+    };
+
+    // The value that is not exceeded for the given fraction of the time.
+    let mut sorted = steps;
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let threshold = percentile * total as f64;
+    let mut accumulated = 0;
+    for (value, duration) in sorted.iter() {
+        accumulated += duration;
+        if accumulated as f64 >= threshold {
+            return Ok(*value);
+        }
+    }
+    Ok(sorted.last().unwrap().0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_active_query_counts() {
+        let (timestamp, count) = get_active_query_counts(&[0, 5, 10], &[10, 2, 5]);
+        assert_eq!(timestamp, vec![0, 5, 7, 10, 15]);
+        assert_eq!(count, vec![1, 2, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_clip_step_function() {
+        let timestamp = [0, 10, 20, 30];
+        let value = [1, 2, 3, 0];
+        assert_eq!(
+            clip_step_function(&timestamp, &value, Some(5), Some(25)),
+            (vec![5, 10, 20, 25], vec![1, 2, 3, 3])
+        );
+        assert_eq!(
+            clip_step_function(&timestamp, &value, Some(10), Some(30)),
+            (vec![10, 20, 30], vec![2, 3, 0])
+        );
+        assert_eq!(
+            clip_step_function(&timestamp, &value, None, None),
+            (timestamp.to_vec(), value.to_vec())
+        );
+        assert_eq!(
+            clip_step_function(&timestamp, &value, Some(12), Some(15)),
+            (vec![12, 15], vec![2, 2])
+        );
+        assert_eq!(
+            clip_step_function(&timestamp, &value, Some(40), None),
+            (vec![40], vec![0])
+        );
+    }
+
+    #[test]
+    fn test_group_step_function_by_window() {
+        let binning_function = |t: i64| t / 10;
+        let unbinning_function = |i: i64| i * 10;
+        let (windows, steps) = group_step_function_by_window(
+            &[0, 5, 25, 30],
+            &[1.0, 3.0, 2.0, 0.0],
+            &binning_function,
+            &unbinning_function,
+        );
+        assert_eq!(windows, vec![0, 1, 2, 3]);
+        assert_eq!(steps[0], vec![(1.0, 5), (3.0, 5)]);
+        assert_eq!(steps[1], vec![(3.0, 10)]);
+        assert_eq!(steps[2], vec![(3.0, 5), (2.0, 5)]);
+        assert_eq!(steps[3], vec![(0.0, 0)]);
+
+        let mean = get_time_weighted_statistic(&steps[0], &AggregationMode::Mean).unwrap();
+        assert_eq!(mean, 2.0);
+        let max = get_time_weighted_statistic(&steps[2], &AggregationMode::Max).unwrap();
+        assert_eq!(max, 3.0);
+        let median = get_time_weighted_statistic(&[(1.0, 9), (5.0, 1)], &AggregationMode::Median);
+        assert_eq!(median.unwrap(), 1.0);
+        let min = get_time_weighted_statistic(&[(1.0, 5), (0.0, 0)], &AggregationMode::Min);
+        assert_eq!(min.unwrap(), 1.0);
+        let last = get_time_weighted_statistic(&steps[3], &AggregationMode::Last).unwrap();
+        assert_eq!(last, 0.0);
+        let mean = get_time_weighted_statistic(&steps[3], &AggregationMode::Mean).unwrap();
+        assert!(mean.is_nan());
+        let integral =
+            get_time_weighted_statistic(&[(2.0, 3_000_000_000)], &AggregationMode::Integral);
+        assert_eq!(integral.unwrap(), 6.0);
+    }
+}
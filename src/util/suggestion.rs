@@ -0,0 +1,42 @@
+/// The minimum similarity, as the normalized Damerau-Levenshtein distance, for a name to be
+/// suggested for a misspelled one.
+const MIN_SUGGESTION_SIMILARITY: f64 = 0.7;
+
+/// Suggest the candidate that is most similar to a misspelled name, such as a configuration key
+/// or a column, to add to an error message.
+///
+/// # Returns
+/// `, did you mean `candidate`?`, or an empty string if no candidate is similar enough.
+pub(crate) fn get_suggestion<'a>(
+    misspelled: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> String {
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            (
+                strsim::normalized_damerau_levenshtein(misspelled, candidate),
+                candidate,
+            )
+        })
+        .filter(|(similarity, _)| *similarity >= MIN_SUGGESTION_SIMILARITY)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| format!(", did you mean `{}`?", candidate))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_suggestion() {
+        let candidates = ["mode", "size", "mincount", "timezone"];
+        assert_eq!(get_suggestion("sise", candidates), ", did you mean `size`?");
+        assert_eq!(
+            get_suggestion("min_count", candidates),
+            ", did you mean `mincount`?"
+        );
+        assert_eq!(get_suggestion("colour", candidates), "");
+    }
+}
@@ -0,0 +1,275 @@
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, TimeZone, Utc, Weekday};
+use regex::Regex;
+use rusqlite::Connection;
+
+use crate::config::{TimePeriod, TimeUnit};
+use crate::util::{self, Result};
+
+/*
+ * Time expressions are used for the start and end of filters. Besides absolute dates, which are
+ * handled by `parse_datetime_floor` and `parse_datetime_ceil`, they can be relative to the current
+ * time or to the last timestamp in the database, or name a calendar period such as a week. Like
+ * an absolute date at a given precision, each expression covers a period of time: `start` is the
+ * beginning of the period and `end` is its end, so `start: last week` and `end: last week` select
+ * the whole of last week. Calendar periods are in UTC, like the absolute dates.
+ */
+
+lazy_static! {
+    static ref OFFSET_REGEX: Regex =
+        Regex::new(r"^(?P<base>.*?)\s*(?P<sign>[+-])\s*(?P<period>\d+\s*[A-Za-z]+)$").unwrap();
+    static ref ISO_WEEK_REGEX: Regex =
+        Regex::new(r"^(?P<year>\d{4})-W(?P<week>\d{2})(?:-(?P<day>[1-7]))?$").unwrap();
+    static ref NAMED_PERIOD_REGEX: Regex =
+        Regex::new(r"^(?i)(?P<which>last|this)\s+(?P<period>[a-z]+)$").unwrap();
+}
+
+const EXPECTED_FORMATS: &str = "expected YYYY[-MM[-DD[ HH[:MM[:SS]]]]], YYYY-Www[-D], now, \
+    latest, today, yesterday, last <weekday>, last or this week, month or year, optionally \
+    followed by offsets such as -7d or +2h";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    Floor,
+    Ceil,
+}
+
+/// Parse a time expression into the beginning of the period that it covers.
+///
+/// # Arguments
+/// * `conn` - The database that `latest` refers to, if any.
+/// * `expression` - The time expression, e.g. `2024-03`, `2024-W12`, `now`, `-7d`, `last monday`
+///   or `latest - 24h`.
+pub(crate) fn parse_time_floor(
+    conn: Option<&Connection>,
+    expression: &str,
+) -> Result<DateTime<Utc>> {
+    parse_time_expression(conn, expression, Bound::Floor, Utc::now())
+}
+
+/// Parse a time expression into the end of the period that it covers, see `parse_time_floor`.
+pub(crate) fn parse_time_ceil(
+    conn: Option<&Connection>,
+    expression: &str,
+) -> Result<DateTime<Utc>> {
+    parse_time_expression(conn, expression, Bound::Ceil, Utc::now())
+}
+
+/// Parse a time expression into the beginning and the end of the period that it covers, see
+/// `parse_time_floor`. Both are relative to the same current time.
+pub(crate) fn parse_time_range(
+    conn: Option<&Connection>,
+    expression: &str,
+) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let now = Utc::now();
+    Ok((
+        parse_time_expression(conn, expression, Bound::Floor, now)?,
+        parse_time_expression(conn, expression, Bound::Ceil, now)?,
+    ))
+}
+
+fn parse_time_expression(
+    conn: Option<&Connection>,
+    expression: &str,
+    bound: Bound,
+    now: DateTime<Utc>,
+) -> Result<DateTime<Utc>> {
+    let expression = expression.trim();
+    if let Some(captures) = OFFSET_REGEX.captures(expression) {
+        let base = captures.name("base").unwrap().as_str();
+        // An offset without a base, such as `-7d`, is relative to the current time.
+        let base = if base.is_empty() {
+            now
+        } else {
+            parse_time_expression(conn, base, bound, now)?
+        };
+        let period = captures.name("period").unwrap().as_str();
+        let negative = captures.name("sign").unwrap().as_str() == "-";
+        return add_offset(base, period, negative);
+    }
+
+    let today = now.date_naive();
+    match expression.to_lowercase().as_str() {
+        "" => return Err(EXPECTED_FORMATS.into()),
+        "now" => return Ok(now),
+        "latest" => {
+            let conn = conn.ok_or("latest can only be used with a database")?;
+            return get_latest_timestamp(conn);
+        }
+        "today" => return Ok(get_bound(today, today + Duration::days(1), bound)),
+        "yesterday" => {
+            return Ok(get_bound(today - Duration::days(1), today, bound));
+        }
+        _ => (),
+    }
+
+    if let Some(captures) = NAMED_PERIOD_REGEX.captures(expression) {
+        let last = captures["which"].eq_ignore_ascii_case("last");
+        let period = captures["period"].to_lowercase();
+        let (start, end) = match period.as_str() {
+            "week" => {
+                let start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                let start = if last {
+                    start - Duration::weeks(1)
+                } else {
+                    start
+                };
+                (start, start + Duration::weeks(1))
+            }
+            "month" => {
+                let start = today.with_day(1).unwrap();
+                let start = if last { start - Months::new(1) } else { start };
+                (start, start + Months::new(1))
+            }
+            "year" => {
+                let year = if last { today.year() - 1 } else { today.year() };
+                let start = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+                (start, start + Months::new(12))
+            }
+            weekday if last => {
+                let weekday: Weekday = weekday
+                    .parse()
+                    .map_err(|_| format!("Unknown period {}, {}", weekday, EXPECTED_FORMATS))?;
+                // The most recent such day before today.
+                let days_back = (today.weekday().num_days_from_monday() + 6
+                    - weekday.num_days_from_monday())
+                    % 7
+                    + 1;
+                let start = today - Duration::days(days_back as i64);
+                (start, start + Duration::days(1))
+            }
+            _ => return Err(format!("Unknown period {}, {}", period, EXPECTED_FORMATS).into()),
+        };
+        return Ok(get_bound(start, end, bound));
+    }
+
+    if let Some(captures) = ISO_WEEK_REGEX.captures(expression) {
+        let year: i32 = captures["year"].parse().unwrap();
+        let week: u32 = captures["week"].parse().unwrap();
+        let monday = NaiveDate::from_isoywd_opt(year, week, Weekday::Mon)
+            .ok_or_else(|| format!("Week {} does not exist in {}", week, year))?;
+        let (start, end) = match captures.name("day") {
+            Some(day) => {
+                let start = monday + Duration::days(day.as_str().parse::<i64>().unwrap() - 1);
+                (start, start + Duration::days(1))
+            }
+            None => (monday, monday + Duration::weeks(1)),
+        };
+        return Ok(get_bound(start, end, bound));
+    }
+
+    let datetime = match bound {
+        Bound::Floor => util::parse_datetime_floor(expression),
+        Bound::Ceil => util::parse_datetime_ceil(expression),
+    };
+    datetime.map_err(|e| format!("{}, {}", e, EXPECTED_FORMATS).into())
+}
+
+/// Get the beginning or the end of the days from `start` to `end`, exclusive. Like the absolute
+/// dates, the end is the last second of the period.
+fn get_bound(start: NaiveDate, end: NaiveDate, bound: Bound) -> DateTime<Utc> {
+    let date = match bound {
+        Bound::Floor => start,
+        Bound::Ceil => end,
+    };
+    let datetime = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap());
+    match bound {
+        Bound::Floor => datetime,
+        Bound::Ceil => datetime - Duration::seconds(1),
+    }
+}
+
+/// Add or subtract a time period such as `7D` or `24h`. For convenience, days, weeks and years
+/// can also be given as `d`, `w` and `y`.
+fn add_offset(datetime: DateTime<Utc>, offset: &str, negative: bool) -> Result<DateTime<Utc>> {
+    let period = match offset.strip_suffix(['d', 'w', 'y']) {
+        Some(quantity) => format!("{}{}", quantity, offset[quantity.len()..].to_uppercase()),
+        None => offset.to_string(),
+    };
+    let period: TimePeriod = period
+        .parse()
+        .map_err(|e| format!("{} {}, {}", e, offset, EXPECTED_FORMATS))?;
+    let out_of_range = || format!("The offset {} is out of range", offset);
+    let months = match period.unit {
+        TimeUnit::Years => Some(period.quantity.checked_mul(12)),
+        TimeUnit::Months => Some(Some(period.quantity)),
+        _ => None,
+    };
+    if let Some(months) = months {
+        let months = months
+            .and_then(|months| u32::try_from(months).ok())
+            .map(Months::new)
+            .ok_or_else(out_of_range)?;
+        let datetime = if negative {
+            datetime.checked_sub_months(months)
+        } else {
+            datetime.checked_add_months(months)
+        };
+        return datetime.ok_or_else(|| out_of_range().into());
+    }
+    let duration = period
+        .as_nanoseconds()
+        .map(Duration::nanoseconds)
+        .ok_or_else(out_of_range)?;
+    let datetime = if negative {
+        datetime.checked_sub_signed(duration)
+    } else {
+        datetime.checked_add_signed(duration)
+    };
+    datetime.ok_or_else(|| out_of_range().into())
+}
+
+/// Get the last timestamp in the database, over all tables with a `timestamp` column.
+fn get_latest_timestamp(conn: &Connection) -> Result<DateTime<Utc>> {
+    let mut stmt = conn.prepare(
+        "select m.name from sqlite_master as m, pragma_table_info(m.name) as c \
+         where m.type = 'table' and c.name = 'timestamp'",
+    )?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    let mut latest: Option<i64> = None;
+    for table in tables {
+        let timestamp: Option<i64> = conn.query_row(
+            &format!("select max(timestamp) from \"{}\"", table),
+            [],
+            |row| row.get(0),
+        )?;
+        latest = latest.max(timestamp);
+    }
+    let latest = latest.ok_or("latest can not be used with an empty database")?;
+    Ok(Utc.timestamp_nanos(latest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_expression() {
+        let now = Utc::now();
+        let time = |s: &str, bound: Bound| parse_time_expression(None, s, bound, now).unwrap();
+        let date = |s: &str| util::parse_datetime_floor(s).unwrap();
+
+        assert_eq!(time("2024-W12", Bound::Floor), date("2024-03-18"));
+        assert_eq!(time("2024-W12", Bound::Ceil), date("2024-03-24 23:59:59"));
+        assert_eq!(time("2024-W01-7", Bound::Floor), date("2024-01-07"));
+        assert_eq!(time("2024-03-01 -1D", Bound::Floor), date("2024-02-29"));
+        assert_eq!(
+            time("2024-03-31+1M-2h", Bound::Floor),
+            date("2024-04-29 22")
+        );
+        assert_eq!(time("2024 + 1y", Bound::Ceil), date("2025-12-31 23:59:59"));
+
+        let monday = time("last monday", Bound::Floor);
+        assert_eq!(monday.weekday(), Weekday::Mon);
+        assert!(now - monday <= Duration::weeks(1));
+        assert!(now - monday > Duration::days(1));
+        assert_eq!(time("-7d", Bound::Floor), now - Duration::weeks(1));
+        assert_eq!(time("now - 1w", Bound::Ceil), now - Duration::weeks(1));
+
+        let invalid = ["", "2024-W54", "last fortnight", "-7x", "2024-13", "latest"];
+        for expression in invalid {
+            assert!(parse_time_expression(None, expression, Bound::Floor, now).is_err());
+        }
+    }
+}